@@ -8,7 +8,10 @@ import {
 } from "@mui/material";
 import { styled } from "@mui/material/styles";
 import GameStateDisplay from "./GameStateDisplay";
-import { ALBUM_LOGOS, ALBUM_ORDER, generateSongHref, getAlbumChipWidth } from "../utils/Utils";
+import {
+  ALBUM_LOGOS, ALBUM_ORDER, gameSecretConfig, generateSongHref, getAlbumChipWidth,
+  saveGameSecret, songListFromAlbums
+} from "../utils/Utils";
 
 
 const Item = styled(Paper)(({ theme }) => ({
@@ -42,6 +45,7 @@ export default function Game() {
     axios.post("/game/start", includedSongList).then((response) => {
       setGameState(response.data);
       Cookies.set("tsgg-game-id", response.data.id);
+      saveGameSecret(response.data.secret);
       // eslint-disable-next-line no-console
       console.log("starting game with id: ", response.data.id);
       setHasStarted(true);
@@ -50,8 +54,8 @@ export default function Game() {
 
   React.useEffect(() => {
     axios.get("/songs").then((response) => {
-      setOrderedSongList(JSON.parse(JSON.stringify(response.data)));
-      let songs = response.data;
+      let songs = songListFromAlbums(response.data);
+      setOrderedSongList(JSON.parse(JSON.stringify(songs)));
       for (let album of Object.keys(songs)) {
         const names = [...songs[album]];
         songs[album] = {};
@@ -67,8 +71,8 @@ export default function Game() {
       // eslint-disable-next-line no-console
       console.log(`Loading game from cookies... (the id is: ${maybeGameId})`);
       axios.get("/songs").then((response) => {
-        const songs = response.data;
-        axios.get(`/game/next?id=${maybeGameId}`).then((response) => {
+        const songs = songListFromAlbums(response.data);
+        axios.post("/game/next", {id: maybeGameId}, gameSecretConfig()).then((response) => {
           if (!response.data.id) {
             // return early because the game id in cookies is invalid.
             // eslint-disable-next-line no-console
@@ -93,6 +97,10 @@ export default function Game() {
           setSongList(newSongList);
 
           setHasStarted(true);
+        }).catch(() => {
+          // the game from cookies no longer exists (the server responds with 404).
+          // eslint-disable-next-line no-console
+          console.log("invalid id from cookies. Aborting.");
         });
       });
 
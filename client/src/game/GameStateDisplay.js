@@ -3,7 +3,9 @@ import axios from "axios";
 import Cookies from "js-cookie";
 import { Box, Button, ButtonBase, Divider, Link, TextField, Typography } from "@mui/material";
 import ResultDisplay from "./ResultDisplay";
-import { ALBUM_LOGOS, generateSongHref, normalizeQuotes } from "../utils/Utils";
+import {
+  ALBUM_LOGOS, gameSecretConfig, generateSongHref, normalizeQuotes
+} from "../utils/Utils";
 import ThumbDownIcon from "@mui/icons-material/ThumbDown";
 import ThumbUpIcon from "@mui/icons-material/ThumbUp";
 
@@ -62,7 +64,7 @@ export default function GameStateDisplay({gameState, setGameState, setHasStarted
       return;
     }
 
-    axios.get(`/game/submit-guess?guess=${guess}&id=${id}`).then((response) => {
+    axios.post("/game/submit-guess", {id, guess}, gameSecretConfig()).then((response) => {
       const {game_state, guess_res} = response.data;
       setGameState(game_state);
       setGuessResult(guess_res);
@@ -75,7 +77,7 @@ export default function GameStateDisplay({gameState, setGameState, setHasStarted
   const sendUpvote = () => {
     const {song, shown_line} = current_question;
     const {album, name} = song;
-    axios.get(`/feedback/upvote_line?album=${album}&song_name=${name}&line=${shown_line}`)
+    axios.post("/feedback/upvote_line", {album, song_name: name, line: shown_line})
       .then(() => {
         setHasSentFeedback(true);
       });
@@ -83,14 +85,14 @@ export default function GameStateDisplay({gameState, setGameState, setHasStarted
   const sendDownvote = () => {
     const {song, shown_line} = current_question;
     const {album, name} = song;
-    axios.get(`/feedback/downvote_line?album=${album}&song_name=${name}&line=${shown_line}`)
+    axios.post("/feedback/downvote_line", {album, song_name: name, line: shown_line})
       .then(() => {
         setHasSentFeedback(true);
       });
   };
 
   const goToNextQuestion = () => {
-    axios.get(`/game/next?id=${id}`).then((response) => {
+    axios.post("/game/next", {id}, gameSecretConfig()).then((response) => {
       setGameState(response.data);
       setGuessResult({});
       setHasSentFeedback(false);
@@ -99,7 +101,7 @@ export default function GameStateDisplay({gameState, setGameState, setHasStarted
 
   const handleMultipleChoiceClick = () => {
     if (!completed_question) {
-      axios.get(`/game/reduce-multiple-choice?id=${id}`).then((response) => {
+      axios.post("/game/reduce-multiple-choice", {id}, gameSecretConfig()).then((response) => {
         setGameState(response.data);
       });
     }
@@ -121,8 +123,8 @@ export default function GameStateDisplay({gameState, setGameState, setHasStarted
 
   const claimGame = name => {
     setCurrentName("");
-    axios.get(`/game/claim?id=${id}&name=${name}`).then((response) => {
-      if (response.status === 200) {
+    axios.post("/game/claim", {id, name}, gameSecretConfig()).then((response) => {
+      if (response.status === 204) {
         setGuessResult({});
         setHasStarted(false);
       }
@@ -260,7 +262,8 @@ function LifelineSection({gameState, setGameState, setGuessResult}) {
   const {show_title_album, show_prev_lines, skip} = gameState.lifeline_inv;
 
   const consumeLifeline = lifelineToUse => {
-    axios.get(`/game/use-lifeline?id=${id}&lifeline=${lifelineToUse}`).then((response) => {
+    const request = {id, lifeline: lifelineToUse};
+    axios.post("/game/use-lifeline", request, gameSecretConfig()).then((response) => {
       const newGameState = response.data;
       setGameState(newGameState);
 
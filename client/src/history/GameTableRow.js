@@ -46,6 +46,9 @@ export default function GameTableRow({game, index}) {
       <TableCell align="right">
         <Box pl={1}>
           {score}
+          {game.end_reason === "abandoned" &&
+            <span style={{color:"darkgray"}}>{" (abandoned)"}</span>
+          }
         </Box>
       </TableCell>
       <TableCell align="right">
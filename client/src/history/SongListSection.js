@@ -4,7 +4,9 @@ import {
   Checkbox, Button, Snackbar, Alert
 } from "@mui/material";
 import axios from "axios";
-import { ALBUM_ORDER, ALBUM_LOGOS, getAlbumChipWidth, generateSongHref } from "../utils/Utils";
+import {
+  ALBUM_ORDER, ALBUM_LOGOS, getAlbumChipWidth, generateSongHref, songListFromAlbums
+} from "../utils/Utils";
 import { Item } from "../song/SongPage";
 
 
@@ -15,7 +17,7 @@ export default function SongListSection({selectedSongs, songlistId}) {
 
   React.useEffect(() => {
     axios.get(`/songs?id=${songlistId}`).then((response) => {
-      setFullSongList(response.data);
+      setFullSongList(songListFromAlbums(response.data));
     });
   }, []);
 
@@ -8,7 +8,7 @@ import { styled } from "@mui/material/styles";
 import {
   ALBUM_LOGOS, ALBUM_ORDER,
   generateLineHistoryHref,
-  generateSongHref, getAlbumChipWidth, isTouchDevice, normalizeQuotes
+  generateSongHref, getAlbumChipWidth, isTouchDevice, normalizeQuotes, songListFromAlbums
 } from "../utils/Utils";
 import { LinePopoverContent } from "../history/GuessHistory";
 import { NotFoundImg } from "../not-found/NotFound";
@@ -36,7 +36,7 @@ export default function SongPage() {
   React.useEffect(() => {
     setIsListLoading(true);
     axios.get("/songs").then((response) => {
-      setSongList(response.data);
+      setSongList(songListFromAlbums(response.data));
       setIsListLoading(false);
     });
   }, []);
@@ -1,6 +1,7 @@
 import { useSearchParams } from "react-router-dom";
 import { useTheme } from "@mui/material/styles";
 import useMediaQuery from "@mui/material/useMediaQuery";
+import Cookies from "js-cookie";
 
 
 export const ALBUM_ORDER = [
@@ -24,6 +25,16 @@ export const ALBUM_LOGOS = {
   "The Life of a Showgirl": "https://i.scdn.co/image/ab67616d0000b273d7812467811a7da6e6a44902",
 };
 
+// The `/songs` endpoint lists albums in release order, each with its songs in track order.
+// Returns an object mapping each album name to the names of its songs, in the same order.
+export const songListFromAlbums = albums => {
+  const songList = {};
+  for (const album of albums) {
+    songList[album.name] = album.songs.map(song => song.name);
+  }
+  return songList;
+};
+
 export const normalizeQuotes = string => {
   const result = string
     .replaceAll("“", "\"")
@@ -103,6 +114,20 @@ export const generateLineBackendAPIHistoryHref = (album, song, prompt) => {
   return `/history/line?album=${album_esc}&song=${song_esc}&prompt=${prompt_esc}`;
 };
 
+// The secret of a game is only returned when the game starts, and every request which
+// changes the game must send it in the X-Game-Secret header. It is kept in a cookie next
+// to the id of the game, so that the game can be resumed after a reload.
+const GAME_SECRET_COOKIE = "tsgg-game-secret";
+
+export const saveGameSecret = secret => {
+  Cookies.set(GAME_SECRET_COOKIE, secret);
+};
+
+export const gameSecretConfig = () => {
+  const secret = Cookies.get(GAME_SECRET_COOKIE);
+  return secret ? { headers: { "X-Game-Secret": secret } } : {};
+};
+
 export const unescapeQuestionMarks = s => {
   return s.replaceAll("%3F", "?").replaceAll("%26", "&");
 };
@@ -0,0 +1,56 @@
+-- The original schema of the database, before songlists were given integer ids.
+
+CREATE TABLE songlists (
+    sha1sum CHAR(40) NOT NULL PRIMARY KEY,
+    content JSON NOT NULL
+);
+
+CREATE TABLE games (
+    uuid VARCHAR(36) NOT NULL PRIMARY KEY,
+    start_time DATETIME NOT NULL,
+    songlist_sha CHAR(40) NOT NULL,
+    selected_songs JSON NOT NULL,
+    has_terminated BOOLEAN NOT NULL DEFAULT FALSE,
+    terminal_score INT NULL,
+    player_name VARCHAR(255) NULL,
+    CONSTRAINT games_ibfk_1 FOREIGN KEY (songlist_sha) REFERENCES songlists(sha1sum)
+);
+
+-- The column order of this table matters, since guesses are inserted with
+-- `INSERT INTO guesses VALUES (...)`.
+CREATE TABLE guesses (
+    game_uuid VARCHAR(36) NOT NULL,
+    order_num INT NOT NULL,
+    album VARCHAR(255) NOT NULL,
+    song_name VARCHAR(255) NOT NULL,
+    prompt TEXT NOT NULL,
+    correct_answer TEXT NOT NULL,
+    result VARCHAR(16) NOT NULL,
+    user_guess TEXT NOT NULL,
+    points_earned INT NOT NULL,
+    lifeline_earned VARCHAR(32) NULL,
+    lifelines_used JSON NOT NULL,
+    options JSON NOT NULL,
+    submit_time DATETIME NOT NULL,
+    PRIMARY KEY (game_uuid, order_num),
+    FOREIGN KEY (game_uuid) REFERENCES games(uuid)
+);
+
+-- Each line has at most one row, which is created by `INSERT IGNORE` before the first vote.
+CREATE TABLE votes (
+    album VARCHAR(255) NOT NULL,
+    song_name VARCHAR(255) NOT NULL,
+    lyric VARCHAR(255) NOT NULL,
+    num_upvotes INT NOT NULL DEFAULT 0,
+    num_downvotes INT NOT NULL DEFAULT 0,
+    PRIMARY KEY (album, song_name, lyric)
+);
+
+CREATE TABLE feedback (
+    time DATETIME NOT NULL,
+    album VARCHAR(255) NOT NULL,
+    song_name VARCHAR(255) NOT NULL,
+    lyric TEXT NOT NULL,
+    message TEXT NOT NULL,
+    contact TEXT NOT NULL
+);
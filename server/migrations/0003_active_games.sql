@@ -0,0 +1,7 @@
+-- Saved states of in-progress games, so that games survive a server restart.
+
+CREATE TABLE active_games (
+    uuid VARCHAR(36) NOT NULL PRIMARY KEY,
+    state JSON NOT NULL,
+    last_updated DATETIME NOT NULL
+);
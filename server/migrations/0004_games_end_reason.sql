@@ -0,0 +1,4 @@
+-- Why a game ended, e.g. "incorrect" or "abandoned".
+-- NULL for games that have not ended, or that ended before the reason was recorded.
+
+ALTER TABLE games ADD end_reason VARCHAR(32) NULL;
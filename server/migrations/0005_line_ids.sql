@@ -0,0 +1,13 @@
+-- Guesses, votes and feedback were matched to lines by album, song name and text, using `LIKE`.
+-- This migration gives them the stable id of their line (see `song::line_id`), which they are matched by instead.
+--
+-- The ids depend on the album directories of the lyrics, so the existing rows are backfilled by the server
+-- at startup, after migrating. Until then, their `line_id` is NULL.
+
+ALTER TABLE guesses ADD line_id VARCHAR(255) NULL AFTER song_name;
+CREATE INDEX guesses_line_id ON guesses (line_id);
+
+ALTER TABLE votes ADD line_id VARCHAR(255) NULL AFTER lyric;
+CREATE INDEX votes_line_id ON votes (line_id);
+
+ALTER TABLE feedback ADD line_id VARCHAR(255) NULL AFTER lyric;
@@ -0,0 +1,18 @@
+-- The text of the lines of every song, as of the last time the lyrics were loaded.
+-- When the lyrics of a song change, the new lines are diffed against these to find the lines which were corrected.
+CREATE TABLE song_revisions (
+    song_id VARCHAR(255) NOT NULL PRIMARY KEY,
+    line_texts JSON NOT NULL,
+    last_updated DATETIME NOT NULL
+);
+
+-- Each row maps a line which was corrected to the line which replaced it,
+-- so that the history of the new line includes the guesses and votes of the old one.
+CREATE TABLE line_revisions (
+    old_line_id VARCHAR(255) NOT NULL PRIMARY KEY,
+    new_line_id VARCHAR(255) NOT NULL,
+    old_text TEXT NOT NULL,
+    new_text TEXT NOT NULL,
+    time DATETIME NOT NULL,
+    INDEX line_revisions_new_line_id (new_line_id)
+);
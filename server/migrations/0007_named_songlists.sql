@@ -0,0 +1,6 @@
+-- Songlists were only created implicitly, from the songs available when a game started, and had no name.
+-- Curated songlists, such as "Eras Tour setlist", are created by the admin and have a unique name.
+-- Games only ever refer to unnamed songlists, since curated songlists can be edited.
+
+ALTER TABLE songlists ADD name VARCHAR(255) NULL, ADD description TEXT NULL;
+CREATE UNIQUE INDEX songlists_name ON songlists (name);
@@ -0,0 +1,6 @@
+-- Games stored their selected songs as a JSON map from album names to one boolean per song of the album.
+-- They now store an encoded selection (see `selection.rs`) instead. The selections of existing games are converted
+-- by the server at startup, after migrating. `selected_songs` is kept, but no longer written.
+
+ALTER TABLE games ADD selection TEXT NULL AFTER songlist_id;
+ALTER TABLE games MODIFY selected_songs JSON NULL;
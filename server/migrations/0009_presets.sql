@@ -0,0 +1,9 @@
+-- Presets save a selection of songs under a short code, which players share to start games with the same songs.
+-- Like games, a preset refers to an unnamed songlist of every song available when it was created.
+CREATE TABLE presets (
+    code VARCHAR(16) NOT NULL PRIMARY KEY,
+    songlist_id INT NOT NULL,
+    selection TEXT NOT NULL,
+    created_time DATETIME NOT NULL,
+    CONSTRAINT FK_presets_songlist_id FOREIGN KEY (songlist_id) REFERENCES songlists(id)
+);
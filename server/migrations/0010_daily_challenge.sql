@@ -0,0 +1,4 @@
+-- Games played in the daily challenge record its date. Their player is named when the game starts,
+-- and may only play the daily challenge once per day.
+ALTER TABLE games ADD daily_date DATE NULL AFTER selection;
+CREATE UNIQUE INDEX games_daily_date_player_name ON games (daily_date, player_name);
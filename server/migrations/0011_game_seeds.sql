@@ -0,0 +1,3 @@
+-- The seed of the random draws of every game, so that games can be replayed (see `game_rng.rs`).
+-- Games started before games had seeds have none.
+ALTER TABLE games ADD seed BIGINT UNSIGNED NULL AFTER selection;
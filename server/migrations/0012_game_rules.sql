@@ -0,0 +1,6 @@
+-- The rules that every game is played with (see `rules.rs`). Games started before rules could be chosen have none,
+-- and were played with the classic rules.
+ALTER TABLE games ADD rules JSON NULL AFTER seed;
+
+-- The name of the preset of rules that games started from a preset are played with.
+ALTER TABLE presets ADD rules VARCHAR(32) NOT NULL DEFAULT 'classic' AFTER selection;
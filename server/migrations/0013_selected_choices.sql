@@ -0,0 +1,3 @@
+-- The index in `options` of the choice picked for a multiple-choice question. Guesses which were submitted as text
+-- before choices could be picked by index have none.
+ALTER TABLE guesses ADD selected_choice INT NULL AFTER options;
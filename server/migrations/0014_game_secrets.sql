@@ -0,0 +1,3 @@
+-- The hash of the secret which is returned when a game starts, and which every request that changes the game must
+-- send (see `game_secret.rs`). Games started before games had secrets have none.
+ALTER TABLE games ADD secret_hash CHAR(40) NULL AFTER rules;
@@ -0,0 +1,21 @@
+-- Player accounts, which players log in to with a password (see `accounts.rs`). Only a hash of the password is
+-- stored, and only hashes of the tokens of sessions.
+CREATE TABLE accounts (
+    id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    name VARCHAR(64) NOT NULL,
+    password_hash VARCHAR(255) NOT NULL,
+    created_time DATETIME NOT NULL,
+    CONSTRAINT accounts_name UNIQUE (name)
+);
+
+CREATE TABLE sessions (
+    token_hash CHAR(40) NOT NULL PRIMARY KEY,
+    account_id INT NOT NULL,
+    created_time DATETIME NOT NULL,
+    expires_time DATETIME NOT NULL,
+    CONSTRAINT FK_sessions_account_id FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+-- The account of the player of a game, if they were logged in when it started or linked it to their account since.
+ALTER TABLE games ADD account_id INT NULL AFTER player_name;
+ALTER TABLE games ADD CONSTRAINT FK_games_account_id FOREIGN KEY (account_id) REFERENCES accounts(id);
@@ -0,0 +1,367 @@
+//! Player accounts, which give players a persistent identity across games.
+//!
+//! A player registers a name and a password, and logs in to get a session token, which is sent in an
+//! `Authorization: Bearer <token>` header. Games started with a session are linked to the account and named after
+//! it, and past games can be linked with their secret (see [`game_secret`](crate::game_secret)). The history and
+//! the daily leaderboard can then tell the games of logged-in players apart from games with a typed-in name, which
+//! anyone can use.
+//!
+//! Only hashes are stored: passwords are hashed with Argon2, and session tokens, which are random, with SHA-1.
+//! Sessions expire after [`SESSION_DAYS`] days.
+
+use crate::error::{ApiError, ApiResult};
+use crate::game_secret::{generate_secret, hash_secret, GameSecret};
+use crate::history::{self, format_date, Game};
+use crate::storage::{Account, DynStorage, GamesQuery};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::rngs::OsRng;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::status::NoContent;
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::{
+    Object, SecurityRequirement, SecurityScheme, SecuritySchemeData,
+};
+use rocket_okapi::openapi;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::types::time::{OffsetDateTime, PrimitiveDateTime};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// How long a session lasts, in days.
+pub const SESSION_DAYS: u64 = 30;
+
+/// The longest name of an account.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// The shortest password of an account.
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Hash a password with a random salt, as a PHC string.
+fn hash_password(password: &str) -> ApiResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| ApiError::Internal(format!("failed to hash a password: {}", e)))
+}
+
+/// Whether `password` is the password whose hash is `password_hash`.
+fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// The hash that the password of a login is checked against when there is no account with the name, so that a
+/// login takes about as long whether or not the account exists.
+fn dummy_password_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| {
+        hash_password("not the password of any account")
+            .expect("hashing with the default parameters doesn't fail")
+    })
+}
+
+/// Check that `name` can be the name of an account, and return it without surrounding whitespace.
+fn validate_name(name: &str) -> ApiResult<&str> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "the name of an account must have between 1 and {} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+    Ok(name)
+}
+
+/// Fail if `name` is the name of an account, which only its player may play as.
+pub async fn check_name_is_free(storage: &DynStorage, name: &str) -> ApiResult<()> {
+    if storage.get_account(name.trim()).await?.is_some() {
+        return Err(ApiError::Conflict(format!(
+            "`{}` is the name of an account, log in to play as `{}`",
+            name.trim(),
+            name.trim()
+        )));
+    }
+    Ok(())
+}
+
+/// A request guard which succeeds only if the request carries the token of an unexpired session.
+/// Endpoints which may be used without an account take an `Option<Player>`.
+pub struct Player {
+    pub account: Account,
+    token_hash: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Player {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let unauthorized = |message: &str| {
+            Outcome::Failure((
+                Status::Unauthorized,
+                ApiError::Unauthorized(message.to_owned()),
+            ))
+        };
+        let Some(token) = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+        else {
+            return unauthorized("a session token is required, log in to get one");
+        };
+        let Some(storage) = request.rocket().state::<DynStorage>() else {
+            return Outcome::Failure((
+                Status::InternalServerError,
+                ApiError::Internal("the storage is not managed".to_owned()),
+            ));
+        };
+        let token_hash = hash_secret(token);
+        match storage.get_session_account(&token_hash).await {
+            Ok(Some(account)) => Outcome::Success(Player {
+                account,
+                token_hash,
+            }),
+            Ok(None) => unauthorized("the session is unknown or has expired, log in again"),
+            Err(e) => {
+                let error = ApiError::from(e);
+                Outcome::Failure((error.status(), error))
+            }
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for Player {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        let scheme = SecurityScheme {
+            description: Some("The token returned by `POST /accounts/login`.".to_owned()),
+            data: SecuritySchemeData::Http {
+                scheme: "bearer".to_owned(),
+                bearer_format: None,
+            },
+            extensions: Object::default(),
+        };
+        let mut requirement = SecurityRequirement::new();
+        requirement.insert("SessionToken".to_owned(), Vec::new());
+        Ok(RequestHeaderInput::Security(
+            "SessionToken".to_owned(),
+            scheme,
+            requirement,
+        ))
+    }
+}
+
+/// The body of a request to register or log in.
+#[derive(Deserialize, JsonSchema)]
+pub struct Credentials {
+    pub name: String,
+    pub password: String,
+}
+
+/// A new session.
+#[derive(Serialize, JsonSchema)]
+pub struct Session {
+    /// The name of the account, as it was registered.
+    name: String,
+    /// The token to send in an `Authorization: Bearer <token>` header.
+    token: String,
+    /// The date after which the token is no longer accepted, e.g. `"2024-03-14"`.
+    expires: String,
+}
+
+/// Start a session of `account`.
+async fn start_session(storage: &DynStorage, account: Account) -> ApiResult<Json<Session>> {
+    let token = generate_secret();
+    let expires = OffsetDateTime::now_utc() + Duration::from_secs(SESSION_DAYS * 24 * 60 * 60);
+    let expires = PrimitiveDateTime::new(expires.date(), expires.time());
+    storage
+        .insert_session(&hash_secret(&token), account.id, expires)
+        .await?;
+    Ok(Json(Session {
+        name: account.name,
+        token,
+        expires: format_date(expires.date()),
+    }))
+}
+
+/// API endpoint to register an account, and log in to it.
+/// Names are unique, ignoring case, and passwords must have at least 8 characters.
+#[openapi(tag = "Accounts")]
+#[post(
+    "/accounts/register",
+    format = "application/json",
+    data = "<credentials>"
+)]
+pub async fn register(
+    credentials: Json<Credentials>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<Session>> {
+    let name = validate_name(&credentials.name)?;
+    if credentials.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "the password must have at least {} characters",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+
+    let password_hash = hash_password(&credentials.password)?;
+    let Some(id) = storage.insert_account(name, &password_hash).await? else {
+        return Err(ApiError::Conflict(format!(
+            "there is already an account named `{}`",
+            name
+        )));
+    };
+    let account = Account {
+        id,
+        name: name.to_owned(),
+        password_hash,
+    };
+    start_session(storage, account).await
+}
+
+/// API endpoint to log in to an account.
+#[openapi(tag = "Accounts")]
+#[post("/accounts/login", format = "application/json", data = "<credentials>")]
+pub async fn login(
+    credentials: Json<Credentials>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<Session>> {
+    let account = storage.get_account(credentials.name.trim()).await?;
+    let password_hash = match &account {
+        Some(account) => account.password_hash.as_str(),
+        None => dummy_password_hash(),
+    };
+    let is_password_right = verify_password(&credentials.password, password_hash);
+    match account {
+        Some(account) if is_password_right => start_session(storage, account).await,
+        _ => Err(ApiError::Unauthorized(
+            "the name or the password is wrong".to_owned(),
+        )),
+    }
+}
+
+/// API endpoint to end the session whose token the request carries.
+#[openapi(tag = "Accounts")]
+#[post("/accounts/logout")]
+pub async fn logout(player: Player, storage: &State<DynStorage>) -> ApiResult<NoContent> {
+    storage.delete_session(&player.token_hash).await?;
+    Ok(NoContent)
+}
+
+/// The account of the player.
+#[derive(Serialize, JsonSchema)]
+pub struct AccountPublic {
+    name: String,
+}
+
+/// API endpoint to get the account that the player is logged in to.
+#[openapi(tag = "Accounts")]
+#[get("/accounts/me")]
+pub async fn get_account(player: Player) -> Json<AccountPublic> {
+    Json(AccountPublic {
+        name: player.account.name,
+    })
+}
+
+/// API endpoint to list the finished games of the player's account, most recent first.
+/// Results are paginated.
+#[openapi(tag = "Accounts")]
+#[get("/accounts/me/games?<limit>&<page_num>")]
+pub async fn get_account_games(
+    player: Player,
+    storage: &State<DynStorage>,
+    limit: Option<usize>,
+    page_num: Option<usize>,
+) -> ApiResult<Json<Vec<Game>>> {
+    let limit = limit.unwrap_or(20);
+    let page_num = page_num.map_or(1, |num| if num > 0 { num } else { 1 });
+    let query = GamesQuery {
+        sort_by_score: false,
+        search: String::new(),
+        include_nameless: true,
+        daily_date: None,
+        account_id: Some(player.account.id),
+        verified_only: true,
+        limit,
+        offset: (page_num - 1) * limit,
+    };
+    Ok(Json(history::list_games(storage, &query).await?))
+}
+
+/// The body of a request to link a game to an account.
+#[derive(Deserialize, JsonSchema)]
+pub struct LinkRequest {
+    pub id: String,
+}
+
+/// API endpoint to link a game played without an account to the player's account, which renames it after the
+/// account. The request must carry the secret of the game, so games started before games had secrets can't be
+/// linked.
+#[openapi(tag = "Accounts")]
+#[post("/accounts/games", format = "application/json", data = "<request>")]
+pub async fn link_game(
+    player: Player,
+    request: Json<LinkRequest>,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> ApiResult<NoContent> {
+    let id = &request.id;
+    let Some(game) = storage.get_game(id).await? else {
+        return Err(ApiError::NotFound(format!("no game with id `{}`", id)));
+    };
+    let Some(secret_hash) = game.secret_hash.as_deref() else {
+        return Err(ApiError::Conflict(format!(
+            "game `{}` was started before games had secrets, and can't be linked to an account",
+            id
+        )));
+    };
+    secret.verify(id, Some(secret_hash))?;
+    match game.account_id {
+        Some(account_id) if account_id == player.account.id => return Ok(NoContent),
+        Some(_) => {
+            return Err(ApiError::Conflict(format!(
+                "game `{}` is linked to another account",
+                id
+            )))
+        }
+        None => {}
+    }
+    if !storage.link_game(id, &player.account).await? {
+        return Err(ApiError::Conflict(format!(
+            "`{}` has already played the daily challenge of {}",
+            player.account.name,
+            game.daily_date.map(format_date).unwrap_or_default()
+        )));
+    }
+    Ok(NoContent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_are_verified_against_their_hash() {
+        let hash = hash_password("correct horse").unwrap();
+        assert_ne!(hash, hash_password("correct horse").unwrap());
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("battery staple", &hash));
+        assert!(!verify_password("correct horse", "not a hash"));
+        assert!(!verify_password("correct horse", dummy_password_hash()));
+    }
+}
@@ -0,0 +1,118 @@
+//! Endpoints for the maintainer of the server.
+//!
+//! Every admin endpoint requires the admin token, which is set with the `ADMIN_TOKEN` environment variable
+//! and sent in an `Authorization: Bearer <token>` header. If `ADMIN_TOKEN` is not set, the admin endpoints
+//! reject every request.
+
+use crate::error::{ApiError, ApiResult};
+use crate::loader_v2::SongLibrary;
+use crate::revisions;
+use crate::storage::DynStorage;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::{
+    Object, SecurityRequirement, SecurityScheme, SecuritySchemeData,
+};
+use rocket_okapi::openapi;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The admin token, managed by rocket.
+pub struct AdminConfig {
+    pub token: Option<String>,
+}
+
+impl AdminConfig {
+    pub fn from_env() -> Self {
+        AdminConfig {
+            token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+        }
+    }
+}
+
+/// A request guard which succeeds only if the request carries the admin token.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = request
+            .rocket()
+            .state::<AdminConfig>()
+            .and_then(|config| config.token.as_deref());
+        let given = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "));
+        match (token, given) {
+            (Some(token), Some(given)) if token == given => Outcome::Success(Admin),
+            _ => Outcome::Failure((
+                Status::Unauthorized,
+                ApiError::Unauthorized("a valid admin token is required".to_owned()),
+            )),
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for Admin {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        let scheme = SecurityScheme {
+            description: Some("The admin token, set with the `ADMIN_TOKEN` variable.".to_owned()),
+            data: SecuritySchemeData::Http {
+                scheme: "bearer".to_owned(),
+                bearer_format: None,
+            },
+            extensions: Object::default(),
+        };
+        let mut requirement = SecurityRequirement::new();
+        requirement.insert("AdminToken".to_owned(), Vec::new());
+        Ok(RequestHeaderInput::Security(
+            "AdminToken".to_owned(),
+            scheme,
+            requirement,
+        ))
+    }
+}
+
+/// A summary of the songs after they have been reloaded.
+#[derive(Serialize, JsonSchema)]
+pub struct ReloadResult {
+    num_songs: usize,
+    num_albums: usize,
+    /// The number of lines which were changed since the lyrics were last loaded (see [`revisions`]).
+    num_revised_lines: usize,
+}
+
+/// API endpoint to reload the lyrics without restarting the server.
+/// In-progress games are not affected, and will draw their next questions from the new songs.
+///
+/// If any lyrics file is invalid, the current songs are kept, and the response lists every problem found.
+#[openapi(tag = "Admin")]
+#[post("/admin/reload-lyrics")]
+pub async fn reload_lyrics(
+    _admin: Admin,
+    songs: &State<SongLibrary>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<ReloadResult>> {
+    let (albums, songs) = songs.reload().map_err(|errors| {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        ApiError::BadRequest(format!("invalid lyrics:\n{}", errors.join("\n")))
+    })?;
+    let num_revised_lines = revisions::record_revisions(storage, &songs).await?;
+
+    Ok(Json(ReloadResult {
+        num_songs: songs.len(),
+        num_albums: albums.len(),
+        num_revised_lines,
+    }))
+}
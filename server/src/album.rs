@@ -0,0 +1,141 @@
+//! Albums, and the listing of songs grouped by album.
+//!
+//! Each album is a directory of `lyrics_data`, named like `04_Red`. The number before the first `_` orders albums
+//! without a release date, and the rest of the name gives the id of the album. An album directory may contain an
+//! `album.toml` file with the release date of the album, by which albums are ordered, and the key of its cover art
+//! if the key differs from the id:
+//!
+//! ```toml
+//! release_date = 2012-10-22
+//! cover_art = "red"
+//! ```
+
+use crate::error::ApiResult;
+use crate::loader_v2::SongLibrary;
+use crate::song::Song;
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Album {
+    /// A stable identifier, e.g. `red`, taken from the name of the album's directory.
+    pub id: String,
+    /// The name of the album, as it appears in its song files.
+    pub name: String,
+    /// The date the album was released, as `YYYY-MM-DD`.
+    pub release_date: Option<String>,
+    /// The position of the album in release order, starting from 1.
+    pub ordinal: u32,
+    /// The key of the album's cover art, which the client uses to pick an image.
+    pub cover_art: String,
+}
+
+/// Split the name of an album directory into the ordinal and id of the album,
+/// e.g. `11_THE_TORTURED_POETS_DEPARTMENT` into `11` and `the-tortured-poets-department`.
+pub fn parse_album_dir_name(dir_name: &str) -> (Option<u32>, String) {
+    let (ordinal, name) = match dir_name.split_once('_') {
+        Some((prefix, name)) => match prefix.parse() {
+            Ok(ordinal) => (Some(ordinal), name),
+            Err(_) => (None, dir_name),
+        },
+        None => (None, dir_name),
+    };
+    (ordinal, name.to_lowercase().replace(['_', ' '], "-"))
+}
+
+/// A song in a listing of an album.
+#[derive(Serialize, JsonSchema)]
+pub struct Track {
+    pub name: String,
+    /// The position of the song on the album, if the song is still part of the game.
+    pub track: Option<u32>,
+}
+
+/// An album, with the songs of a listing which belong to it.
+#[derive(Serialize, JsonSchema)]
+pub struct AlbumListing {
+    #[serde(flatten)]
+    pub album: Album,
+    pub songs: Vec<Track>,
+}
+
+/// Group `entries`, which are (album name, song name) pairs, by album. Albums are given in release order, and
+/// songs in track order.
+///
+/// `entries` may come from an old songlist, so they may name songs and albums which are no longer part of the
+/// game. Such songs are listed after the other songs of their album, and such albums after the other albums.
+pub fn list_by_album(
+    albums: &[Album],
+    songs: &[Song],
+    entries: &[(String, String)],
+) -> Vec<AlbumListing> {
+    let mut listings: Vec<AlbumListing> = Vec::new();
+    let mut next_unknown_ordinal = albums.iter().map(|album| album.ordinal).max().unwrap_or(0) + 1;
+    for (album_name, song_name) in entries {
+        let index = match listings.iter().position(|l| &l.album.name == album_name) {
+            Some(index) => index,
+            None => {
+                let album = match albums.iter().find(|album| &album.name == album_name) {
+                    Some(album) => album.clone(),
+                    None => {
+                        let id = parse_album_dir_name(album_name).1;
+                        next_unknown_ordinal += 1;
+                        Album {
+                            cover_art: id.clone(),
+                            id,
+                            name: album_name.clone(),
+                            release_date: None,
+                            ordinal: next_unknown_ordinal - 1,
+                        }
+                    }
+                };
+                listings.push(AlbumListing {
+                    album,
+                    songs: Vec::new(),
+                });
+                listings.len() - 1
+            }
+        };
+        let track = songs
+            .iter()
+            .find(|song| song.album == album_name && song.name == song_name)
+            .map(|song| song.track);
+        listings[index].songs.push(Track {
+            name: song_name.clone(),
+            track,
+        });
+    }
+
+    // sorting is stable, so unknown albums and songs keep the order of `entries`
+    listings.sort_by_key(|listing| listing.album.ordinal);
+    for listing in &mut listings {
+        listing
+            .songs
+            .sort_by_key(|song| song.track.unwrap_or(u32::MAX));
+    }
+    listings
+}
+
+/// API endpoint for getting all albums, in release order.
+#[openapi(tag = "Songs")]
+#[get("/albums")]
+pub fn get_albums(songs: &State<SongLibrary>) -> ApiResult<Json<Vec<Album>>> {
+    Ok(Json(songs.albums().to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn album_dir_names_give_ordinal_and_id() {
+        assert_eq!(
+            parse_album_dir_name("11_THE_TORTURED_POETS_DEPARTMENT"),
+            (Some(11), "the-tortured-poets-department".to_owned())
+        );
+        assert_eq!(parse_album_dir_name("Red"), (None, "red".to_owned()));
+    }
+}
@@ -0,0 +1,230 @@
+//! The daily challenge: a game which every player plays with the same questions on a given day.
+//!
+//! A daily challenge game draws from every song, and its random draws are seeded by the date (in UTC), so every
+//! player is asked the same questions, offered the same choices and earns lifelines in the same way (see
+//! [`game_rng`](crate::game_rng)). Reloading the lyrics changes the questions of the rest of the day. The seed is
+//! an HMAC of the date with a key from the server's config (see [`DailyConfig`]), so that the questions of a day
+//! can't be worked out ahead of time.
+//!
+//! The daily challenge is played with the classic rules. The player is named when the game starts, after their
+//! account if they are logged in (see [`accounts`](crate::accounts)), and each name may only start one daily
+//! challenge game per day. Daily challenge games are listed in the history like any other
+//! game, and the best scores of each day are listed by `GET /daily/leaderboard`.
+
+use crate::accounts::{check_name_is_free, Player};
+use crate::error::{ApiError, ApiResult};
+use crate::game::{start_game, GameSetup, GameState, GameStatePublic};
+use crate::game_secret::generate_secret;
+use crate::history::format_date;
+use crate::loader_v2::SongLibrary;
+use crate::rules::GameRules;
+use crate::storage::{DynStorage, GamesQuery};
+use chrono::{Datelike, NaiveDate};
+use hmac::{Hmac, Mac};
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::Serialize;
+use sha1::Sha1;
+use sqlx::types::time::{Date, OffsetDateTime};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The most games listed on one page of the leaderboard.
+const MAX_LEADERBOARD_LIMIT: usize = 100;
+
+/// The key which the seeds of daily challenges are derived from, managed by rocket.
+pub struct DailyConfig {
+    pub seed_key: String,
+}
+
+impl DailyConfig {
+    /// Reads the key from the `DAILY_SEED_KEY` environment variable. Without it, a random key is used, so the
+    /// questions of the daily challenge change whenever the server restarts.
+    pub fn from_env() -> Self {
+        let seed_key = match std::env::var("DAILY_SEED_KEY") {
+            Ok(key) if !key.is_empty() => key,
+            _ => {
+                println!("Warning, DAILY_SEED_KEY is not set, so the daily challenge changes on every restart");
+                generate_secret()
+            }
+        };
+        DailyConfig { seed_key }
+    }
+}
+
+/// The date of today's daily challenge.
+pub fn today() -> Date {
+    OffsetDateTime::now_utc().date()
+}
+
+/// Parse a date such as `2024-03-14`.
+fn parse_date(date: &str) -> ApiResult<Date> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| Date::from_ordinal_date(date.year(), date.ordinal() as u16).ok())
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "invalid date `{}`, expected e.g. `2024-03-14`",
+                date
+            ))
+        })
+}
+
+/// The seed of the random draws of the daily challenge of `date`.
+pub fn daily_seed(seed_key: &str, date: Date) -> u64 {
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(seed_key.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(format!("daily challenge {}", format_date(date)).as_bytes());
+    let hash = mac.finalize().into_bytes();
+    u64::from_be_bytes(hash[..8].try_into().unwrap())
+}
+
+/// API endpoint to start today's daily challenge, as the player `name`, or as the player's account if the request
+/// carries a session token. The name of an account can only be played as by logging in to it.
+/// Each player may only play the daily challenge once per day.
+#[openapi(tag = "Game")]
+#[post("/daily/start?<name>")]
+pub async fn start_daily_challenge(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    songs: &State<SongLibrary>,
+    name: Option<&str>,
+    player: Option<Player>,
+    config: &State<DailyConfig>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GameStatePublic>> {
+    let (name, account_id) = match &player {
+        Some(player) => (player.account.name.as_str(), Some(player.account.id)),
+        None => {
+            let name = name.unwrap_or_default().trim();
+            if name.is_empty() {
+                return Err(ApiError::BadRequest(
+                    "the daily challenge needs a player name".to_owned(),
+                ));
+            }
+            check_name_is_free(storage, name).await?;
+            (name, None)
+        }
+    };
+    // a player who has already played today's challenge is turned away by `start_game`, which fails to record a
+    // second game with the same date and name
+    let date = today();
+    let setup = GameSetup {
+        seed: Some(daily_seed(&config.seed_key, date)),
+        daily_date: Some(date),
+        player_name: Some(name.to_owned()),
+        account_id,
+        rules: GameRules::classic(),
+    };
+    start_game(game_state, songs, Vec::new(), setup, storage).await
+}
+
+/// A finished game of a daily challenge.
+#[derive(Serialize, JsonSchema)]
+pub struct DailyScore {
+    /// The rank of the game on the leaderboard, starting from 1. Games with the same score share a rank.
+    rank: usize,
+    player_name: String,
+    /// Whether the player was logged in to the account named `player_name`.
+    verified: bool,
+    score: i32,
+    num_guesses: i32,
+    /// The uuid of the game, whose guesses are listed by `GET /history/game?id=<uuid>`.
+    game_uuid: String,
+}
+
+/// API endpoint to list the finished games of the daily challenge of `date` (today if not given, e.g.
+/// `2024-03-14`), best score first. If `verified` is true, only the games of players who were logged in to an
+/// account are listed. Results are paginated, with at most 100 games per page.
+#[openapi(tag = "History")]
+#[get("/daily/leaderboard?<date>&<verified>&<limit>&<page_num>")]
+pub async fn get_daily_leaderboard(
+    storage: &State<DynStorage>,
+    date: Option<&str>,
+    verified: Option<bool>,
+    limit: Option<usize>,
+    page_num: Option<usize>,
+) -> ApiResult<Json<Vec<DailyScore>>> {
+    let date = match date {
+        Some(date) => parse_date(date)?,
+        None => today(),
+    };
+    let limit = limit.unwrap_or(20);
+    if limit > MAX_LEADERBOARD_LIMIT {
+        return Err(ApiError::BadRequest(format!(
+            "at most {} games can be listed per page",
+            MAX_LEADERBOARD_LIMIT
+        )));
+    }
+    let page_num = page_num.map_or(1, |num| if num > 0 { num } else { 1 });
+    let offset = (page_num - 1)
+        .checked_mul(limit)
+        .ok_or_else(|| ApiError::BadRequest(format!("page {} is too far", page_num)))?;
+    let verified_only = verified.unwrap_or(false);
+
+    let games = storage
+        .get_games(&GamesQuery {
+            sort_by_score: true,
+            search: String::new(),
+            include_nameless: false,
+            daily_date: Some(date),
+            account_id: None,
+            verified_only,
+            limit,
+            offset,
+        })
+        .await?;
+
+    let mut scores = Vec::new();
+    let mut previous_score = None;
+    let mut rank = 0;
+    for (index, game) in games.into_iter().enumerate() {
+        let score = game.terminal_score.unwrap_or_default();
+        if previous_score != Some(score) {
+            rank = if index == 0 {
+                // the first game of the page is ranked after every better game, including those of earlier pages
+                storage
+                    .count_better_daily_games(date, score, verified_only)
+                    .await?
+                    + 1
+            } else {
+                offset + index + 1
+            };
+            previous_score = Some(score);
+        }
+        scores.push(DailyScore {
+            rank,
+            player_name: game.player_name.unwrap_or_default(),
+            verified: game.account_id.is_some(),
+            score,
+            num_guesses: game.num_guesses,
+            game_uuid: game.uuid,
+        });
+    }
+    Ok(Json(scores))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_are_parsed_and_seed_the_challenge() {
+        let date = parse_date("2024-03-14").unwrap();
+        assert_eq!(format_date(date), "2024-03-14");
+        assert!(parse_date("14/03/2024").is_err());
+        assert!(parse_date("2024-02-30").is_err());
+
+        assert_eq!(
+            daily_seed("key", date),
+            daily_seed("key", parse_date("2024-03-14").unwrap())
+        );
+        assert_ne!(
+            daily_seed("key", date),
+            daily_seed("key", parse_date("2024-03-15").unwrap())
+        );
+        // without the key, the seed can't be worked out
+        assert_ne!(daily_seed("key", date), daily_seed("other key", date));
+    }
+}
@@ -19,6 +19,7 @@
 //! 12. Length of an SES is D
 //! 13. Stop
 //! 14. Length of an SES is greater than MAX
+//!
 //! The full explanation of the algorithm can be found here:time
 //! <http://www.xmailserver.org/diff2.pdf>
 mod negative_array;
@@ -0,0 +1,158 @@
+//! The error type of the API endpoints.
+//!
+//! Every endpoint returns an [`ApiResult`]. An [`ApiError`] is turned into a response with the matching
+//! HTTP status and a JSON body of the form `{"error": "not_found", "message": "..."}`.
+//! The catchers below give the same shape to errors raised by rocket itself, e.g. when no route matches.
+
+use rocket::http::Status;
+use rocket::response::{self, status, Responder};
+use rocket::serde::json::Json;
+use rocket::Request;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::Responses;
+use rocket_okapi::response::OpenApiResponderInner;
+use rocket_okapi::util::add_schema_response;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// The requested game, song or songlist does not exist. Responds with 404.
+    NotFound(String),
+    /// The request is malformed, e.g. it names an unknown lifeline. Responds with 400.
+    BadRequest(String),
+    /// The request lacks valid credentials, e.g. the admin token. Responds with 401.
+    Unauthorized(String),
+    /// The request conflicts with the current state, e.g. answering a question twice. Responds with 409.
+    Conflict(String),
+    /// The database could not be reached. Responds with 503.
+    Unavailable(String),
+    /// Anything else, such as data in the database which can't be interpreted. Responds with 500.
+    Internal(String),
+}
+
+/// The body of an error response.
+#[derive(Serialize, JsonSchema)]
+pub struct ErrorBody {
+    /// A short machine-readable description of the error, e.g. `"not_found"`.
+    pub error: &'static str,
+    /// A human-readable description of the error.
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Conflict(_) => Status::Conflict,
+            ApiError::Unavailable(_) => Status::ServiceUnavailable,
+            ApiError::Internal(_) => Status::InternalServerError,
+        }
+    }
+
+    fn body(self) -> ErrorBody {
+        match self {
+            ApiError::NotFound(message) => ErrorBody {
+                error: "not_found",
+                message,
+            },
+            ApiError::BadRequest(message) => ErrorBody {
+                error: "bad_request",
+                message,
+            },
+            ApiError::Unauthorized(message) => ErrorBody {
+                error: "unauthorized",
+                message,
+            },
+            ApiError::Conflict(message) => ErrorBody {
+                error: "conflict",
+                message,
+            },
+            ApiError::Unavailable(message) => ErrorBody {
+                error: "unavailable",
+                message,
+            },
+            ApiError::Internal(message) => ErrorBody {
+                error: "internal",
+                message,
+            },
+        }
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(error: sqlx::Error) -> Self {
+        match error {
+            sqlx::Error::RowNotFound => ApiError::NotFound("not found".to_owned()),
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed => {
+                ApiError::Unavailable(format!("the database is unavailable: {}", error))
+            }
+            _ => ApiError::Internal(format!("database error: {}", error)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(error: serde_json::Error) -> Self {
+        ApiError::Internal(format!("failed to convert to JSON: {}", error))
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
+        if status.code >= 500 {
+            println!(
+                "Warning, {} {} failed: {:?}",
+                request.method(),
+                request.uri(),
+                self
+            );
+        }
+        status::Custom(status, Json(self.body())).respond_to(request)
+    }
+}
+
+/// Documents every error status that an endpoint may respond with, each with an [`ErrorBody`].
+impl OpenApiResponderInner for ApiError {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Responses::default();
+        let schema = gen.json_schema::<ErrorBody>();
+        for status in [400, 404, 409, 500, 503] {
+            add_schema_response(&mut responses, status, "application/json", schema.clone())?;
+        }
+        Ok(responses)
+    }
+}
+
+#[catch(400)]
+pub fn bad_request() -> ApiError {
+    ApiError::BadRequest("the request is malformed".to_owned())
+}
+
+#[catch(401)]
+pub fn unauthorized() -> ApiError {
+    ApiError::Unauthorized("valid credentials are required".to_owned())
+}
+
+#[catch(404)]
+pub fn not_found(request: &Request) -> ApiError {
+    ApiError::NotFound(format!("no route matches {}", request.uri()))
+}
+
+#[catch(422)]
+pub fn unprocessable_entity() -> ApiError {
+    ApiError::BadRequest("the request body could not be parsed".to_owned())
+}
+
+#[catch(500)]
+pub fn internal_server_error() -> ApiError {
+    ApiError::Internal("internal server error".to_owned())
+}
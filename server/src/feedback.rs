@@ -1,82 +1,88 @@
+use crate::error::ApiResult;
+use crate::loader_v2::SongLibrary;
 use crate::rss::{RecentVotesCache, VoteEvent};
+use crate::storage::{DynStorage, LineKey};
 use chrono::prelude::*;
+use rocket::response::status::NoContent;
 use rocket::serde::json::Json;
+use rocket::State;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
 use serde::Deserialize;
-use sqlx::{MySql, Pool};
 use std::sync::{Arc, Mutex};
 
-/// API endpoint to upvote a line
-#[get("/feedback/upvote_line?<album>&<song_name>&<line>")]
-pub async fn upvote_line(
-    album: &str,
-    song_name: &str,
-    line: &str,
-    pool: &rocket::State<Pool<MySql>>,
-    vote_cache: &rocket::State<Arc<Mutex<RecentVotesCache>>>,
-) -> String {
-    let _ = sqlx::query("INSERT IGNORE INTO votes (album, song_name, lyric, num_upvotes, num_downvotes) VALUES (?, ?, ?, 0, 0);")
-        .bind(album)
-        .bind(song_name)
-        .bind(line)
-        .fetch_all(pool.inner())
-        .await;
-    let _ = sqlx::query("UPDATE votes SET num_upvotes = num_upvotes + 1 WHERE album LIKE ? AND song_name LIKE ? AND lyric LIKE ?;")
-        .bind(album)
-        .bind(song_name)
-        .bind(line)
-        .fetch_all(pool.inner())
-        .await;
+/// The body of a request to vote on a line.
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct Vote {
+    pub album: String,
+    pub song_name: String,
+    pub line: String,
+}
+
+/// Record a vote on a line, and add it to the recent votes.
+async fn add_vote(
+    vote: Vote,
+    is_upvote: bool,
+    storage: &DynStorage,
+    songs: &SongLibrary,
+    vote_cache: &Mutex<RecentVotesCache>,
+) -> ApiResult<NoContent> {
+    let Vote {
+        album,
+        song_name,
+        line,
+    } = vote;
+    let line_id = songs.line_id(&album, &song_name, &line);
+    let line_key = LineKey {
+        id: &line_id,
+        album: &album,
+        song_name: &song_name,
+        text: &line,
+    };
+    storage.add_vote(&line_key, is_upvote).await?;
     {
         let mut guard = vote_cache.lock().unwrap();
         guard.add(VoteEvent {
             time: Utc::now(),
-            album: album.to_string(),
-            song_name: song_name.to_string(),
-            lyric: line.to_string(),
-            is_upvote: true,
+            album,
+            song_name,
+            lyric: line,
+            is_upvote,
         });
     }
 
-    "".to_owned()
+    Ok(NoContent)
+}
+
+/// API endpoint to upvote a line
+#[openapi(tag = "Feedback")]
+#[post("/feedback/upvote_line", format = "application/json", data = "<vote>")]
+pub async fn upvote_line(
+    vote: Json<Vote>,
+    storage: &State<DynStorage>,
+    songs: &State<SongLibrary>,
+    vote_cache: &rocket::State<Arc<Mutex<RecentVotesCache>>>,
+) -> ApiResult<NoContent> {
+    add_vote(vote.into_inner(), true, storage, songs, vote_cache).await
 }
 
 /// API endpoint to downvote a line
-#[get("/feedback/downvote_line?<album>&<song_name>&<line>")]
+#[openapi(tag = "Feedback")]
+#[post(
+    "/feedback/downvote_line",
+    format = "application/json",
+    data = "<vote>"
+)]
 pub async fn downvote_line(
-    album: &str,
-    song_name: &str,
-    line: &str,
-    pool: &rocket::State<Pool<MySql>>,
+    vote: Json<Vote>,
+    storage: &State<DynStorage>,
+    songs: &State<SongLibrary>,
     vote_cache: &rocket::State<Arc<Mutex<RecentVotesCache>>>,
-) -> String {
-    let _ = sqlx::query("INSERT IGNORE INTO votes (album, song_name, lyric, num_upvotes, num_downvotes) VALUES (?, ?, ?, 0, 0);")
-        .bind(album)
-        .bind(song_name)
-        .bind(line)
-        .fetch_all(pool.inner())
-        .await;
-    let _ = sqlx::query("UPDATE votes SET num_downvotes = num_downvotes + 1 WHERE album LIKE ? AND song_name LIKE ? AND lyric LIKE ?;")
-        .bind(album)
-        .bind(song_name)
-        .bind(line)
-        .fetch_all(pool.inner())
-        .await;
-
-    {
-        let mut guard = vote_cache.lock().unwrap();
-        guard.add(VoteEvent {
-            time: Utc::now(),
-            album: album.to_string(),
-            song_name: song_name.to_string(),
-            lyric: line.to_string(),
-            is_upvote: false,
-        });
-    }
-
-    "".to_owned()
+) -> ApiResult<NoContent> {
+    add_vote(vote.into_inner(), false, storage, songs, vote_cache).await
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, JsonSchema)]
 pub struct Feedback {
     album: String,
     song: String,
@@ -86,6 +92,7 @@ pub struct Feedback {
 }
 
 /// API endpoint for general feedback
+#[openapi(tag = "Feedback")]
 #[post(
     "/feedback/general",
     format = "application/json",
@@ -93,8 +100,9 @@ pub struct Feedback {
 )]
 pub async fn get_feedback(
     feedback_data: Json<Feedback>,
-    pool: &rocket::State<Pool<MySql>>,
-) -> String {
+    storage: &State<DynStorage>,
+    songs: &State<SongLibrary>,
+) -> ApiResult<NoContent> {
     let feedback_data = feedback_data.into_inner();
     dbg!(&feedback_data);
     let Feedback {
@@ -105,14 +113,16 @@ pub async fn get_feedback(
         contact,
     } = feedback_data;
 
-    let _ = sqlx::query("INSERT INTO feedback (time, album, song_name, lyric, message, contact) VALUES (NOW(), ?, ?, ?, ?, ?)")
-        .bind(album)
-        .bind(song)
-        .bind(lyric)
-        .bind(message)
-        .bind(contact)
-        .fetch_all(pool.inner())
-        .await;
+    let line_id = songs.line_id(&album, &song, &lyric);
+    let line_key = LineKey {
+        id: &line_id,
+        album: &album,
+        song_name: &song,
+        text: &lyric,
+    };
+    storage
+        .insert_feedback(&line_key, &message, &contact)
+        .await?;
 
-    "".to_owned()
+    Ok(NoContent)
 }
@@ -1,17 +1,34 @@
+use crate::accounts::{check_name_is_free, Player};
+use crate::admin::Admin;
+use crate::error::{ApiError, ApiResult};
+use crate::game_rng::{random_seed, rng_for, Draw};
+use crate::game_secret::{generate_secret, hash_secret, GameSecret};
 use crate::guess_generating::{
     lowercase_ignore_punctuation_edit_dist, optimal_truncated_dist, pick_distractors,
     pick_random_guess, Question,
 };
-use crate::history::{Songlist, SonglistSchema};
+use crate::history::format_date;
 use crate::lifelines::{Lifeline, LifelineInventory};
+use crate::loader_v2::SongLibrary;
+use crate::persistence::{
+    delete_game_state, save_game_state, PersistedGameState, PersistedQuestion,
+};
+use crate::presets;
+use crate::reaper::END_REASON_INCORRECT;
+use crate::rules::GameRules;
+use crate::selection::Selection;
 use crate::song::Song;
+use crate::songlists::unnamed_songlist;
+use crate::storage::{now, DynStorage, NewGame, NewGuess};
 use rand::prelude::SliceRandom;
 use rand::Rng;
+use rocket::response::status::NoContent;
 use rocket::serde::json::Json;
 use rocket::State;
-use serde::Serialize;
-use sha1::{Digest, Sha1};
-use sqlx::{MySql, Pool};
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::types::time::{Date, PrimitiveDateTime};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use uuid::Uuid;
@@ -19,15 +36,10 @@ use uuid::Uuid;
 /// These characters are to be ignored when taking the edit distance between two strings:
 pub const CHARS_TO_IGNORE: &[char] = &['(', ')', ',', '.', '-', ':', ';', '"', '\'', '?', '!', ' '];
 
-/// If a guess's dist is greater than `MAX_ACCEPTABLE_DIST` from the answer, then the game ends.
-const MAX_ACCEPTABLE_DIST: usize = 13;
-/// A bonus is awarded the guess matches the answer perfectly.
-const POINTS_FOR_PERFECT_MATCH: i32 = 26;
-
 /// An enum representing a shown hint.
 /// The `Skip` variant is classified as a hint, even though it isn't really a hint,
 /// more of a lifeline.
-#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, JsonSchema)]
 pub enum Hint {
     ShowTitle(String),
     ShowPrevLines {
@@ -70,11 +82,22 @@ pub struct GameState {
     /// A vector of songs included in the game. The (str, str) pairs are
     /// (Album_name, Song_name) pairs.
     included_songs: Vec<(&'static str, &'static str)>,
+    /// The seed of the random draws of the game (see [`game_rng`](crate::game_rng)).
+    seed: u64,
+    /// The rules that the game is played with.
+    rules: GameRules,
+    /// The hash of the secret of the game (see [`game_secret`](crate::game_secret)), or `None` for games started
+    /// before games had secrets.
+    secret_hash: Option<String>,
+    /// When the state last changed, or `None` if it hasn't changed since it was restored from the database.
+    /// The [`reaper`](crate::reaper) checks it before ending a game, since a change is saved to the database only
+    /// after the state is updated.
+    last_activity: Option<PrimitiveDateTime>,
 }
 
 /// A struct related to [`GameState`]
 /// Used in responses to the player, while [`GameState`] is used by the server to store internal state.
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct GameStatePublic {
     id: String,
     score: i32,
@@ -82,14 +105,21 @@ pub struct GameStatePublic {
     current_question: Question,
     lifeline_inv: LifelineInventory,
     hints_shown: Vec<Hint>,
+    /// The answer choices of a multiple-choice question, or empty. The index of a choice in this vector is how
+    /// it is answered with `/game/submit-choice`, and doesn't change until the next question.
     choices: Vec<&'static str>,
     terminated: bool,
     included_songs: Vec<(&'static str, &'static str)>,
     completed_question: bool,
+    rules: GameRules,
+    /// The secret of the game, which is only returned when the game starts. Every request which changes the game
+    /// must send it in the `X-Game-Secret` header.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
 }
 
 /// A struct representing a result of a player's guess.
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub enum GuessResult {
     /// Asking for more. Used when the player's guess is on the right track but too short.
     /// In this case, we tell the player the target_length.
@@ -112,7 +142,7 @@ pub enum GuessResult {
 
 /// This is a combination of a [`GuessResult`] and a [`GameState`] sent back to the player
 /// after each guess.
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct GuessResultPublic {
     guess_res: GuessResult,
     game_state: GameStatePublic,
@@ -124,7 +154,12 @@ impl GameState {
     /// This function will modify the argument `songs_to_include`, so that if it's the empty vector,
     /// it will end up containing all songs in songs. It will also filter out any
     /// invalid songs in `songs_to_include`.
-    pub fn new(songs: &[Song], songs_to_include: &mut Vec<(&str, &str)>) -> Self {
+    pub fn new(
+        songs: &[Song],
+        songs_to_include: &mut Vec<(&str, &str)>,
+        seed: u64,
+        rules: GameRules,
+    ) -> Self {
         let mut actual_songs_to_include: Vec<(&'static str, &'static str)> = songs_to_include
             .clone()
             .into_iter()
@@ -142,16 +177,35 @@ impl GameState {
         GameState {
             score: 0,
             guesses_made: 0,
-            current_question: pick_random_guess(songs, &actual_songs_to_include),
-            lifeline_inv: LifelineInventory::new(),
+            current_question: pick_random_guess(
+                songs,
+                &actual_songs_to_include,
+                &mut rng_for(seed, 0, Draw::Question),
+            ),
+            lifeline_inv: rules.starting_lifelines.clone(),
             hints_shown: vec![],
             choices: vec![],
             terminated: false,
             completed_question: false,
             included_songs: actual_songs_to_include,
+            seed,
+            rules,
+            secret_hash: None,
+            last_activity: Some(now()),
         }
     }
 
+    /// Record that the state changed at `time`.
+    pub fn touch(&mut self, time: PrimitiveDateTime) {
+        self.last_activity = Some(time);
+    }
+
+    /// Whether the state has changed since `time`, as far as this server knows.
+    pub fn is_active_since(&self, time: PrimitiveDateTime) -> bool {
+        self.last_activity
+            .is_some_and(|last_activity| last_activity >= time)
+    }
+
     /// convert a [`GameState`] into a [`GameStatePublic`], with the answer hidden.
     /// since the GameState does not have a UUID, it must be provided.
     pub fn into_public(&self, id: String) -> GameStatePublic {
@@ -166,6 +220,8 @@ impl GameState {
             terminated: self.terminated,
             included_songs: self.included_songs.clone(),
             completed_question: self.completed_question,
+            rules: self.rules.clone(),
+            secret: None,
         }
     }
 
@@ -183,6 +239,8 @@ impl GameState {
             terminated: self.terminated,
             included_songs: self.included_songs.clone(),
             completed_question: self.completed_question,
+            rules: self.rules.clone(),
+            secret: None,
         }
     }
 
@@ -219,119 +277,400 @@ impl GameState {
     fn set_single_answer(&mut self, ans: &'static str) {
         self.current_question.answers = vec![ans];
     }
+
+    /// convert a [`GameState`] into a [`PersistedGameState`], which can be saved to the database.
+    pub fn to_persisted(&self) -> PersistedGameState {
+        PersistedGameState {
+            score: self.score,
+            guesses_made: self.guesses_made,
+            current_question: PersistedQuestion {
+                album: self.current_question.song.album.to_owned(),
+                song_name: self.current_question.song.name.to_owned(),
+                shown_line: self.current_question.shown_line.to_owned(),
+                answers: self
+                    .current_question
+                    .answers
+                    .iter()
+                    .map(|ans| ans.to_string())
+                    .collect(),
+            },
+            lifeline_inv: self.lifeline_inv.clone(),
+            hints_shown: self.hints_shown.clone(),
+            choices: self.choices.iter().map(|c| c.to_string()).collect(),
+            terminated: self.terminated,
+            completed_question: self.completed_question,
+            included_songs: self
+                .included_songs
+                .iter()
+                .map(|(album, name)| (album.to_string(), name.to_string()))
+                .collect(),
+            seed: Some(self.seed),
+            rules: self.rules.clone(),
+            secret_hash: self.secret_hash.clone(),
+        }
+    }
+
+    /// Restore a [`GameState`] from a [`PersistedGameState`].
+    ///
+    /// Returns `None` if the song of the current question no longer exists in `songs`.
+    /// Strings which cannot be found in `songs` (such as included songs that have since been removed,
+    /// or the easter egg distractor) are leaked, just like the lyrics themselves when songs are loaded.
+    pub fn from_persisted(persisted: PersistedGameState, songs: &[Song]) -> Option<Self> {
+        let song = songs.iter().find(|song| {
+            song.album == persisted.current_question.album
+                && song.name == persisted.current_question.song_name
+        })?;
+
+        let to_static = |s: String| -> &'static str {
+            songs
+                .iter()
+                .flat_map(|song| song.lines.iter())
+                .find(|line| line.text == s)
+                .map(|line| line.text)
+                .unwrap_or_else(|| Box::leak(s.into_boxed_str()))
+        };
+
+        let included_songs = persisted
+            .included_songs
+            .into_iter()
+            .map(|(album, name)| {
+                songs
+                    .iter()
+                    .find(|song| song.album == album && song.name == name)
+                    .map(|song| (song.album, song.name))
+                    .unwrap_or_else(|| {
+                        (
+                            Box::leak(album.into_boxed_str()) as &'static str,
+                            Box::leak(name.into_boxed_str()) as &'static str,
+                        )
+                    })
+            })
+            .collect();
+
+        Some(GameState {
+            score: persisted.score,
+            guesses_made: persisted.guesses_made,
+            current_question: Question {
+                shown_line: to_static(persisted.current_question.shown_line),
+                song: song.clone(),
+                answers: persisted
+                    .current_question
+                    .answers
+                    .into_iter()
+                    .map(to_static)
+                    .collect(),
+            },
+            lifeline_inv: persisted.lifeline_inv,
+            hints_shown: persisted.hints_shown,
+            choices: persisted.choices.into_iter().map(to_static).collect(),
+            terminated: persisted.terminated,
+            completed_question: persisted.completed_question,
+            included_songs,
+            // games saved before games had seeds can't be replayed, but draw like any other game from now on
+            seed: persisted.seed.unwrap_or_else(random_seed),
+            rules: persisted.rules,
+            secret_hash: persisted.secret_hash,
+            last_activity: None,
+        })
+    }
 }
 
 /// API endpoint to start a new game.
+///
+/// The body lists the (album, song name) pairs to draw questions from, or is an empty list to draw from every
+/// song.
+/// A game can also be started from a preset, with `POST /game/start?preset=<code>` and no body.
+///
+/// `rules` names the preset of rules to play with (see `GET /rules`), `classic` by default.
+///
+/// If the request carries a session token, the game is linked to the player's account and named after it, as are
+/// games started from a songlist or a preset.
+// ranked after `init_game_from_preset`, which forwards requests without a preset to this route
+#[openapi(tag = "Game")]
 #[post(
-    "/game/start",
+    "/game/start?<rules>",
+    rank = 2,
     format = "application/json",
     data = "<songs_to_include>"
 )]
 pub async fn init_game(
     game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
-    songs: &State<Vec<Song>>,
+    songs: &State<SongLibrary>,
     songs_to_include: Json<Vec<(&str, &str)>>,
-    pool: &rocket::State<Pool<MySql>>,
-) -> String {
-    let mut songs_to_include = songs_to_include.to_vec();
-    let new_game_state = GameState::new(songs, &mut songs_to_include);
-    let uuid = Uuid::new_v4().to_string();
+    rules: Option<&str>,
+    player: Option<Player>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GameStatePublic>> {
+    let setup = GameSetup {
+        rules: GameRules::preset_or_classic(rules)?,
+        ..GameSetup::for_player(player)
+    };
+    start_game(game_state, songs, songs_to_include.to_vec(), setup, storage).await
+}
 
-    {
-        let mut guard = game_state.lock().unwrap();
-        (*guard).insert(uuid.clone(), new_game_state.clone());
+/// API endpoint to start a new game with the songs of a songlist, e.g. a curated songlist such as
+/// `Eras Tour setlist`. Songs of the songlist which are no longer part of the game are left out.
+///
+/// If `selection` is given, only the songs of the songlist which it selects are included. This is the
+/// `selection` of a past game, so that a game with the same songs can be started from a shared link.
+///
+/// `rules` names the preset of rules to play with (see `GET /rules`), `classic` by default.
+#[openapi(tag = "Game")]
+#[post("/game/start/<songlist_id>?<selection>&<rules>")]
+pub async fn init_game_from_songlist(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    songs: &State<SongLibrary>,
+    songlist_id: i32,
+    selection: Option<&str>,
+    rules: Option<&str>,
+    player: Option<Player>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GameStatePublic>> {
+    let rules = GameRules::preset_or_classic(rules)?;
+    let Some(songlist) = storage.get_songlist(songlist_id).await? else {
+        return Err(ApiError::NotFound(format!(
+            "no songlist with id {}",
+            songlist_id
+        )));
+    };
+    let selected_songs: Vec<&(String, String)> = match selection {
+        Some(selection) => Selection::decode(selection)
+            .and_then(|selection| selection.songs(&songlist.content))
+            .map_err(|e| ApiError::BadRequest(format!("invalid selection: {}", e)))?,
+        None => songlist.content.iter().collect(),
+    };
+    let current_songs = songs.current();
+    let songs_to_include: Vec<(&str, &str)> = selected_songs
+        .into_iter()
+        .map(|(album, name)| (album.as_str(), name.as_str()))
+        .filter(|&(album, name)| {
+            current_songs
+                .iter()
+                .any(|song| song.album == album && song.name == name)
+        })
+        .collect();
+    if songs_to_include.is_empty() {
+        return Err(ApiError::Conflict(format!(
+            "none of the songs of songlist {} are part of the game anymore",
+            songlist_id
+        )));
     }
+    let setup = GameSetup {
+        rules,
+        ..GameSetup::for_player(player)
+    };
+    start_game(game_state, songs, songs_to_include, setup, storage).await
+}
 
-    let full_songlist: Vec<(&'static str, &'static str)> =
-        songs.iter().map(|song| (song.album, song.name)).collect();
-    let mut songlist_desc: HashMap<&'static str, Vec<bool>> = HashMap::new();
-    // The for loop below builds out the songlist_desc object, which is a Hashmap mapping album names to a list of boolean values.
-    // The list of boolean values represents which songs are included/excluded in the game.
-    for song in &full_songlist {
-        let is_included = songs_to_include.contains(song);
-        if let Some(v) = songlist_desc.get(song.0) {
-            let mut v = v.clone();
-            v.push(is_included);
-            songlist_desc.insert(song.0, v);
-        } else {
-            songlist_desc.insert(song.0, vec![is_included]);
-        }
+/// API endpoint to start a new game with the songs and rules of the preset with code `preset` (see [`presets`]).
+///
+/// If any song of the preset is no longer part of the game, no game is started, and the response lists the
+/// missing songs.
+///
+/// This route shares its path with [`init_game`], which is the one described in the OpenAPI document.
+#[openapi(skip)]
+#[post("/game/start?<preset>")]
+pub async fn init_game_from_preset(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    songs: &State<SongLibrary>,
+    preset: &str,
+    player: Option<Player>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GameStatePublic>> {
+    let resolved = presets::resolve(preset, &songs.current(), storage).await?;
+    if !resolved.missing_songs.is_empty() {
+        return Err(ApiError::Conflict(format!(
+            "preset `{}` includes songs which are no longer part of the game: {}",
+            resolved.code,
+            presets::describe_songs(&resolved.missing_songs)
+        )));
     }
+    let songs_to_include: Vec<(&str, &str)> = resolved
+        .songs
+        .iter()
+        .map(|(album, name)| (album.as_str(), name.as_str()))
+        .collect();
+    let setup = GameSetup {
+        rules: resolved.rules,
+        ..GameSetup::for_player(player)
+    };
+    start_game(game_state, songs, songs_to_include, setup, storage).await
+}
 
-    let mut hasher = Sha1::new();
-    hasher.update(serde_json::to_string(&full_songlist).unwrap().as_bytes());
-    let full_songlist_hash = format!("{:X}", hasher.finalize());
-
-    let full_songlist_json: sqlx::types::Json<Vec<(&'static str, &'static str)>> =
-        sqlx::types::Json(full_songlist);
-    let songlist_desc_json = sqlx::types::Json(songlist_desc);
-
-    // check if the current songlist SHA already exists
-    let result: Vec<SonglistSchema> =
-        sqlx::query_as("SELECT * FROM songlists WHERE sha1sum LIKE ?")
-            .bind(full_songlist_hash.clone())
-            .fetch_all(pool.inner())
-            .await
-            .unwrap();
-
-    println!("result has length {}", result.len());
-
-    if result.is_empty() {
-        // insert a new record if the current songlist sha is not found
-        let _ = sqlx::query("INSERT INTO songlists (sha1sum, content) VALUES (?, ?)")
-            .bind(full_songlist_hash.clone())
-            .bind(full_songlist_json)
-            .fetch_all(pool.inner())
-            .await;
+/// API endpoint to start a new game with the same songs, seed and rules as the game `id`, e.g. to reproduce a bug
+/// report or to review a suspicious score. As long as the lyrics haven't changed since the game was played, the
+/// new game is asked the same questions, and offered the same choices and lifelines, when it is played the same way.
+#[openapi(tag = "Admin")]
+#[post("/admin/games/<id>/replay")]
+pub async fn replay_game(
+    _admin: Admin,
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    songs: &State<SongLibrary>,
+    id: &str,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GameStatePublic>> {
+    let Some(game) = storage.get_game(id).await? else {
+        return Err(ApiError::NotFound(format!("no game with id `{}`", id)));
+    };
+    let Some(seed) = game.seed else {
+        return Err(ApiError::Conflict(format!(
+            "game `{}` was started before games had seeds, and can't be replayed",
+            id
+        )));
+    };
+    let Some(songlist) = storage.get_songlist(game.songlist_id).await? else {
+        return Err(ApiError::Internal(format!(
+            "game `{}` refers to unknown songlist {}",
+            id, game.songlist_id
+        )));
+    };
+    let selected_songs = Selection::decode(game.selection.as_deref().unwrap_or_default())
+        .and_then(|selection| selection.songs(&songlist.content))
+        .map_err(|e| {
+            ApiError::Internal(format!("the selection of game `{}` is invalid: {}", id, e))
+        })?;
+
+    let current_songs = songs.current();
+    let (songs_to_include, missing_songs): (Vec<_>, Vec<_>) = selected_songs
+        .into_iter()
+        .cloned()
+        .partition(|(album, name)| {
+            current_songs
+                .iter()
+                .any(|song| song.album == album && song.name == name)
+        });
+    if !missing_songs.is_empty() {
+        return Err(ApiError::Conflict(format!(
+            "game `{}` includes songs which are no longer part of the game: {}",
+            id,
+            presets::describe_songs(&missing_songs)
+        )));
     }
 
-    let songlists: Vec<SonglistSchema> =
-        sqlx::query_as("SELECT * FROM songlists WHERE sha1sum LIKE ?")
-            .bind(full_songlist_hash.clone())
-            .fetch_all(pool.inner())
-            .await
-            .unwrap();
-
-    let songlists: Vec<Songlist> = songlists
-        .into_iter()
-        .map(|songlist| Songlist {
-            id: songlist.id,
-            sha1sum: songlist.sha1sum,
-            content: songlist.content.as_ref().clone(),
-        })
+    let songs_to_include = songs_to_include
+        .iter()
+        .map(|(album, name)| (album.as_str(), name.as_str()))
         .collect();
+    let setup = GameSetup {
+        seed: Some(seed),
+        // games recorded before rules could be chosen were played with the classic rules
+        rules: game.rules.map(|rules| rules.0).unwrap_or_default(),
+        ..GameSetup::default()
+    };
+    start_game(game_state, songs, songs_to_include, setup, storage).await
+}
+
+/// How a new game is set up, besides its songs.
+#[derive(Debug, Clone, Default)]
+pub struct GameSetup {
+    /// The seed of the random draws of the game (see [`game_rng`](crate::game_rng)), or `None` for a random seed.
+    pub seed: Option<u64>,
+    /// The date of the daily challenge that the game is played in, if any (see [`daily`](crate::daily)).
+    pub daily_date: Option<Date>,
+    /// The name of the player, for games which are named when they start rather than claimed when they end.
+    pub player_name: Option<String>,
+    /// The account of the player, if they are logged in (see [`accounts`](crate::accounts)).
+    pub account_id: Option<i32>,
+    pub rules: GameRules,
+}
+
+impl GameSetup {
+    /// The setup of a game started by `player`, which is named after their account if they are logged in.
+    pub fn for_player(player: Option<Player>) -> Self {
+        match player {
+            Some(player) => GameSetup {
+                player_name: Some(player.account.name),
+                account_id: Some(player.account.id),
+                ..GameSetup::default()
+            },
+            None => GameSetup::default(),
+        }
+    }
+}
 
-    let songlist_id = songlists
-        .first()
-        .expect(
-            "Expect to have one songlist with appropriate SHA1 sum after inserting the songlist",
-        )
-        .id;
+/// Start a new game with the songs `songs_to_include`, or with every song if it is empty.
+pub async fn start_game(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    songs: &State<SongLibrary>,
+    mut songs_to_include: Vec<(&str, &str)>,
+    setup: GameSetup,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GameStatePublic>> {
+    let songs = songs.current();
+    let seed = setup.seed.unwrap_or_else(random_seed);
+    let mut new_game_state =
+        GameState::new(&songs, &mut songs_to_include, seed, setup.rules.clone());
+    let uuid = Uuid::new_v4().to_string();
+    let secret = generate_secret();
+    new_game_state.secret_hash = Some(hash_secret(&secret));
+
+    let (songlist_id, full_songlist) = unnamed_songlist(&songs, storage).await?;
+    let selection = Selection::from_songs(&full_songlist, &songs_to_include);
 
     // save the game to database
-    let _ = sqlx::query("INSERT INTO games VALUES (?, NOW(), ?, ?, 0, NULL, NULL)")
-        .bind(uuid.clone())
-        .bind(songlist_id)
-        .bind(songlist_desc_json)
-        .fetch_all(pool.inner())
-        .await;
+    let new_game = NewGame {
+        uuid: uuid.clone(),
+        songlist_id,
+        selection: selection.encode(),
+        seed,
+        daily_date: setup.daily_date,
+        player_name: setup.player_name,
+        account_id: setup.account_id,
+        rules: setup.rules,
+        secret_hash: new_game_state.secret_hash.clone(),
+    };
+    if !storage.insert_game(&new_game).await? {
+        return Err(ApiError::Conflict(format!(
+            "`{}` has already played the daily challenge of {}",
+            new_game.player_name.unwrap_or_default(),
+            new_game.daily_date.map(format_date).unwrap_or_default()
+        )));
+    }
 
-    serde_json::to_string(&new_game_state.into_public(uuid.clone())).unwrap()
+    {
+        let mut guard = game_state.lock().unwrap();
+        (*guard).insert(uuid.clone(), new_game_state.clone());
+    }
+
+    save_game_state(storage, &uuid, &new_game_state).await;
+
+    Ok(Json(GameStatePublic {
+        secret: Some(secret),
+        ..new_game_state.into_public(uuid.clone())
+    }))
+}
+
+/// The body of a request about a game, such as advancing to its next question.
+#[derive(Deserialize, JsonSchema)]
+pub struct GameRequest {
+    pub id: String,
+}
+
+/// The body of a request to use a lifeline.
+#[derive(Deserialize, JsonSchema)]
+pub struct LifelineRequest {
+    pub id: String,
+    /// `show_title_album`, `show_prev_lines` or `skip`.
+    pub lifeline: String,
 }
 
 /// API endpoint to use a lifeline specified by `lifeline`.
-#[get("/game/use-lifeline?<id>&<lifeline>")]
+#[openapi(tag = "Game")]
+#[post("/game/use-lifeline", format = "application/json", data = "<request>")]
 pub async fn game_lifelines(
     game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
-    id: String,
-    lifeline: &str,
-    pool: &rocket::State<Pool<MySql>>,
-) -> String {
-    let res = 'outer_block: {
+    request: Json<LifelineRequest>,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GameStatePublic>> {
+    let LifelineRequest { id, lifeline } = request.into_inner();
+    let (res, is_skip) = 'outer_block: {
         let mut guard = game_state.lock().unwrap();
         if let Some(game_state) = (*guard).get(&id) {
+            secret.verify(&id, game_state.secret_hash.as_deref())?;
             let mut new_game_state = game_state.clone();
-            match lifeline {
+            new_game_state.touch(now());
+            match lifeline.as_str() {
                 "show_title_album" => {
                     if !new_game_state.has_used_lifeline(Lifeline::ShowTitleAlbum)
                         && new_game_state
@@ -345,11 +684,10 @@ pub async fn game_lifelines(
                         );
                         new_game_state.hints_shown.push(Hint::ShowTitle(title));
                         (*guard).insert(id.clone(), new_game_state.clone());
-                        return serde_json::to_string(&new_game_state.into_public(id.clone()))
-                            .unwrap();
+                        break 'outer_block (new_game_state, false);
                     } else {
                         // no lifelines remaining, so do nothing
-                        return serde_json::to_string(&game_state.into_public(id.clone())).unwrap();
+                        return Ok(Json(game_state.into_public(id.clone())));
                     }
                 }
                 "show_prev_lines" => {
@@ -358,18 +696,19 @@ pub async fn game_lifelines(
                             .lifeline_inv
                             .consume_lifeline(Lifeline::ShowPrevLines)
                     {
-                        let (lines, is_at_song_beginning) =
-                            get_previous_lines(&new_game_state.current_question);
+                        let (lines, is_at_song_beginning) = get_previous_lines(
+                            &new_game_state.current_question,
+                            new_game_state.rules.prev_lines_to_show,
+                        );
                         new_game_state.hints_shown.push(Hint::ShowPrevLines {
                             lines,
                             is_at_song_beginning,
                         });
                         (*guard).insert(id.clone(), new_game_state.clone());
-                        return serde_json::to_string(&new_game_state.into_public(id.clone()))
-                            .unwrap();
+                        break 'outer_block (new_game_state, false);
                     } else {
                         // no lifelines remaining, so do nothing
-                        return serde_json::to_string(&game_state.into_public(id)).unwrap();
+                        return Ok(Json(game_state.into_public(id)));
                     }
                 }
                 "skip" => {
@@ -379,154 +718,332 @@ pub async fn game_lifelines(
                         new_game_state.hints_shown.push(Hint::Skip);
                         new_game_state.completed_question = true;
                         (*guard).insert(id.clone(), new_game_state.clone());
-                        // not calling into_public() because we want to show everything, including all answers.
-                        break 'outer_block new_game_state;
+                        break 'outer_block (new_game_state, true);
                     } else {
                         // no lifelines remaining, so do nothing
-                        return serde_json::to_string(&game_state.into_public(id.clone())).unwrap();
+                        return Ok(Json(game_state.into_public(id.clone())));
                     }
                 }
-                _ => {}
+                _ => {
+                    return Err(ApiError::BadRequest(format!(
+                        "unknown lifeline `{}`",
+                        lifeline
+                    )));
+                }
             }
         }
 
-        return "{}".to_owned();
+        return Err(game_not_found(&id));
     };
+
+    save_game_state(storage, &id, &res).await;
+
+    if !is_skip {
+        return Ok(Json(res.into_public(id.clone())));
+    }
+
+    // not calling into_public() below because we want to show everything, including all answers.
     let gs = res.clone();
 
     let answer = gs
         .current_question
         .answers
-        .choose(&mut rand::thread_rng())
+        .choose(&mut rng_for(gs.seed, gs.guesses_made, Draw::SkippedAnswer))
         .unwrap();
 
-    let _ = sqlx::query("INSERT INTO guesses VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, NOW())")
-        .bind(id.clone())
-        .bind(gs.guesses_made)
-        .bind(gs.current_question.song.album)
-        .bind(gs.current_question.song.name)
-        .bind(gs.current_question.shown_line)
-        .bind(answer)
-        .bind("skipped")
-        .bind("")
-        .bind(0)
-        .bind(Option::<String>::None)
-        .bind(sqlx::types::Json(
-            gs.hints_shown
+    storage
+        .insert_guess(&NewGuess {
+            game_uuid: id.clone(),
+            order_num: gs.guesses_made,
+            album: gs.current_question.song.album.to_owned(),
+            song_name: gs.current_question.song.name.to_owned(),
+            line_id: gs
+                .current_question
+                .song
+                .line_id_of(gs.current_question.shown_line),
+            prompt: gs.current_question.shown_line.to_owned(),
+            correct_answer: answer.to_string(),
+            result: "skipped".to_owned(),
+            user_guess: "".to_owned(),
+            points_earned: 0,
+            lifeline_earned: None,
+            lifelines_used: gs
+                .hints_shown
                 .iter()
                 .map(|hint| hint.underlying_lifeline().as_string())
-                .collect::<Vec<String>>(),
-        ))
-        .bind(sqlx::types::Json(gs.choices))
-        .fetch_all(pool.inner())
-        .await;
+                .collect(),
+            options: gs.choices.iter().map(|c| c.to_string()).collect(),
+            selected_choice: None,
+        })
+        .await?;
 
-    serde_json::to_string(&res.into_public_with_answers(id.clone())).unwrap()
+    Ok(Json(res.into_public_with_answers(id.clone())))
 }
 
 /// API endpoint to turn the current question into multiple choice.
 /// Returns the new [`GameState`]
-#[get("/game/reduce-multiple-choice?<id>")]
-pub fn reduce_multiple_choice(
+#[openapi(tag = "Game")]
+#[post(
+    "/game/reduce-multiple-choice",
+    format = "application/json",
+    data = "<request>"
+)]
+pub async fn reduce_multiple_choice(
     game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
-    songs: &State<Vec<Song>>,
-    id: String,
-) -> String {
-    let mut guard = game_state.lock().unwrap();
-    if let Some(game_state) = (*guard).get(&id) {
+    songs: &State<SongLibrary>,
+    request: Json<GameRequest>,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GameStatePublic>> {
+    let GameRequest { id } = request.into_inner();
+    let songs = songs.current();
+    let new_game_state = {
+        let mut guard = game_state.lock().unwrap();
+        let Some(game_state) = (*guard).get(&id) else {
+            return Err(game_not_found(&id));
+        };
+        secret.verify(&id, game_state.secret_hash.as_deref())?;
         if !game_state.choices.is_empty() {
             // we do nothing if the current game state has already been reduced to multiple choice
-            return serde_json::to_string(&game_state.into_public(id.clone())).unwrap();
+            return Ok(Json(game_state.into_public(id.clone())));
         }
 
         let mut new_game_state = game_state.clone();
+        new_game_state.touch(now());
         let answers = new_game_state.current_question.answers.clone();
 
         // we pick the first answer. The answers vec will have already been shuffled.
         // it's important to pick the first one to remain consistent with the showPrevLines behavior
         let answer = *answers.first().unwrap();
 
-        new_game_state.choices = pick_distractors(answers, songs);
+        let mut rng = rng_for(
+            new_game_state.seed,
+            new_game_state.guesses_made,
+            Draw::Distractors,
+        );
+        new_game_state.choices = pick_distractors(
+            answers,
+            &songs,
+            new_game_state.rules.num_distractors,
+            &mut rng,
+        );
         new_game_state.choices.push(answer);
-        new_game_state.choices.shuffle(&mut rand::thread_rng());
+        new_game_state.choices.shuffle(&mut rng);
         // the question should now have only a single answer
         new_game_state.set_single_answer(answer);
 
         (*guard).insert(id.clone(), new_game_state.clone());
-        return serde_json::to_string(&new_game_state.into_public(id.clone())).unwrap();
-    }
+        new_game_state
+    };
+
+    save_game_state(storage, &id, &new_game_state).await;
 
-    "{}".to_owned()
+    Ok(Json(new_game_state.into_public(id.clone())))
 }
 
 /// API endpoint to advance to the next question. Does nothing if the current question is not completed.
 /// Returns the new [`GameState`]
-#[get("/game/next?<id>")]
-pub fn next_question(
+#[openapi(tag = "Game")]
+#[post("/game/next", format = "application/json", data = "<request>")]
+pub async fn next_question(
     game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
-    songs: &State<Vec<Song>>,
-    id: String,
-) -> String {
-    let mut guard = game_state.lock().unwrap();
-    if let Some(game_state) = (*guard).get(&id) {
-        if game_state.completed_question && !game_state.terminated {
-            let mut new_game_state = game_state.clone();
-            new_game_state.current_question = pick_random_guess(songs, &game_state.included_songs);
-            new_game_state.completed_question = false;
-            new_game_state.choices = vec![];
-            new_game_state.hints_shown = vec![];
-            new_game_state.guesses_made += 1;
-
-            (*guard).insert(id.clone(), new_game_state.clone());
-            return serde_json::to_string(&new_game_state.into_public(id.clone())).unwrap();
-        } else {
-            return serde_json::to_string(&game_state.into_public(id.clone())).unwrap();
+    songs: &State<SongLibrary>,
+    request: Json<GameRequest>,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GameStatePublic>> {
+    let id = request.into_inner().id;
+    let songs = songs.current();
+    let new_game_state = {
+        let mut guard = game_state.lock().unwrap();
+        let Some(game_state) = (*guard).get(&id) else {
+            return Err(game_not_found(&id));
+        };
+        secret.verify(&id, game_state.secret_hash.as_deref())?;
+        if !game_state.completed_question || game_state.terminated {
+            return Ok(Json(game_state.into_public(id.clone())));
         }
-    }
 
-    "{}".to_owned()
+        let mut new_game_state = game_state.clone();
+        new_game_state.touch(now());
+        new_game_state.guesses_made += 1;
+        new_game_state.current_question = pick_random_guess(
+            &songs,
+            &game_state.included_songs,
+            &mut rng_for(game_state.seed, new_game_state.guesses_made, Draw::Question),
+        );
+        new_game_state.completed_question = false;
+        new_game_state.choices = vec![];
+        new_game_state.hints_shown = vec![];
+
+        (*guard).insert(id.clone(), new_game_state.clone());
+        new_game_state
+    };
+
+    save_game_state(storage, &id, &new_game_state).await;
+
+    Ok(Json(new_game_state.into_public(id.clone())))
+}
+
+/// The body of a request to claim a game.
+#[derive(Deserialize, JsonSchema)]
+pub struct ClaimRequest {
+    pub id: String,
+    pub name: String,
 }
 
 /// API endpoint to claim a game
 /// When a game first ends after an incorrect response, the game is "unclaimed", and so the player name
 /// will be NULL in the database. If the player enters their name, this API endpoint will be called.
-#[get("/game/claim?<id>&<name>")]
-pub async fn claim_game(id: String, name: String, pool: &rocket::State<Pool<MySql>>) -> String {
-    let _ = sqlx::query(
-        "UPDATE games
-			SET
-				player_name = ?
-			WHERE
-				UUID = ?
-		",
-    )
-    .bind(name)
-    .bind(id)
-    .fetch_all(pool.inner())
-    .await;
-
-    "{}".to_owned()
+/// A game can only be claimed once, and not with the name of an account (see [`accounts`](crate::accounts)).
+#[openapi(tag = "Game")]
+#[post("/game/claim", format = "application/json", data = "<request>")]
+pub async fn claim_game(
+    request: Json<ClaimRequest>,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> ApiResult<NoContent> {
+    let ClaimRequest { id, name } = request.into_inner();
+    let Some(game) = storage.get_game(&id).await? else {
+        return Err(game_not_found(&id));
+    };
+    secret.verify(&id, game.secret_hash.as_deref())?;
+    if game.daily_date.is_some() {
+        return Err(ApiError::Conflict(format!(
+            "game `{}` is a daily challenge, which is named when it starts",
+            id
+        )));
+    }
+    check_name_is_free(storage, &name).await?;
+    if !storage.claim_game(&id, &name).await? {
+        return Err(ApiError::Conflict(format!(
+            "game `{}` has already been claimed",
+            id
+        )));
+    }
+
+    Ok(NoContent)
+}
+
+/// The body of a request to submit a guess.
+#[derive(Deserialize, JsonSchema)]
+pub struct GuessRequest {
+    pub id: String,
+    pub guess: String,
+}
+
+/// The body of a request to answer a multiple-choice question with the index of a choice.
+#[derive(Deserialize, JsonSchema)]
+pub struct ChoiceRequest {
+    pub id: String,
+    pub choice: usize,
 }
 
 /// Submit a guess for a game.
-#[get("/game/submit-guess?<id>&<guess>")]
+///
+/// If the current question is multiple choice, the guess should be the text of one of the `choices`; a guess which
+/// matches none of them, even ignoring case and punctuation, is wrong. Prefer answering with the index of the
+/// choice (see [`take_choice`]).
+#[openapi(tag = "Game")]
+#[post("/game/submit-guess", format = "application/json", data = "<request>")]
 pub async fn take_guess(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    request: Json<GuessRequest>,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GuessResultPublic>> {
+    let GuessRequest { id, guess } = request.into_inner();
+    submit(game_state, id, Submission::Text(&guess), secret, storage).await
+}
+
+/// Answer the current multiple-choice question of a game with the index of one of its `choices`.
+#[openapi(tag = "Game")]
+#[post("/game/submit-choice", format = "application/json", data = "<request>")]
+pub async fn take_choice(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    request: Json<ChoiceRequest>,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GuessResultPublic>> {
+    let ChoiceRequest { id, choice } = request.into_inner();
+    submit(game_state, id, Submission::Choice(choice), secret, storage).await
+}
+
+/// What a player submitted to answer a question.
+#[derive(Clone, Copy, Debug)]
+enum Submission<'a> {
+    /// A typed guess, or the text of a choice.
+    Text(&'a str),
+    /// The index of a choice of a multiple-choice question.
+    Choice(usize),
+}
+
+impl GameState {
+    /// The guess that `submission` stands for, along with the index of the chosen choice if the current
+    /// question is multiple choice and the submission is one of its choices.
+    fn resolve_submission<'a>(
+        &self,
+        submission: Submission<'a>,
+    ) -> ApiResult<(&'a str, Option<usize>)> {
+        match submission {
+            Submission::Choice(_) if self.choices.is_empty() => Err(ApiError::Conflict(
+                "the current question is not multiple choice".to_owned(),
+            )),
+            Submission::Choice(index) => match self.choices.get(index) {
+                Some(choice) => Ok((choice, Some(index))),
+                None => Err(ApiError::BadRequest(format!(
+                    "there is no choice {}, the choices are numbered from 0 to {}",
+                    index,
+                    self.choices.len() - 1
+                ))),
+            },
+            Submission::Text(guess) if self.choices.is_empty() => Ok((guess, None)),
+            Submission::Text(guess) => {
+                let index = self
+                    .choices
+                    .iter()
+                    .position(|choice| *choice == guess)
+                    .or_else(|| {
+                        self.choices.iter().position(|choice| {
+                            lowercase_ignore_punctuation_edit_dist(choice, guess) == 0
+                        })
+                    });
+                Ok(match index {
+                    Some(index) => (self.choices[index], Some(index)),
+                    None => (guess, None),
+                })
+            }
+        }
+    }
+}
+
+/// Handle a submission for the current question of a game, and record it if it was correct or incorrect.
+async fn submit(
     game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
     id: String,
-    guess: &str,
-    pool: &rocket::State<Pool<MySql>>,
-) -> String {
+    submission: Submission<'_>,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<GuessResultPublic>> {
     let outer_game_state: GameState;
     let mut closest_answer;
+    let guess;
+    let selected_choice;
     let guess_res = 'outer_block: {
         let mut guard = game_state.lock().unwrap();
         if let Some(game_state) = (*guard).get(&id) {
+            secret.verify(&id, game_state.secret_hash.as_deref())?;
             closest_answer = game_state.current_question.answers[0];
             if game_state.completed_question {
                 // already guessed, so we do nothing
-                return serde_json::to_string(&game_state.into_public_with_answers(id)).unwrap();
+                return Err(ApiError::Conflict(format!(
+                    "the current question of game `{}` has already been answered",
+                    id
+                )));
             }
-            if guess.chars().count() > 150 {
+            (guess, selected_choice) = game_state.resolve_submission(submission)?;
+            let rules = &game_state.rules;
+            if guess.chars().count() > rules.max_guess_length {
                 // We also return AFM (refuse to process the guess) if the user submits a ridiculously long guess.
                 let res = GuessResultPublic {
                     game_state: game_state.into_public(id),
@@ -535,7 +1052,7 @@ pub async fn take_guess(
                         guess_length: guess.chars().count(),
                     },
                 };
-                return serde_json::to_string(&res).unwrap();
+                return Ok(Json(res));
             }
 
             // HANDLE MULTIPLE CHOICE (inside this if statement)
@@ -543,8 +1060,9 @@ pub async fn take_guess(
                 let correct_answer = game_state.current_question.answers[0];
 
                 let mut new_game_state = game_state.clone();
+                new_game_state.touch(now());
 
-                if guess == correct_answer {
+                if selected_choice.is_some() && guess == correct_answer {
                     // The user guessed correctly on a multiple choice question
                     new_game_state.score += 1;
                     new_game_state.completed_question = true;
@@ -595,7 +1113,7 @@ pub async fn take_guess(
                 // evaluate the answer
                 let (truncate_amt_local, dist) = optimal_truncated_dist(guess, ans);
 
-                if dist <= MAX_ACCEPTABLE_DIST {
+                if dist <= rules.max_acceptable_dist {
                     // the guess is close enough
                     has_correct_continuation = true;
                     if dist < minimal_edit_dist
@@ -608,7 +1126,7 @@ pub async fn take_guess(
                         truncate_amt = truncate_amt_local;
                     }
                 }
-                if is_afm(ans, guess) {
+                if is_afm(ans, guess, rules.afm_chars_per_error) {
                     // this is a possible AFM
                     can_be_afm = true;
                     target_length = ans.len();
@@ -623,24 +1141,30 @@ pub async fn take_guess(
                         guess_length: guess.chars().count(),
                     },
                 };
-                return serde_json::to_string(&res).unwrap();
+                return Ok(Json(res));
             }
 
             let mut maybe_new_lifeline = None;
             let mut new_game_state = game_state.clone();
+            new_game_state.touch(now());
 
             if has_correct_continuation {
                 // the user got the guess right
+                let mut rng = rng_for(
+                    game_state.seed,
+                    game_state.guesses_made,
+                    Draw::LifelineReward,
+                );
                 let points_earned = if minimal_edit_dist != 0 {
                     // The guess was correct but not perfect.
-                    if rand::thread_rng().gen_range(0..MAX_ACCEPTABLE_DIST) > minimal_edit_dist {
-                        maybe_new_lifeline = Some(Lifeline::random_lifeline());
+                    if rng.gen_range(0..rules.max_acceptable_dist) > minimal_edit_dist {
+                        maybe_new_lifeline = Some(Lifeline::random_lifeline(&mut rng));
                     }
-                    (MAX_ACCEPTABLE_DIST - minimal_edit_dist + 1) as i32
+                    (rules.max_acceptable_dist - minimal_edit_dist + 1) as i32
                 } else {
                     // perfect match
-                    maybe_new_lifeline = Some(Lifeline::random_lifeline());
-                    POINTS_FOR_PERFECT_MATCH
+                    maybe_new_lifeline = Some(Lifeline::random_lifeline(&mut rng));
+                    rules.points_for_perfect_match
                 };
 
                 new_game_state.score += points_earned;
@@ -678,7 +1202,7 @@ pub async fn take_guess(
                 break 'outer_block res;
             }
         }
-        return "{}".to_owned();
+        return Err(game_not_found(&id));
     };
 
     let gs = outer_game_state;
@@ -707,54 +1231,59 @@ pub async fn take_guess(
     // If the code runs to this point, then the guess is either correct or incorrect (not AFM state).
     // We can now record the guess into the database before returning.
 
-    let _ = sqlx::query("INSERT INTO guesses VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, NOW())")
-        .bind(id.clone())
-        .bind(gs.guesses_made)
-        .bind(gs.current_question.song.album)
-        .bind(gs.current_question.song.name)
-        .bind(gs.current_question.shown_line)
-        .bind(closest_answer)
-        .bind(if is_correct { "correct" } else { "incorrect" })
-        .bind(guess)
-        .bind(num_points_earned)
-        .bind(lifeline_earned)
-        .bind(sqlx::types::Json(
-            gs.hints_shown
+    let recorded = storage
+        .insert_guess(&NewGuess {
+            game_uuid: id.clone(),
+            order_num: gs.guesses_made,
+            album: gs.current_question.song.album.to_owned(),
+            song_name: gs.current_question.song.name.to_owned(),
+            line_id: gs
+                .current_question
+                .song
+                .line_id_of(gs.current_question.shown_line),
+            prompt: gs.current_question.shown_line.to_owned(),
+            correct_answer: closest_answer.to_owned(),
+            result: if is_correct { "correct" } else { "incorrect" }.to_owned(),
+            user_guess: guess.to_owned(),
+            points_earned: num_points_earned,
+            lifeline_earned,
+            lifelines_used: gs
+                .hints_shown
                 .iter()
                 .map(|hint| hint.underlying_lifeline().as_string())
-                .collect::<Vec<String>>(),
-        ))
-        .bind(sqlx::types::Json(gs.choices))
-        .fetch_all(pool.inner())
+                .collect(),
+            options: gs.choices.iter().map(|c| c.to_string()).collect(),
+            selected_choice: selected_choice.map(|index| index as i32),
+        })
         .await;
 
-    if !is_correct {
-        let _ = sqlx::query(
-            "UPDATE games 
-			SET 
-				has_terminated = true,
-				terminal_score = ?
-			WHERE
-				UUID = ?
-		",
-        )
-        .bind(gs.score)
-        .bind(id.clone())
-        .fetch_all(pool.inner())
-        .await;
+    // the game has already changed in memory, so its saved state must follow even if the guess wasn't recorded:
+    // otherwise, a game which was lost would be restored after a restart, with another try at the question
+    if is_correct {
+        save_game_state(storage, &id, &gs).await;
+    } else {
+        delete_game_state(storage, &id).await;
+        storage
+            .end_game(&id, gs.score, END_REASON_INCORRECT)
+            .await?;
     }
+    recorded?;
+
+    Ok(Json(guess_res))
+}
 
-    serde_json::to_string(&guess_res).unwrap()
+fn game_not_found(id: &str) -> ApiError {
+    ApiError::NotFound(format!("no game in progress with id `{}`", id))
 }
 
-fn is_afm(ans: &str, guess: &str) -> bool {
+fn is_afm(ans: &str, guess: &str, chars_per_error: usize) -> bool {
     if guess.chars().count() >= ans.chars().count() {
         return false;
     }
     let n = guess.chars().count();
     let ans = ans.chars().take(n).collect::<String>();
 
-    lowercase_ignore_punctuation_edit_dist(&ans, guess) <= (n / 5)
+    lowercase_ignore_punctuation_edit_dist(&ans, guess) <= (n / chars_per_error)
 }
 
 /// whether `guess` is on the right track to `answer`. If a guess is on the right track,
@@ -762,15 +1291,13 @@ fn is_afm(ans: &str, guess: &str) -> bool {
 ///
 /// Note that as implemented, the guess "owuefh" is on the right track to any guess, since
 /// the after truncation of answer, the distance of truncated answer and guess will not exceed
-/// `MAX_ACCEPTABLE_DIST`.
-pub fn is_on_right_track(guess: &str, answer: &str) -> bool {
+/// `max_acceptable_dist`.
+pub fn is_on_right_track(guess: &str, answer: &str, max_acceptable_dist: usize) -> bool {
     let (_, dist) = optimal_truncated_dist(answer, guess);
-    dist <= MAX_ACCEPTABLE_DIST
+    dist <= max_acceptable_dist
 }
 
-fn get_previous_lines(question: &Question) -> (String, bool) {
-    const PREV_LINES_TO_SHOW: usize = 2;
-
+fn get_previous_lines(question: &Question, prev_lines_to_show: usize) -> (String, bool) {
     let preferred_answer = *question.answers.first().unwrap();
 
     let lines = &question.song.lines;
@@ -786,8 +1313,8 @@ fn get_previous_lines(question: &Question) -> (String, bool) {
     }
     let mut output = String::new();
 
-    let is_at_song_beginning = answer_position <= PREV_LINES_TO_SHOW;
-    let beginning_index = std::cmp::max(answer_position as i32 - PREV_LINES_TO_SHOW as i32, 0);
+    let is_at_song_beginning = answer_position <= prev_lines_to_show;
+    let beginning_index = std::cmp::max(answer_position as i32 - prev_lines_to_show as i32, 0);
 
     for line in lines
         .iter()
@@ -0,0 +1,61 @@
+//! The random draws of a game.
+//!
+//! Every game has a seed, which is recorded with the game, and makes each random draw with a generator derived
+//! from its seed, the number of the question and the kind of draw. So every game with the same seed is asked the
+//! same questions and offered the same choices, and the draws of a question don't depend on what happened earlier
+//! in the game, e.g. on whether an earlier question was turned into multiple choice. A game can thus be replayed
+//! exactly, for bug reports, tests and reviews of suspicious scores.
+//!
+//! Games are given a random seed, except the daily challenge, whose seed is derived from its date
+//! (see [`daily`](crate::daily)).
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// A kind of random draw made during a question.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Draw {
+    /// Picking the question.
+    Question,
+    /// Picking the choices of a multiple-choice question.
+    Distractors,
+    /// Deciding whether a correct guess earns a lifeline, and which one.
+    LifelineReward,
+    /// Picking which of the answers of a skipped question is recorded as its answer.
+    SkippedAnswer,
+}
+
+/// The number of kinds of [`Draw`].
+const NUM_DRAWS: u64 = 4;
+
+/// A seed for a new game.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+/// The generator for the draw `draw` of the `question_num`-th question (counting from 0) of a game with the seed
+/// `seed`.
+pub fn rng_for(seed: u64, question_num: i32, draw: Draw) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(question_num as u64 * NUM_DRAWS + draw as u64);
+    rng
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    fn draws(seed: u64, question_num: i32, draw: Draw) -> Vec<u32> {
+        let mut rng = rng_for(seed, question_num, draw);
+        (0..4).map(|_| rng.next_u32()).collect()
+    }
+
+    #[test]
+    fn seeded_draws_are_reproducible_and_independent() {
+        assert_eq!(draws(7, 2, Draw::Question), draws(7, 2, Draw::Question));
+        assert_ne!(draws(7, 2, Draw::Question), draws(8, 2, Draw::Question));
+        assert_ne!(draws(7, 2, Draw::Question), draws(7, 3, Draw::Question));
+        assert_ne!(draws(7, 2, Draw::Question), draws(7, 2, Draw::Distractors));
+    }
+}
@@ -0,0 +1,113 @@
+//! The secrets which prove that a request comes from the player of a game.
+//!
+//! Starting a game returns a secret along with its uuid. Since uuids are listed by the history endpoints, every
+//! endpoint which changes a game requires the secret as well, in an `X-Game-Secret` header. Only a hash of the
+//! secret is stored, with the game and with its in-progress state.
+//!
+//! Games started before games had secrets have none, and can be played without one.
+
+use crate::error::{ApiError, ApiResult};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::{
+    Object, SecurityRequirement, SecurityScheme, SecuritySchemeData,
+};
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use sha1::{Digest, Sha1};
+
+/// The header which carries the secret of a game.
+pub const SECRET_HEADER: &str = "X-Game-Secret";
+
+/// A new random secret, as URL-safe base64.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 24];
+    OsRng.fill_bytes(&mut bytes);
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// The hash of a secret, which is what is stored.
+pub fn hash_secret(secret: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(secret.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// A request guard for the secret sent with a request, if any. It never fails, so that the endpoint can tell
+/// whether the game it is about needs a secret.
+pub struct GameSecret(Option<String>);
+
+impl GameSecret {
+    /// Check that the secret was sent, if the game with uuid `id` has one with hash `secret_hash`.
+    pub fn verify(&self, id: &str, secret_hash: Option<&str>) -> ApiResult<()> {
+        let Some(secret_hash) = secret_hash else {
+            return Ok(());
+        };
+        match &self.0 {
+            Some(secret) if hash_secret(secret) == secret_hash => Ok(()),
+            Some(_) => Err(ApiError::Unauthorized(format!(
+                "the secret of game `{}` is wrong",
+                id
+            ))),
+            None => Err(ApiError::Unauthorized(format!(
+                "the secret of game `{}` is required in the `{}` header",
+                id, SECRET_HEADER
+            ))),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for GameSecret {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let secret = request.headers().get_one(SECRET_HEADER);
+        Outcome::Success(GameSecret(secret.map(str::to_owned)))
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for GameSecret {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        let scheme = SecurityScheme {
+            description: Some("The secret returned when the game was started.".to_owned()),
+            data: SecuritySchemeData::ApiKey {
+                name: SECRET_HEADER.to_owned(),
+                location: "header".to_owned(),
+            },
+            extensions: Object::default(),
+        };
+        let mut requirement = SecurityRequirement::new();
+        requirement.insert("GameSecret".to_owned(), Vec::new());
+        Ok(RequestHeaderInput::Security(
+            "GameSecret".to_owned(),
+            scheme,
+            requirement,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_secret_of_a_game_is_accepted() {
+        let secret = generate_secret();
+        assert_ne!(secret, generate_secret());
+        let hash = hash_secret(&secret);
+
+        assert!(GameSecret(Some(secret)).verify("id", Some(&hash)).is_ok());
+        assert!(GameSecret(Some("guess".to_owned()))
+            .verify("id", Some(&hash))
+            .is_err());
+        assert!(GameSecret(None).verify("id", Some(&hash)).is_err());
+        // games without a secret don't need one
+        assert!(GameSecret(None).verify("id", None).is_ok());
+    }
+}
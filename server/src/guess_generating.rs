@@ -1,18 +1,16 @@
 use crate::game::CHARS_TO_IGNORE;
-use crate::song::{Line, Song};
+use crate::song::{Line, Song, SongMetadata};
 use edit_distance::edit_distance;
 use rand::seq::SliceRandom;
 use rand::Rng;
+use schemars::JsonSchema;
 use serde::Serialize;
 
-/// If there are 16 distractors, then there are 17 answer choices in total.
-const NUM_DISTRACTORS: i32 = 16;
-
 /// A struct representing a question asked to the player.
 /// The field `answer` is a list of possible answers, and
 /// The field `song` is the song that the question
 /// comes from, so these fields should be hidden from the user until the question is answered.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct Question {
     pub shown_line: &'static str,
     pub song: Song,
@@ -25,11 +23,14 @@ impl Question {
         Question {
             shown_line: self.shown_line,
             song: Song {
+                id: String::new(),
                 album: "",
                 name: "",
+                track: 0,
                 lyrics_raw: "",
                 lines: vec![],
                 lines_unique: vec![],
+                metadata: SongMetadata::default(),
             },
             answers: Vec::new(),
         }
@@ -90,23 +91,21 @@ fn is_acceptable_guess(guess: &'static str, lines: &[Line]) -> bool {
         .any(|l| l.is_bad_prompt.is_none() && l.text == guess)
 }
 
-/// Generates distractor answer choices for a multiple choice question, while ensuring that
+/// Generates `num_distractors` distractor answer choices for a multiple choice question, while ensuring that
 /// the distractors are not too close to the correct answer
 ///
 pub fn pick_distractors(
     correct_answers: Vec<&'static str>,
-    songs: &Vec<Song>,
+    songs: &[Song],
+    num_distractors: usize,
+    rng: &mut impl Rng,
 ) -> Vec<&'static str> {
     let mut distractors = Vec::new();
-    for _ in 0..NUM_DISTRACTORS {
-        let random_song = songs.choose(&mut rand::thread_rng()).unwrap();
+    for _ in 0..num_distractors {
+        let random_song = songs.choose(rng).unwrap();
         let mut random_line;
         loop {
-            random_line = random_song
-                .lines
-                .choose(&mut rand::thread_rng())
-                .unwrap()
-                .text;
+            random_line = random_song.lines.choose(rng).unwrap().text;
             let mut is_far_from_all_answers = true;
             for ans in &correct_answers {
                 if are_close_enough(random_line, ans) {
@@ -121,7 +120,7 @@ pub fn pick_distractors(
     }
 
     // This is an easter egg, where there's a small probability for one of the distractors to be a funny quote by Ms. Swift:
-    if rand::thread_rng().gen::<i32>() % 100 == 0 {
+    if rng.gen::<i32>() % 100 == 0 {
         distractors[0] = "umm I think for me...";
     }
 
@@ -132,23 +131,24 @@ pub fn pick_distractors(
 pub fn pick_random_guess(
     songs: &[Song],
     songs_to_include: &[(&'static str, &'static str)],
+    rng: &mut impl Rng,
 ) -> Question {
-    let songs: Vec<Song> = songs
+    let mut included: Vec<Song> = songs
         .iter()
         .filter(|song| songs_to_include.contains(&(song.album, song.name)))
         .cloned()
         .collect();
-    let random_song = songs.choose(&mut rand::thread_rng()).unwrap();
+    if included.is_empty() {
+        // every included song has been removed since the game started (by reloading the lyrics),
+        // so we draw from all songs instead.
+        included = songs.to_vec();
+    }
+    let songs = included;
+    let random_song = songs.choose(rng).unwrap();
 
-    let mut random_line = random_song
-        .lines_unique
-        .choose(&mut rand::thread_rng())
-        .unwrap();
+    let mut random_line = random_song.lines_unique.choose(rng).unwrap();
     while !is_acceptable_guess(random_line, &random_song.lines) {
-        random_line = random_song
-            .lines_unique
-            .choose(&mut rand::thread_rng())
-            .unwrap();
+        random_line = random_song.lines_unique.choose(rng).unwrap();
     }
 
     let lines = &random_song.lines;
@@ -164,7 +164,7 @@ pub fn pick_random_guess(
     // assume that the first answer in the `answers` is the preferred one.
     // we make this assumption to ensure consistent answers between multiple choice and showPrevLines.
     // thus it is important to shuffle the answers array here
-    answers.shuffle(&mut rand::thread_rng());
+    answers.shuffle(rng);
 
     Question {
         shown_line: random_line,
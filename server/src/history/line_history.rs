@@ -1,35 +1,17 @@
+use crate::error::ApiResult;
+use crate::loader_v2::SongLibrary;
+use crate::revisions::line_ids_with_history;
+use crate::storage::{DynStorage, GuessRecord};
+use rocket::serde::json::Json;
 use rocket::time::format_description;
+use rocket::State;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
 use serde::Serialize;
-use sqlx::{
-    types::{time::PrimitiveDateTime, Json},
-    MySql, Pool,
-};
 
-#[derive(sqlx::FromRow, Debug)]
-pub struct GuessSchema {
-    game_uuid: String,
-    order_num: i32,
-    album: String,
-    song_name: String,
-    prompt: String,
-    correct_answer: String,
-    result: String,
-    user_guess: String,
-    points_earned: i32,
-    lifeline_earned: Option<String>,
-    lifelines_used: Json<Vec<String>>,
-    options: Json<Vec<String>>,
-    submit_time: PrimitiveDateTime,
-    player_name: Option<String>,
-}
-
-#[derive(sqlx::FromRow, Debug)]
-pub struct VotesSchema {
-    num_upvotes: i32,
-    num_downvotes: i32,
-}
-
-#[derive(Serialize)]
+/// A single guess of a line, made in any game.
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "LineGuess")]
 pub struct Guess {
     game_uuid: String,
     order_num: i32,
@@ -43,95 +25,69 @@ pub struct Guess {
     lifeline_earned: Option<String>,
     lifelines_used: Vec<String>,
     options: Vec<String>,
+    /// The index in `options` of the choice that the player picked, if they picked one.
+    selected_choice: Option<i32>,
     submit_time: String,
     player_name: Option<String>,
 }
 
 impl Guess {
-    pub fn from_schema(guess_schema: GuessSchema) -> Self {
+    pub fn from_record(guess: GuessRecord) -> Self {
         let format =
             format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]Z").unwrap();
 
         Guess {
-            game_uuid: guess_schema.game_uuid,
-            order_num: guess_schema.order_num,
-            album: guess_schema.album,
-            song_name: guess_schema.song_name,
-            prompt: guess_schema.prompt,
-            correct_answer: guess_schema.correct_answer,
-            result: guess_schema.result,
-            user_guess: guess_schema.user_guess,
-            points_earned: guess_schema.points_earned,
-            lifeline_earned: guess_schema.lifeline_earned,
-            lifelines_used: serde_json::from_str(
-                &serde_json::to_string(&guess_schema.lifelines_used).unwrap(),
-            )
-            .unwrap(),
-            options: serde_json::from_str(&serde_json::to_string(&guess_schema.options).unwrap())
-                .unwrap(),
-            submit_time: guess_schema.submit_time.format(&format).unwrap(),
-            player_name: guess_schema.player_name,
+            game_uuid: guess.game_uuid,
+            order_num: guess.order_num,
+            album: guess.album,
+            song_name: guess.song_name,
+            prompt: guess.prompt,
+            correct_answer: guess.correct_answer,
+            result: guess.result,
+            user_guess: guess.user_guess,
+            points_earned: guess.points_earned,
+            lifeline_earned: guess.lifeline_earned,
+            lifelines_used: guess.lifelines_used,
+            options: guess.options,
+            selected_choice: guess.selected_choice,
+            submit_time: guess.submit_time.format(&format).unwrap(),
+            player_name: guess.player_name,
         }
     }
 }
 
-#[derive(Serialize)]
+/// All guesses of a line, along with its votes.
+#[derive(Serialize, JsonSchema)]
 pub struct LineResult {
     guesses: Vec<Guess>,
     num_upvotes: i32,
     num_downvotes: i32,
 }
 
+/// API endpoint to get every guess made of a line, most recent first, along with the votes of the line.
+/// The guesses and votes of earlier versions of the line, from before its lyrics were corrected, are included.
+#[openapi(tag = "History")]
 #[get("/history/line?<album>&<song>&<prompt>")]
 pub async fn get_line(
-    pool: &rocket::State<Pool<MySql>>,
+    storage: &State<DynStorage>,
+    songs: &State<SongLibrary>,
     album: &str,
     song: &str,
     prompt: &str,
-) -> String {
-    let guesses: Vec<GuessSchema> = sqlx::query_as(
-        "SELECT guesses.*, games.player_name from guesses
-        INNER JOIN games ON guesses.game_uuid=games.uuid
-        WHERE
-        album LIKE ?
-        AND song_name LIKE ?
-        AND prompt LIKE ?
-        ORDER BY submit_time DESC
-        ",
-    )
-    .bind(album)
-    .bind(song)
-    .bind(prompt)
-    .fetch_all(pool.inner())
-    .await
-    .unwrap();
-
-    let guesses: Vec<Guess> = guesses.into_iter().map(Guess::from_schema).collect();
+) -> ApiResult<Json<LineResult>> {
+    let line_id = songs.line_id(album, song, prompt);
+    let revisions = storage.get_line_revisions().await?;
 
-    let votes: Vec<VotesSchema> = sqlx::query_as(
-        "SELECT * FROM votes WHERE album LIKE ? AND song_name LIKE ? AND lyric LIKE ?;",
-    )
-    .bind(album)
-    .bind(song)
-    .bind(prompt)
-    .fetch_all(pool.inner())
-    .await
-    .unwrap();
-
-    let vote_schema = votes.first();
-
-    let num_upvotes;
-    let num_downvotes;
-    match vote_schema {
-        Some(v) => {
-            num_upvotes = v.num_upvotes;
-            num_downvotes = v.num_downvotes;
-        }
-        None => {
-            num_upvotes = 0;
-            num_downvotes = 0;
-        }
-    };
+    let mut guesses = Vec::new();
+    let (mut num_upvotes, mut num_downvotes) = (0, 0);
+    for line_id in line_ids_with_history(&revisions, &line_id) {
+        guesses.extend(storage.get_guesses_for_line(&line_id).await?);
+        let (upvotes, downvotes) = storage.get_votes(&line_id).await?;
+        num_upvotes += upvotes;
+        num_downvotes += downvotes;
+    }
+    guesses.sort_by_key(|guess| std::cmp::Reverse(guess.submit_time));
+    let guesses: Vec<Guess> = guesses.into_iter().map(Guess::from_record).collect();
 
     let line_result = LineResult {
         guesses,
@@ -139,5 +95,5 @@ pub async fn get_line(
         num_upvotes,
     };
 
-    serde_json::to_string(&line_result).unwrap()
+    Ok(Json(line_result))
 }
@@ -1,13 +1,19 @@
 //! Allows users to view guess details and score summaries of past games.
 
+use crate::daily;
+use crate::error::{ApiError, ApiResult};
+use crate::rules::GameRules;
+use crate::selection::Selection;
+use crate::storage::{DynStorage, GamesQuery, GuessRecord};
+use rocket::serde::json::Json;
 use rocket::time::format_description;
+use rocket::State;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
-use sqlx::{
-    types::{time::PrimitiveDateTime, Json},
-    MySql, Pool,
-};
-use std::collections::HashMap;
+use sqlx::types::time::{Date, PrimitiveDateTime};
+use sqlx::types::Json as SqlJson;
 
 pub mod line_history;
 
@@ -16,182 +22,174 @@ pub struct GameSchema {
     pub uuid: String,
     pub start_time: PrimitiveDateTime,
     pub songlist_id: i32,
-    pub selected_songs: Json<HashMap<String, Vec<bool>>>,
+    /// The encoded [`Selection`] of the songs of the game, over the songs of its songlist.
+    pub selection: Option<String>,
+    /// The seed of the random draws of the game, or `None` for games started before games had seeds.
+    pub seed: Option<u64>,
+    /// The date of the daily challenge that the game was played in, if any.
+    pub daily_date: Option<Date>,
+    /// The rules that the game was played with, or `None` for games started before rules could be chosen.
+    pub rules: Option<SqlJson<GameRules>>,
     pub has_terminated: bool,
     pub terminal_score: Option<i32>,
     pub player_name: Option<String>,
     pub num_guesses: i32,
+    pub end_reason: Option<String>,
+    /// The hash of the secret of the game, or `None` for games started before games had secrets.
+    pub secret_hash: Option<String>,
+    /// The account of the player, if the game was played or linked to an account.
+    pub account_id: Option<i32>,
 }
 
 /// Represents the summary of a past game.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct Game {
     pub uuid: String,
     pub start_time: String,
     pub songlist_id: i32,
     pub selected_songs: Vec<(String, String)>,
+    /// The selected songs, encoded as a [`Selection`] over the songs of the songlist.
+    /// A game with the same songs can be started with `POST /game/start/<songlist_id>?selection=<selection>`.
+    pub selection: Option<String>,
+    /// The seed of the random draws of the game (see [`game_rng`](crate::game_rng)), as a string since it may not
+    /// fit in a JavaScript number. `None` until the game has ended, since the seed gives away every answer, and for
+    /// games started before games had seeds.
+    pub seed: Option<String>,
+    /// The date of the daily challenge that the game was played in (see [`daily`](crate::daily)), e.g.
+    /// `"2024-03-14"`.
+    pub daily_date: Option<String>,
+    /// The rules that the game was played with (see `GET /rules`).
+    /// `None` for games started before rules could be chosen, which were played with the `classic` rules.
+    pub rules: Option<GameRules>,
     pub has_terminated: bool,
     pub terminal_score: Option<i32>,
     pub player_name: Option<String>,
+    /// Whether the player was logged in to the account named `player_name` (see [`accounts`](crate::accounts)).
+    /// Other player names are typed in by whoever played the game.
+    pub verified: bool,
     pub num_guesses: i32,
-}
-
-#[derive(sqlx::FromRow, Debug)]
-pub struct SonglistSchema {
-    pub id: i32,
-    pub sha1sum: String,
-    pub content: Json<Vec<(String, String)>>,
+    /// Why the game ended, e.g. `"incorrect"` or `"abandoned"`.
+    /// `None` for games that have not ended, or that ended before the reason was recorded.
+    pub end_reason: Option<String>,
 }
 
 /// Represents a list of songs available at a particular point in time.
 /// Any game will draw a subset of songs from a particular `Songlist`.
 /// For example, there's a songlist that contains every album released before Midnights.
-#[derive(Debug, Deserialize)]
+///
+/// Such songlists are created implicitly when a game starts, and have no name. Curated songlists, which have a
+/// name, are created by the admin instead (see [`songlists`](crate::songlists)).
+#[derive(Debug, Clone, Deserialize)]
 pub struct Songlist {
     /// `id` is used in a game to indicate which `SongList` is used
     pub id: i32,
     pub sha1sum: String,
     pub content: Vec<(String, String)>,
+    /// The name of a curated songlist, e.g. `Eras Tour setlist`.
+    pub name: Option<String>,
+    pub description: Option<String>,
 }
 
 /// API endpoint to get all past games, with various filtering options.
+/// If `verified` is true, only the games of players who were logged in to an account are listed.
 /// Results are paginated.
-#[get("/history/all?<sort>&<search>&<limit>&<include_nameless>&<page_num>")]
+#[openapi(tag = "History")]
+#[get("/history/all?<sort>&<search>&<limit>&<include_nameless>&<verified>&<page_num>")]
 pub async fn get_games(
-    pool: &rocket::State<Pool<MySql>>,
+    storage: &State<DynStorage>,
     sort: Option<String>,
     search: Option<String>,
     page_num: Option<usize>,
     limit: Option<usize>,
     include_nameless: Option<bool>,
-) -> String {
-    let sort = sort.unwrap_or_else(|| "start_time".to_string());
-    let search = format!("%{}%", search.unwrap_or_default());
+    verified: Option<bool>,
+) -> ApiResult<Json<Vec<Game>>> {
+    let sort_by_score = match sort.as_deref() {
+        None | Some("start_time") => false,
+        Some("score") => true,
+        Some(other) => {
+            return Err(ApiError::BadRequest(format!(
+                "unknown sort `{}`, expected `score` or `start_time`",
+                other
+            )))
+        }
+    };
     let limit = limit.unwrap_or(20); // Default limit is 20 results per page
-    let page_num = page_num.map_or(0, |num| if num > 0 { num } else { 1 });
+    let page_num = page_num.map_or(1, |num| if num > 0 { num } else { 1 });
     let include_nameless = include_nameless.unwrap_or(true);
 
     let query_offset = (page_num - 1) * limit;
 
-    let songlists: Vec<SonglistSchema> = sqlx::query_as("SELECT * from songlists")
-        .fetch_all(pool.inner())
-        .await
-        .unwrap();
-
-    let songlists: Vec<Songlist> = songlists
-        .into_iter()
-        .map(|songlist| Songlist {
-            id: songlist.id,
-            sha1sum: songlist.sha1sum,
-            content: songlist.content.as_ref().clone(),
-        })
-        .collect();
+    let query = GamesQuery {
+        sort_by_score,
+        search: search.unwrap_or_default(),
+        include_nameless,
+        daily_date: None,
+        account_id: None,
+        verified_only: verified.unwrap_or(false),
+        limit,
+        offset: query_offset,
+    };
+    Ok(Json(list_games(storage, &query).await?))
+}
 
-    let sub_query = "select count(*) from guesses where game_uuid like uuid";
+/// The seed of `game` as listed in the history, which is kept secret while the game is being played, and for a
+/// daily challenge, until the day's challenge is over, since every game of the day has the same seed.
+fn public_seed(game: &GameSchema) -> Option<String> {
+    if !game.has_terminated || game.daily_date.is_some_and(|date| date >= daily::today()) {
+        return None;
+    }
+    game.seed.map(|seed| seed.to_string())
+}
 
-    let query = match sort.as_str() {
-        "score" => {
-            if include_nameless {
-                format!(
-                    "SELECT *, ({}) as num_guesses from games
-				WHERE (player_name LIKE ? OR player_name IS NULL) AND has_terminated LIKE TRUE
-				ORDER BY terminal_score DESC
-				LIMIT ? OFFSET ?",
-                    sub_query
-                )
-            } else {
-                format!(
-                    "SELECT *, ({}) as num_guesses from games
-				WHERE (player_name LIKE ?) AND has_terminated LIKE TRUE
-				ORDER BY terminal_score DESC
-				LIMIT ? OFFSET ?",
-                    sub_query
-                )
-            }
-        }
-        _ => {
-            if include_nameless {
-                format!(
-                    "SELECT *, ({}) as num_guesses from games
-				WHERE (player_name LIKE ? OR player_name IS NULL) AND has_terminated LIKE TRUE
-				ORDER BY start_time DESC
-				LIMIT ? OFFSET ?",
-                    sub_query
-                )
-            } else {
-                format!(
-                    "SELECT *, ({}) as num_guesses from games
-				WHERE (player_name LIKE ?) AND has_terminated LIKE TRUE
-				ORDER BY start_time DESC
-				LIMIT ? OFFSET ?",
-                    sub_query
-                )
-            }
-        }
-    };
+/// The finished games which match `query`, with their selected songs.
+pub async fn list_games(storage: &DynStorage, query: &GamesQuery) -> ApiResult<Vec<Game>> {
+    let songlists: Vec<Songlist> = storage.get_songlists().await?;
 
-    let games: Vec<GameSchema> = sqlx::query_as(&query)
-        .bind(search)
-        .bind(limit as i32)
-        .bind(query_offset as i32)
-        .fetch_all(pool.inner())
-        .await
-        .unwrap();
+    let games = storage.get_games(query).await?;
 
-    let games: Vec<Game> = games
+    games
         .into_iter()
         .map(|game| {
-            let selected_songs =
-                serde_json::from_str(&serde_json::to_string(&game.selected_songs).unwrap())
-                    .unwrap();
-            let full_songlist = songlists
-                .iter()
-                .find(|s| s.id == game.songlist_id)
-                .unwrap()
-                .content
-                .clone();
-
-            let selected_songs_desc = get_songs(full_songlist, selected_songs);
+            let selected_songs_desc = get_songs(
+                find_songlist(&songlists, &game)?,
+                game.selection.as_deref(),
+                &game.uuid,
+            )?;
 
             let format =
                 format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]Z")
                     .unwrap();
 
-            Game {
+            let seed = public_seed(&game);
+            Ok(Game {
                 uuid: game.uuid,
                 start_time: game.start_time.format(&format).unwrap(),
                 songlist_id: game.songlist_id,
                 selected_songs: selected_songs_desc,
+                selection: game.selection,
+                seed,
+                daily_date: game.daily_date.map(format_date),
+                rules: game.rules.map(|rules| rules.0),
                 has_terminated: game.has_terminated,
                 terminal_score: game.terminal_score,
                 player_name: game.player_name,
+                verified: game.account_id.is_some(),
                 num_guesses: game.num_guesses,
-            }
+                end_reason: game.end_reason,
+            })
         })
-        .collect();
-
-    serde_json::to_string(&games).unwrap()
+        .collect()
 }
 
-#[derive(sqlx::FromRow, Debug)]
-pub struct GuessSchema {
-    game_uuid: String,
-    order_num: i32,
-    album: String,
-    song_name: String,
-    prompt: String,
-    correct_answer: String,
-    result: String,
-    user_guess: String,
-    points_earned: i32,
-    lifeline_earned: Option<String>,
-    lifelines_used: Json<Vec<String>>,
-    options: Json<Vec<String>>,
-    submit_time: PrimitiveDateTime,
+/// Format a date as e.g. `2024-03-14`.
+pub fn format_date(date: Date) -> String {
+    let format = format_description::parse("[year]-[month]-[day]").unwrap();
+    date.format(&format).unwrap()
 }
 
 /// Represents a single guess within a [`Game`]
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct Guess {
     game_uuid: String,
     order_num: i32,
@@ -205,132 +203,125 @@ pub struct Guess {
     lifeline_earned: Option<String>,
     lifelines_used: Vec<String>,
     options: Vec<String>,
+    /// The index in `options` of the choice that the player picked, if they picked one.
+    selected_choice: Option<i32>,
     submit_time: String,
 }
 
 impl Guess {
-    pub fn from_schema(guess_schema: GuessSchema) -> Self {
+    pub fn from_record(guess: GuessRecord) -> Self {
         let format =
             format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]Z").unwrap();
 
         Guess {
-            game_uuid: guess_schema.game_uuid,
-            order_num: guess_schema.order_num,
-            album: guess_schema.album,
-            song_name: guess_schema.song_name,
-            prompt: guess_schema.prompt,
-            correct_answer: guess_schema.correct_answer,
-            result: guess_schema.result,
-            user_guess: guess_schema.user_guess,
-            points_earned: guess_schema.points_earned,
-            lifeline_earned: guess_schema.lifeline_earned,
-            lifelines_used: serde_json::from_str(
-                &serde_json::to_string(&guess_schema.lifelines_used).unwrap(),
-            )
-            .unwrap(),
-            options: serde_json::from_str(&serde_json::to_string(&guess_schema.options).unwrap())
-                .unwrap(),
-            submit_time: guess_schema.submit_time.format(&format).unwrap(),
+            game_uuid: guess.game_uuid,
+            order_num: guess.order_num,
+            album: guess.album,
+            song_name: guess.song_name,
+            prompt: guess.prompt,
+            correct_answer: guess.correct_answer,
+            result: guess.result,
+            user_guess: guess.user_guess,
+            points_earned: guess.points_earned,
+            lifeline_earned: guess.lifeline_earned,
+            lifelines_used: guess.lifelines_used,
+            options: guess.options,
+            selected_choice: guess.selected_choice,
+            submit_time: guess.submit_time.format(&format).unwrap(),
         }
     }
 }
 
 /// A game with a list of guesses
-#[derive(Serialize)]
-struct GameWithGuesses {
+#[derive(Serialize, JsonSchema)]
+pub struct GameWithGuesses {
     game: Game,
     guesses: Vec<Guess>,
 }
 
-/// Get selected selected songs in the form of (album, song_name) from a set of boolean arrays.
+/// Find the songlist that a game drew its songs from.
+fn find_songlist<'a>(
+    songlists: &'a [Songlist],
+    game: &GameSchema,
+) -> ApiResult<&'a [(String, String)]> {
+    songlists
+        .iter()
+        .find(|s| s.id == game.songlist_id)
+        .map(|s| s.content.as_slice())
+        .ok_or_else(|| {
+            ApiError::Internal(format!(
+                "game `{}` refers to unknown songlist {}",
+                game.uuid, game.songlist_id
+            ))
+        })
+}
+
+/// Get the selected songs of a game in the form of (album, song_name), from its encoded [`Selection`].
 ///
 /// `full_songlist` represents the list of all possible songs at the time of the selection.
-/// `selectedSongs` is a hashmap where each key is an album, and the k-th boolean in the vector indicates whether
-/// the k-th song of the album was included (true means included).
+/// Fails if the selection is over a different number of songs, which means that it doesn't belong to the songlist.
 fn get_songs(
-    full_songlist: Vec<(String, String)>,
-    selected_songs: HashMap<String, Vec<bool>>,
-) -> Vec<(String, String)> {
-    let mut songs: Vec<(String, String)> = Vec::new();
-
-    let mut album_order = full_songlist
-        .iter()
-        .map(|x| x.0.clone())
-        .collect::<Vec<String>>();
-    album_order.dedup(); // remove duplicates since full_songlist contains one entry for each song but we only want a list of albums.
-
-    for album in album_order {
-        let inc_exc_list = selected_songs.get(&album).unwrap();
-
-        let mut album_songs_iter = full_songlist.iter().filter(|s| s.0 == *album);
-        for is_included in inc_exc_list {
-            let curr_album_song = album_songs_iter.next().unwrap();
-            if *is_included {
-                songs.push(curr_album_song.clone());
-            }
-        }
-    }
-
-    songs
+    full_songlist: &[(String, String)],
+    selection: Option<&str>,
+    game_uuid: &str,
+) -> ApiResult<Vec<(String, String)>> {
+    let Some(selection) = selection else {
+        return Err(ApiError::Internal(format!(
+            "the selected songs of game `{}` have not been converted",
+            game_uuid
+        )));
+    };
+    let songs = Selection::decode(selection).and_then(|s| {
+        s.songs(full_songlist)
+            .map(|songs| songs.into_iter().cloned().collect())
+    });
+    songs.map_err(|e| {
+        ApiError::Internal(format!(
+            "the selected songs of game `{}` do not match its songlist: {}",
+            game_uuid, e
+        ))
+    })
 }
 
 /// API endpoint for getting information about a game along with history of each guess
+#[openapi(tag = "History")]
 #[get("/history/game?<id>")]
-pub async fn get_game(pool: &rocket::State<Pool<MySql>>, id: String) -> String {
-    let songlists: Vec<SonglistSchema> = sqlx::query_as("SELECT * from songlists")
-        .fetch_all(pool.inner())
-        .await
-        .unwrap();
+pub async fn get_game(storage: &State<DynStorage>, id: String) -> ApiResult<Json<GameWithGuesses>> {
+    let songlists: Vec<Songlist> = storage.get_songlists().await?;
 
-    let songlists: Vec<Songlist> = songlists
-        .into_iter()
-        .map(|songlist| Songlist {
-            id: songlist.id,
-            sha1sum: songlist.sha1sum,
-            content: songlist.content.as_ref().clone(),
-        })
-        .collect();
-
-    let game: GameSchema = sqlx::query_as(
-        "SELECT *, (select count(*) from guesses where game_uuid like uuid) as num_guesses from games
-        WHERE uuid LIKE ?")
-        .bind(id.clone())
-        .fetch_one(pool.inner())
-        .await.unwrap();
+    let Some(game) = storage.get_game(&id).await? else {
+        return Err(ApiError::NotFound(format!("no game with id `{}`", id)));
+    };
     let format =
         format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]Z").unwrap();
 
-    let selected_songs =
-        serde_json::from_str(&serde_json::to_string(&game.selected_songs).unwrap()).unwrap();
-    let full_songlist = songlists
-        .iter()
-        .find(|s| s.id == game.songlist_id)
-        .unwrap()
-        .content
-        .clone();
-    let selected_songs_desc = get_songs(full_songlist, selected_songs);
+    let selected_songs_desc = get_songs(
+        find_songlist(&songlists, &game)?,
+        game.selection.as_deref(),
+        &game.uuid,
+    )?;
 
-    let guesses: Vec<GuessSchema> = sqlx::query_as(
-        "SELECT * from guesses
-        WHERE game_uuid LIKE ?",
-    )
-    .bind(id.clone())
-    .fetch_all(pool.inner())
-    .await
-    .unwrap();
+    let guesses = storage.get_guesses_for_game(&id).await?;
 
-    let guesses: Vec<Guess> = guesses.into_iter().map(Guess::from_schema).collect();
+    let guesses: Vec<Guess> = guesses.into_iter().map(Guess::from_record).collect();
 
+    let seed = public_seed(&game);
     let game = Game {
         uuid: game.uuid,
         start_time: game.start_time.format(&format).unwrap(),
         songlist_id: game.songlist_id,
         selected_songs: selected_songs_desc,
+        selection: game.selection,
+        seed,
+        daily_date: game.daily_date.map(format_date),
+        rules: game.rules.map(|rules| rules.0),
         has_terminated: game.has_terminated,
         terminal_score: game.terminal_score,
         player_name: game.player_name,
+        verified: game.account_id.is_some(),
         num_guesses: guesses.len() as i32,
+        end_reason: game.end_reason,
     };
 
-    serde_json::to_string(&GameWithGuesses { game, guesses }).unwrap()
+    Ok(Json(GameWithGuesses { game, guesses }))
 }
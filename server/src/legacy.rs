@@ -0,0 +1,178 @@
+//! The deprecated GET versions of the endpoints which change state.
+//!
+//! These endpoints used to take guesses, player names and lyrics in their query strings, which end up in proxy
+//! logs and can be requested by browsers prefetching links. They forward to the POST versions, which take JSON
+//! bodies, and mark their responses with a `Deprecation` header.
+//!
+//! They are mounted unless the `LEGACY_GET_ENDPOINTS` environment variable is `false`, and are left out of the
+//! API documentation.
+
+use crate::error::ApiResult;
+use crate::feedback::{self, Vote};
+use crate::game::{
+    self, ChoiceRequest, ClaimRequest, GameRequest, GameState, GameStatePublic, GuessRequest,
+    GuessResultPublic, LifelineRequest,
+};
+use crate::game_secret::GameSecret;
+use crate::loader_v2::SongLibrary;
+use crate::rss::RecentVotesCache;
+use crate::storage::DynStorage;
+use rocket::request::Request;
+use rocket::response::status::NoContent;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket::{Route, State};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Whether the deprecated GET endpoints are mounted.
+pub struct LegacyConfig {
+    pub get_endpoints: bool,
+}
+
+impl LegacyConfig {
+    pub fn from_env() -> Self {
+        LegacyConfig {
+            get_endpoints: std::env::var("LEGACY_GET_ENDPOINTS")
+                .map(|value| value != "false" && value != "0")
+                .unwrap_or(true),
+        }
+    }
+}
+
+/// A response of a deprecated endpoint, which carries a `Deprecation` header.
+pub struct Deprecated<R>(R);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Deprecated<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        Response::build_from(self.0.respond_to(request)?)
+            .raw_header("Deprecation", "true")
+            .ok()
+    }
+}
+
+/// The deprecated GET endpoints.
+pub fn routes() -> Vec<Route> {
+    routes![
+        game_lifelines,
+        reduce_multiple_choice,
+        next_question,
+        claim_game,
+        take_guess,
+        take_choice,
+        upvote_line,
+        downvote_line
+    ]
+}
+
+/// Deprecated: use `POST /game/use-lifeline`.
+#[get("/game/use-lifeline?<id>&<lifeline>")]
+pub async fn game_lifelines(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    id: String,
+    lifeline: String,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> Deprecated<ApiResult<Json<GameStatePublic>>> {
+    let request = Json(LifelineRequest { id, lifeline });
+    Deprecated(game::game_lifelines(game_state, request, secret, storage).await)
+}
+
+/// Deprecated: use `POST /game/reduce-multiple-choice`.
+#[get("/game/reduce-multiple-choice?<id>")]
+pub async fn reduce_multiple_choice(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    songs: &State<SongLibrary>,
+    id: String,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> Deprecated<ApiResult<Json<GameStatePublic>>> {
+    let request = Json(GameRequest { id });
+    Deprecated(game::reduce_multiple_choice(game_state, songs, request, secret, storage).await)
+}
+
+/// Deprecated: use `POST /game/next`.
+#[get("/game/next?<id>")]
+pub async fn next_question(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    songs: &State<SongLibrary>,
+    id: String,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> Deprecated<ApiResult<Json<GameStatePublic>>> {
+    let request = Json(GameRequest { id });
+    Deprecated(game::next_question(game_state, songs, request, secret, storage).await)
+}
+
+/// Deprecated: use `POST /game/claim`.
+#[get("/game/claim?<id>&<name>")]
+pub async fn claim_game(
+    id: String,
+    name: String,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> Deprecated<ApiResult<NoContent>> {
+    Deprecated(game::claim_game(Json(ClaimRequest { id, name }), secret, storage).await)
+}
+
+/// Deprecated: use `POST /game/submit-guess`.
+#[get("/game/submit-guess?<id>&<guess>")]
+pub async fn take_guess(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    id: String,
+    guess: String,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> Deprecated<ApiResult<Json<GuessResultPublic>>> {
+    let request = Json(GuessRequest { id, guess });
+    Deprecated(game::take_guess(game_state, request, secret, storage).await)
+}
+
+/// Deprecated: use `POST /game/submit-choice`.
+#[get("/game/submit-choice?<id>&<choice>")]
+pub async fn take_choice(
+    game_state: &State<Arc<Mutex<HashMap<String, GameState>>>>,
+    id: String,
+    choice: usize,
+    secret: GameSecret,
+    storage: &State<DynStorage>,
+) -> Deprecated<ApiResult<Json<GuessResultPublic>>> {
+    let request = Json(ChoiceRequest { id, choice });
+    Deprecated(game::take_choice(game_state, request, secret, storage).await)
+}
+
+/// Deprecated: use `POST /feedback/upvote_line`.
+#[get("/feedback/upvote_line?<album>&<song_name>&<line>")]
+pub async fn upvote_line(
+    album: String,
+    song_name: String,
+    line: String,
+    storage: &State<DynStorage>,
+    songs: &State<SongLibrary>,
+    vote_cache: &State<Arc<Mutex<RecentVotesCache>>>,
+) -> Deprecated<ApiResult<NoContent>> {
+    let vote = Json(Vote {
+        album,
+        song_name,
+        line,
+    });
+    Deprecated(feedback::upvote_line(vote, storage, songs, vote_cache).await)
+}
+
+/// Deprecated: use `POST /feedback/downvote_line`.
+#[get("/feedback/downvote_line?<album>&<song_name>&<line>")]
+pub async fn downvote_line(
+    album: String,
+    song_name: String,
+    line: String,
+    storage: &State<DynStorage>,
+    songs: &State<SongLibrary>,
+    vote_cache: &State<Arc<Mutex<RecentVotesCache>>>,
+) -> Deprecated<ApiResult<NoContent>> {
+    let vote = Json(Vote {
+        album,
+        song_name,
+        line,
+    });
+    Deprecated(feedback::downvote_line(vote, storage, songs, vote_cache).await)
+}
@@ -1,9 +1,10 @@
 use rand::prelude::*;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 /// An enum representing one of three possible lifelines.
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
 pub enum Lifeline {
     ShowTitleAlbum,
     ShowPrevLines,
@@ -11,9 +12,7 @@ pub enum Lifeline {
 }
 
 impl Lifeline {
-    pub fn random_lifeline() -> Self {
-        let mut rng = rand::thread_rng();
-
+    pub fn random_lifeline(rng: &mut impl Rng) -> Self {
         match rng.gen_range(0..3) {
             0 => Lifeline::ShowTitleAlbum,
             1 => Lifeline::ShowPrevLines,
@@ -31,7 +30,7 @@ impl Lifeline {
 }
 
 /// A struct representing how many of each lifeline a player has available.
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub struct LifelineInventory {
     show_title_album: i32,
     show_prev_lines: i32,
@@ -40,10 +39,14 @@ pub struct LifelineInventory {
 
 impl LifelineInventory {
     pub fn new() -> LifelineInventory {
+        LifelineInventory::of_each(1)
+    }
+    /// An inventory with `count` of each lifeline.
+    pub fn of_each(count: i32) -> LifelineInventory {
         LifelineInventory {
-            show_title_album: 1,
-            show_prev_lines: 1,
-            skip: 1,
+            show_title_album: count,
+            show_prev_lines: count,
+            skip: count,
         }
     }
     pub fn consume_lifeline(&mut self, lifeline: Lifeline) -> bool {
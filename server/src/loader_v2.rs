@@ -5,45 +5,672 @@
 //! The v2 loader loads songs from the `lyrics_data` directory in the directory. This allows
 //! me to have more control over the format of the songs, and which songs are considered part of the
 //! game can be changed by directly adding/removing files.
+//!
+//! By default, `lyrics_data` is embedded into the binary at compile time. A directory with the same layout
+//! can instead be read at runtime (see [`LyricsSource`]), so that lyrics can be fixed without rebuilding the
+//! server, and reloaded while it runs (see [`SongLibrary::reload`]).
+//!
+//! A song file is in one of two formats. The legacy format is positional: line 1 is the album name, line 2 is
+//! the song name, and the lyrics follow. Alternatively, a song file may begin with TOML front matter between two
+//! `+++` lines, followed by the lyrics:
+//!
+//! ```text
+//! +++
+//! album = "Red"
+//! title = "All Too Well (10 Minute Version)"
+//! track = 30
+//! release_date = 2021-11-12
+//! featured_artists = []
+//! version_tags = ["taylors-version", "vault"]
+//! alternate_titles = ["All Too Well (Taylor's Version)"]
+//! +++
+//! I walked through the door with you, the air was cold
+//! ```
+//!
+//! Every field but `album` and `title` is optional (see [`SongMetadata`]). In both formats, the markers of a
+//! line are written after a `$` at its end, e.g. `Oh, oh$<exclamatory>`.
 
-use crate::song::Song;
+use crate::album::{parse_album_dir_name, Album};
+use crate::lyrics_validator::{validate, Diagnostic, Rule};
+use crate::song::{line_id, slugify, song_id, Song, SongMetadata, VersionTag};
+use chrono::NaiveDate;
 use include_dir::{include_dir, Dir};
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
 /// Directory of lyrics files
 static LYRICS_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/../lyrics_data");
 
+/// Where songs are loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LyricsSource {
+    /// The `lyrics_data` directory, embedded into the binary at compile time.
+    Embedded,
+    /// A directory read at runtime, which has the same layout as `lyrics_data`.
+    Directory(PathBuf),
+}
+
+impl LyricsSource {
+    /// The directory given by the `--lyrics-dir <path>` command line flag, or else by the `LYRICS_DIR`
+    /// environment variable. If neither is set, the embedded lyrics are used.
+    pub fn from_args_and_env(args: &[String]) -> Self {
+        let flag = args
+            .iter()
+            .position(|arg| arg == "--lyrics-dir")
+            .map(|index| {
+                args.get(index + 1)
+                    .expect("--lyrics-dir must be followed by a path")
+                    .clone()
+            });
+        match flag.or_else(|| std::env::var("LYRICS_DIR").ok()) {
+            Some(dir) => LyricsSource::Directory(PathBuf::from(dir)),
+            None => LyricsSource::Embedded,
+        }
+    }
+}
+
+impl fmt::Display for LyricsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LyricsSource::Embedded => write!(f, "embedded lyrics"),
+            LyricsSource::Directory(dir) => write!(f, "{}", dir.display()),
+        }
+    }
+}
+
 /// Load songs from directory into a vector of [`Song`] structs.
 pub fn load_songs_from_files() -> Vec<Song> {
-    let mut songs: Vec<Song> = Vec::new();
+    load_songs(&LyricsSource::Embedded).expect("the embedded lyrics should be valid")
+}
+
+/// Load songs from `source` into a vector of [`Song`] structs.
+pub fn load_songs(source: &LyricsSource) -> Result<Vec<Song>, Vec<Diagnostic>> {
+    load_library(source).map(|(_, songs)| songs)
+}
+
+/// Load the albums and songs of `source`. Albums are in release order, and songs are in the order of their
+/// files, so the songs of each album are in track order.
+///
+/// Every file is validated before any song is created. If any file has an error, every error found is
+/// returned. Warnings never prevent songs from being loaded.
+pub fn load_library(source: &LyricsSource) -> Result<(Vec<Album>, Vec<Song>), Vec<Diagnostic>> {
+    let (files, mut diagnostics) = read_files(source);
+    diagnostics.extend(validate(&files));
+    diagnostics.retain(Diagnostic::is_error);
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    let mut albums = Vec::new();
+    let mut songs = Vec::new();
+    for (album_position, (album_path, album_file)) in files.albums.iter().enumerate() {
+        let dir_name = file_name(album_path);
+        let song_files: Vec<SongFile> = files
+            .songs
+            .iter()
+            .filter(|(path, _)| album_dir_name(path) == Some(dir_name))
+            .map(|(path, contents)| {
+                parse_song_file(path, contents).expect("song files are validated")
+            })
+            .collect();
+        let Some(first_song) = song_files.first() else {
+            continue;
+        };
+
+        let info = match album_file {
+            Some(contents) => {
+                parse_album_file(album_path, contents).expect("album files are validated")
+            }
+            None => AlbumInfo::default(),
+        };
+        let (ordinal, id) = parse_album_dir_name(dir_name);
+        albums.push(Album {
+            name: first_song.album.clone(),
+            release_date: info.release_date,
+            ordinal: ordinal.unwrap_or(album_position as u32 + 1),
+            cover_art: info.cover_art.unwrap_or_else(|| id.clone()),
+            id: id.clone(),
+        });
+
+        for (song_position, file) in song_files.into_iter().enumerate() {
+            let track = file.track.unwrap_or(song_position as u32 + 1);
+            songs.push(Song::new(
+                song_id(&id, &file.name),
+                Box::leak(file.album.into_boxed_str()),
+                Box::leak(file.name.into_boxed_str()),
+                track,
+                Box::leak(file.lyrics.to_owned().into_boxed_str()),
+                file.metadata,
+            ));
+        }
+    }
+    // the ordinals in directory names only order albums without a release date, which come last
+    albums.sort_by_key(|album| {
+        (
+            album.release_date.is_none(),
+            album.release_date.clone(),
+            album.ordinal,
+        )
+    });
+    for (position, album) in albums.iter_mut().enumerate() {
+        album.ordinal = position as u32 + 1;
+    }
+    Ok((albums, songs))
+}
+
+/// The last component of `path`.
+fn file_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path)
+}
+
+/// The name of the album directory which contains the song file at `path`, if it is in one.
+pub fn album_dir_name(path: &str) -> Option<&str> {
+    Path::new(path).parent()?.file_name()?.to_str()
+}
+
+/// The leading number of a song file's name, e.g. `4` for `04_i-knew-you-were-trouble-tv.txt`.
+fn track_from_file_name(path: &str) -> Option<u32> {
+    let name = file_name(path);
+    let digits = name
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(name.len());
+    name[..digits].parse().ok()
+}
+
+/// A song file, split into its parts.
+pub struct SongFile<'a> {
+    pub album: String,
+    pub name: String,
+    /// The track number given in the front matter, or else by the leading number of the file's name.
+    pub track: Option<u32>,
+    pub metadata: SongMetadata,
+    /// The raw lyrics, which are the remaining lines of the file.
+    pub lyrics: &'a str,
+    /// The line number of the first line of `lyrics` in the file, so that problems can be reported by line.
+    pub lyrics_start: usize,
+    pub has_front_matter: bool,
+}
+
+const FRONT_MATTER_DELIMITER: &str = "+++";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrontMatter {
+    #[serde(default)]
+    album: String,
+    #[serde(default)]
+    title: String,
+    track: Option<u32>,
+    /// Either a TOML date, or a string.
+    release_date: Option<toml::Value>,
+    #[serde(default)]
+    featured_artists: Vec<String>,
+    #[serde(default)]
+    version_tags: Vec<VersionTag>,
+    #[serde(default)]
+    alternate_titles: Vec<String>,
+}
+
+/// Split a song file into its album name, song name, metadata and raw lyrics. The file may be in either format
+/// (see the [module docs](self)).
+///
+/// Only malformed front matter is an error here. Whether the names are present is left to the validator.
+pub fn parse_song_file<'a>(path: &str, file_contents: &'a str) -> Result<SongFile<'a>, Diagnostic> {
+    let error = |line: Option<usize>, message: String| {
+        Diagnostic::new(path, line, Rule::InvalidFrontMatter, message)
+    };
+
+    let Some(after_delimiter) = file_contents.strip_prefix(FRONT_MATTER_DELIMITER) else {
+        // each song file in the legacy format always follows the format of:
+        // first line: contains album name
+        // second line: contains song name
+        // remaining lines: contains raw lyrics data
+        let mut parts = file_contents.splitn(3, '\n');
+        return Ok(SongFile {
+            album: parts.next().unwrap_or_default().to_owned(),
+            name: parts.next().unwrap_or_default().to_owned(),
+            track: track_from_file_name(path),
+            metadata: SongMetadata::default(),
+            lyrics: parts.next().unwrap_or_default(),
+            lyrics_start: 3,
+            has_front_matter: false,
+        });
+    };
 
+    let closing = format!("\n{}", FRONT_MATTER_DELIMITER);
+    let Some((front_matter, lyrics)) = after_delimiter.split_once(&closing) else {
+        return Err(error(
+            Some(1),
+            format!(
+                "front matter is not closed by a `{}` line",
+                FRONT_MATTER_DELIMITER
+            ),
+        ));
+    };
+    let lyrics = lyrics.strip_prefix('\n').unwrap_or(lyrics);
+    let front_matter: FrontMatter = toml::from_str(front_matter)
+        // the front matter begins after the opening `+++` line
+        .map_err(|e| toml_error(path, Rule::InvalidFrontMatter, 1, e))?;
+    let release_date =
+        parse_release_date(front_matter.release_date).map_err(|message| error(None, message))?;
+
+    Ok(SongFile {
+        album: front_matter.album,
+        name: front_matter.title,
+        track: front_matter.track.or_else(|| track_from_file_name(path)),
+        metadata: SongMetadata {
+            release_date,
+            featured_artists: front_matter.featured_artists,
+            version_tags: front_matter.version_tags,
+            alternate_titles: front_matter.alternate_titles,
+        },
+        lyrics,
+        lyrics_start: file_contents[..file_contents.len() - lyrics.len()]
+            .matches('\n')
+            .count()
+            + 1,
+        has_front_matter: true,
+    })
+}
+
+/// Turn an error in a TOML file into a diagnostic. `first_line` is the line number of the file on which the
+/// TOML begins.
+///
+/// Errors in a value are reported at the start of the TOML, but their message names the key.
+fn toml_error(path: &str, rule: Rule, first_line: usize, e: toml::de::Error) -> Diagnostic {
+    let line = e.line_col().map(|(line, _)| line + first_line);
+    let mut message = e.to_string();
+    if let Some(position) = message.rfind(" at line ") {
+        message.truncate(position);
+    }
+    Diagnostic::new(path, line, rule, message)
+}
+
+/// A release date may be given as a TOML date, or as a string, but must be of the form `YYYY-MM-DD`.
+fn parse_release_date(value: Option<toml::Value>) -> Result<Option<String>, String> {
+    let date = match value {
+        None => return Ok(None),
+        Some(toml::Value::Datetime(date)) => date.to_string(),
+        Some(toml::Value::String(date)) => date,
+        Some(other) => return Err(format!("release_date `{}` is not a date", other)),
+    };
+    match NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+        Ok(_) => Ok(Some(date)),
+        Err(_) => Err(format!(
+            "release_date `{}` should be of the form YYYY-MM-DD",
+            date
+        )),
+    }
+}
+
+/// The name of the file in an album directory which describes the album.
+const ALBUM_FILE: &str = "album.toml";
+
+/// The contents of an album's `album.toml` (see the [`album`](crate::album) module).
+#[derive(Default)]
+pub struct AlbumInfo {
+    pub release_date: Option<String>,
+    pub cover_art: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AlbumFile {
+    /// Either a TOML date, or a string.
+    release_date: Option<toml::Value>,
+    cover_art: Option<String>,
+}
+
+/// Parse the `album.toml` of the album directory at `album_path`.
+pub fn parse_album_file(album_path: &str, file_contents: &str) -> Result<AlbumInfo, Diagnostic> {
+    let path = Path::new(album_path).join(ALBUM_FILE).display().to_string();
+    let album_file: AlbumFile = toml::from_str(file_contents)
+        .map_err(|e| toml_error(&path, Rule::InvalidAlbumFile, 1, e))?;
+    Ok(AlbumInfo {
+        release_date: parse_release_date(album_file.release_date)
+            .map_err(|message| Diagnostic::new(&path, None, Rule::InvalidAlbumFile, message))?,
+        cover_art: album_file.cover_art,
+    })
+}
+
+/// The files of a lyrics source.
+#[derive(Default)]
+pub struct LyricsFiles {
+    /// Every song file, as (path, contents) pairs.
+    pub songs: Vec<(String, String)>,
+    /// Every album directory, as (path, contents of its `album.toml` if it has one) pairs.
+    pub albums: Vec<(String, Option<String>)>,
+}
+
+/// Read every file of `source`, along with a diagnostic for every file which could not be read.
+/// Song files are the `.txt` files of each album directory.
+pub fn read_files(source: &LyricsSource) -> (LyricsFiles, Vec<Diagnostic>) {
+    match source {
+        LyricsSource::Embedded => read_embedded_files(),
+        LyricsSource::Directory(dir) => read_directory_files(dir),
+    }
+}
+
+fn unreadable(path: &str, message: String) -> Diagnostic {
+    Diagnostic::new(path, None, Rule::UnreadableFile, message)
+}
+
+fn is_song_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "txt")
+}
+
+/// Read every file embedded into the binary.
+fn read_embedded_files() -> (LyricsFiles, Vec<Diagnostic>) {
+    let mut files = LyricsFiles::default();
+    let mut diagnostics = Vec::new();
     for album_dir in LYRICS_DIR.dirs() {
-        for song_file in album_dir.files() {
-            // Note: the directory names and file names have no effect on the underlying data,
-            // except for changing the order in which the songs and albums will be loaded (and thus displayed)
+        let album_path = album_dir.path().display().to_string();
+        let album_file = album_dir.get_file(album_dir.path().join(ALBUM_FILE));
+        let album_file = match album_file.map(|file| file.contents_utf8()) {
+            None => None,
+            Some(Some(contents)) => Some(contents.to_owned()),
+            Some(None) => {
+                diagnostics.push(unreadable(
+                    &album_path,
+                    format!("{} is not valid UTF-8", ALBUM_FILE),
+                ));
+                None
+            }
+        };
+        files.albums.push((album_path, album_file));
 
-            // each song file always follows the format of:
-            // first line: contains album name
-            // second line: contains song name
-            // remaining lines: contains raw lyrics data
+        for song_file in album_dir.files().filter(|file| is_song_file(file.path())) {
+            let path = song_file.path().display().to_string();
+            match song_file.contents_utf8() {
+                Some(contents) => files.songs.push((path, contents.to_owned())),
+                None => diagnostics.push(unreadable(&path, "file is not valid UTF-8".to_owned())),
+            }
+        }
+    }
+    (files, diagnostics)
+}
 
-            let file_contents = song_file.contents_utf8().unwrap();
-            let album_name_length = file_contents.find('\n').unwrap();
+/// Read every file in the album directories of `dir`.
+/// Album directories and song files are read in order of their names, just like the embedded files.
+fn read_directory_files(dir: &Path) -> (LyricsFiles, Vec<Diagnostic>) {
+    let sorted_entries = |dir: &Path| -> Result<Vec<PathBuf>, Diagnostic> {
+        let path = dir.display().to_string();
+        let error =
+            |e: std::io::Error| unreadable(&path, format!("could not read directory: {}", e));
+        let mut paths = std::fs::read_dir(dir)
+            .map_err(error)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(error)?;
+        paths.sort();
+        Ok(paths)
+    };
 
-            let album_name = &file_contents[..album_name_length];
-            let file_contents = &file_contents[album_name_length + 1..];
+    let mut files = LyricsFiles::default();
+    let mut diagnostics = Vec::new();
+    let album_dirs = match sorted_entries(dir) {
+        Ok(album_dirs) => album_dirs,
+        Err(e) => return (files, vec![e]),
+    };
+    for album_dir in album_dirs.into_iter().filter(|path| path.is_dir()) {
+        let song_files = match sorted_entries(&album_dir) {
+            Ok(song_files) => song_files,
+            Err(e) => {
+                diagnostics.push(e);
+                continue;
+            }
+        };
+        let album_path = album_dir.display().to_string();
+        let album_file = album_dir.join(ALBUM_FILE);
+        let album_file = match std::fs::read_to_string(&album_file) {
+            Ok(contents) => Some(contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                let path = album_file.display().to_string();
+                diagnostics.push(unreadable(&path, format!("could not read file: {}", e)));
+                None
+            }
+        };
+        files.albums.push((album_path, album_file));
 
-            let song_name_length = file_contents.find('\n').unwrap();
+        for song_file in song_files
+            .into_iter()
+            .filter(|path| path.is_file() && is_song_file(path))
+        {
+            let path = song_file.display().to_string();
+            match std::fs::read_to_string(&song_file) {
+                Ok(contents) => files.songs.push((path, contents)),
+                Err(e) => {
+                    diagnostics.push(unreadable(&path, format!("could not read file: {}", e)))
+                }
+            }
+        }
+    }
+    if files.songs.is_empty() && diagnostics.is_empty() {
+        diagnostics.push(unreadable(
+            &dir.display().to_string(),
+            "directory contains no song files".to_owned(),
+        ));
+    }
+    (files, diagnostics)
+}
 
-            let song_name = &file_contents[..song_name_length];
-            let file_contents = &file_contents[song_name_length + 1..];
+/// The albums and songs of a [`SongLibrary`] at one point in time.
+pub type LibrarySnapshot = (Arc<Vec<Album>>, Arc<Vec<Song>>);
 
-            songs.push(Song::new(
-                Box::leak(album_name.to_owned().into_boxed_str()),
-                Box::leak(song_name.to_owned().into_boxed_str()),
-                Box::leak(file_contents.to_owned().into_boxed_str()),
-            ));
+/// The albums and songs of the game, which can be swapped for newly loaded ones while the server runs.
+///
+/// Handlers take a snapshot of the songs with [`SongLibrary::current`]. In-progress games keep their own
+/// copy of their current question, so swapping the songs never interrupts them.
+pub struct SongLibrary {
+    source: LyricsSource,
+    /// The albums and songs are swapped together, so that a snapshot of either always matches the other.
+    contents: RwLock<LibrarySnapshot>,
+}
+
+impl SongLibrary {
+    pub fn new(source: LyricsSource, albums: Vec<Album>, songs: Vec<Song>) -> Self {
+        SongLibrary {
+            source,
+            contents: RwLock::new((Arc::new(albums), Arc::new(songs))),
         }
     }
 
-    songs
+    /// Load the albums and songs from `source`.
+    pub fn load(source: LyricsSource) -> Result<Self, Vec<Diagnostic>> {
+        let (albums, songs) = load_library(&source)?;
+        Ok(SongLibrary::new(source, albums, songs))
+    }
+
+    /// The songs which are currently loaded.
+    pub fn current(&self) -> Arc<Vec<Song>> {
+        self.contents.read().unwrap().1.clone()
+    }
+
+    /// The albums which are currently loaded, in release order.
+    pub fn albums(&self) -> Arc<Vec<Album>> {
+        self.contents.read().unwrap().0.clone()
+    }
+
+    /// The id of the line of the song `song_name` of the album `album` with the text `text`
+    /// (see [`Song::line_id_of`]), whether or not the song is still loaded.
+    pub fn line_id(&self, album: &str, song_name: &str, text: &str) -> String {
+        let (albums, songs) = self.contents.read().unwrap().clone();
+        if let Some(song) = songs
+            .iter()
+            .find(|song| song.album == album && song.name == song_name)
+        {
+            return song.line_id_of(text);
+        }
+        let album_id = match albums.iter().find(|a| a.name == album) {
+            Some(album) => album.id.clone(),
+            None => slugify(album),
+        };
+        line_id(&song_id(&album_id, song_name), text, 0)
+    }
+
+    /// Load the albums and songs from the source again, and swap them in.
+    /// If any file has an error, the current songs are kept and every error found is returned.
+    ///
+    /// Note that the previously loaded songs are leaked, like all songs are, since in-progress games may
+    /// still refer to their lyrics.
+    pub fn reload(&self) -> Result<LibrarySnapshot, Vec<Diagnostic>> {
+        let (albums, songs) = load_library(&self.source)?;
+        let contents = (Arc::new(albums), Arc::new(songs));
+        *self.contents.write().unwrap() = contents.clone();
+        Ok(contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_lyrics_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lyrics-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("01_Album")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn all_problems_in_a_directory_are_reported() {
+        let dir = temp_lyrics_dir("invalid");
+        std::fs::write(dir.join("01_Album/01.txt"), "Album\n").unwrap();
+        std::fs::write(
+            dir.join("01_Album/02.txt"),
+            "Album\nSong\nWe’re happy, free\nconfused and lonely at the same time\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("01_Album/03.txt"), "Album\nEmpty\n[Chorus]\n").unwrap();
+
+        let errors = load_songs(&LyricsSource::Directory(dir.clone())).unwrap_err();
+        let rules: Vec<(Option<usize>, Rule)> = errors.iter().map(|e| (e.line, e.rule)).collect();
+        assert_eq!(
+            rules,
+            [
+                (Some(2), Rule::MissingTitle),
+                (None, Rule::EmptySong),
+                (Some(3), Rule::InvalidChar),
+                (None, Rule::EmptySong),
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn front_matter_is_parsed_alongside_the_legacy_format() {
+        let dir = temp_lyrics_dir("front-matter");
+        std::fs::write(
+            dir.join("01_Album/01.txt"),
+            "+++\nalbum = \"Red\"\ntitle = \"Nothing New\"\ntrack = 26\nrelease_date = 2021-11-12\n\
+            featured_artists = [\"Phoebe Bridgers\"]\nversion_tags = [\"taylors-version\", \"vault\"]\n+++\n\
+            How can a person know everything at eighteen but nothing at twenty-two?\nAnd will you still want me\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("01_Album/02.txt"),
+            "Red\nRed\nLoving him is like driving a new Maserati\nDown a dead end street\n",
+        )
+        .unwrap();
+
+        let songs = load_songs(&LyricsSource::Directory(dir.clone())).unwrap();
+        assert_eq!((songs[0].album, songs[0].name), ("Red", "Nothing New"));
+        assert_eq!(songs[0].track, 26);
+        assert_eq!(
+            songs[0].metadata.release_date.as_deref(),
+            Some("2021-11-12")
+        );
+        assert_eq!(songs[0].metadata.featured_artists, ["Phoebe Bridgers"]);
+        assert_eq!(
+            songs[0].metadata.version_tags,
+            [VersionTag::TaylorsVersion, VersionTag::Vault]
+        );
+        assert_eq!(
+            songs[0].lines[0].text,
+            "How can a person know everything at eighteen but nothing at twenty-two?"
+        );
+        // without front matter, the track number comes from the file name
+        assert_eq!(songs[1].track, 2);
+
+        std::fs::write(
+            dir.join("01_Album/01.txt"),
+            "+++\nalbum = \"Red\"\ntitle = \"Nothing New\"\nversion_tags = [\"remastered\"]\n+++\nA line\n",
+        )
+        .unwrap();
+        let errors = load_songs(&LyricsSource::Directory(dir.clone())).unwrap_err();
+        assert_eq!(
+            (errors[0].line, errors[0].rule),
+            (Some(1), Rule::InvalidFrontMatter)
+        );
+        assert!(errors[0]
+            .message
+            .starts_with("unknown variant `remastered`"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn albums_are_ordered_by_release_date() {
+        let dir = temp_lyrics_dir("release-order");
+        for (dir_name, album, release_date) in [
+            ("01_Album", "Lover", Some("2019-08-23")),
+            ("02_Unreleased", "Unreleased", None),
+            ("03_Red", "Red", Some("2012-10-22")),
+        ] {
+            std::fs::create_dir_all(dir.join(dir_name)).unwrap();
+            std::fs::write(
+                dir.join(dir_name).join("01.txt"),
+                format!("{}\nSong\nA line\n", album),
+            )
+            .unwrap();
+            if let Some(release_date) = release_date {
+                std::fs::write(
+                    dir.join(dir_name).join(ALBUM_FILE),
+                    format!("release_date = {}\n", release_date),
+                )
+                .unwrap();
+            }
+        }
+
+        let (albums, _) = load_library(&LyricsSource::Directory(dir.clone())).unwrap();
+        let order: Vec<(&str, u32)> = albums
+            .iter()
+            .map(|album| (album.name.as_str(), album.ordinal))
+            .collect();
+        assert_eq!(order, [("Red", 1), ("Lover", 2), ("Unreleased", 3)]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reload_swaps_in_new_songs() {
+        let dir = temp_lyrics_dir("reload");
+        let song_file = dir.join("01_Album/01.txt");
+        let lyrics = "I knew you were trouble when you walked in\nSo shame on me now\n";
+        std::fs::write(
+            &song_file,
+            format!("Red\nI Knew You Were Trouble\n{}", lyrics),
+        )
+        .unwrap();
+
+        let source = LyricsSource::Directory(dir.clone());
+        let library = SongLibrary::load(source).unwrap();
+        let before = library.current();
+        assert_eq!(before[0].name, "I Knew You Were Trouble");
+
+        std::fs::write(&song_file, format!("Red\nTrouble\n{}", lyrics)).unwrap();
+        library.reload().unwrap();
+        assert_eq!(library.current()[0].name, "Trouble");
+        // snapshots taken before the reload are unaffected
+        assert_eq!(before[0].name, "I Knew You Were Trouble");
+
+        std::fs::write(&song_file, "Red\n").unwrap();
+        assert!(library.reload().is_err());
+        assert_eq!(library.current()[0].name, "Trouble");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,177 @@
+//! Checks lyrics files against the house rules of `new_lyrics_notes.md`, and optionally fixes them.
+//!
+//! The rules are:
+//! * `-in'` endings are spelled out, so `gettin'` becomes `getting`
+//! * `tryna` becomes `trying to`, and `'fore` becomes `before`
+//! * no character from [`INVALID_CHARS`], e.g. straight quotes only
+//! * no parentheses in lines which may be used as prompts
+//!
+//! All but the last can be fixed automatically. Fixes only rewrite the text of a line, so the `$<marker>`
+//! suffix of every line is kept as it is. Run with the `lyrics-lint [--fix]` subcommand.
+
+use crate::loader_v2::parse_song_file;
+use crate::lyrics_validator::{Diagnostic, Rule};
+use crate::song::{parse_markers, split_markers, INVALID_CHARS};
+use std::path::PathBuf;
+
+/// The `lyrics_data` directory of the source tree, which is linted unless another directory is given.
+pub fn default_lyrics_dir() -> PathBuf {
+    PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../lyrics_data"))
+}
+
+/// What to replace each of the [`INVALID_CHARS`] with.
+fn replacement_for(invalid_char: char) -> &'static str {
+    match invalid_char {
+        'е' => "e",
+        '”' | '“' => "\"",
+        '’' | '‘' => "'",
+        '\u{2005}' | '\u{205f}' => " ",
+        '\u{200b}' => "",
+        '—' | '–' => "-",
+        '…' => "...",
+        _ => unreachable!("no replacement for invalid character `{}`", invalid_char),
+    }
+}
+
+/// Replace every invalid character of `text`, reporting each one.
+fn fix_invalid_chars(text: &str, report: &mut impl FnMut(Rule, String)) -> String {
+    let mut fixed = String::with_capacity(text.len());
+    for c in text.chars() {
+        if INVALID_CHARS.contains(&c) {
+            let replacement = replacement_for(c);
+            report(
+                Rule::InvalidChar,
+                format!(
+                    "invalid character `{}` (U+{:04X}) should be `{}`",
+                    c, c as u32, replacement
+                ),
+            );
+            fixed.push_str(replacement);
+        } else {
+            fixed.push(c);
+        }
+    }
+    fixed
+}
+
+/// The spelling the house rules prefer for `word`, if it breaks one of them.
+/// A word is a run of letters and apostrophes, so that `'fore` and `gettin'` are whole words.
+fn fix_word(word: &str) -> Option<(Rule, String)> {
+    if let Some(stem) = word.strip_suffix("in'") {
+        if stem.chars().last().is_some_and(char::is_alphabetic) {
+            return Some((Rule::InApostrophe, format!("{}ing", stem)));
+        }
+    }
+    match word {
+        "tryna" => Some((Rule::Tryna, "trying to".to_owned())),
+        "Tryna" => Some((Rule::Tryna, "Trying to".to_owned())),
+        "'fore" => Some((Rule::Fore, "before".to_owned())),
+        "'Fore" => Some((Rule::Fore, "Before".to_owned())),
+        _ => None,
+    }
+}
+
+/// Respell every word of `text` which breaks a house rule, reporting each one.
+fn fix_words(text: &str, report: &mut impl FnMut(Rule, String)) -> String {
+    let is_word_char = |c: char| c.is_alphabetic() || c == '\'';
+    let mut fixed = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_word_char) {
+        let (before, from_word) = rest.split_at(start);
+        let end = from_word
+            .find(|c: char| !is_word_char(c))
+            .unwrap_or(from_word.len());
+        let (word, after) = from_word.split_at(end);
+        fixed.push_str(before);
+        match fix_word(word) {
+            Some((rule, replacement)) => {
+                report(rule, format!("`{}` should be `{}`", word, replacement));
+                fixed.push_str(&replacement);
+            }
+            None => fixed.push_str(word),
+        }
+        rest = after;
+    }
+    fixed.push_str(rest);
+    fixed
+}
+
+/// Check a song file against the house rules.
+/// Returns every violation found, and the contents of the file with every fixable violation fixed.
+pub fn lint_file(path: &str, file_contents: &str) -> (Vec<Diagnostic>, String) {
+    let mut diagnostics = Vec::new();
+    let mut fixed_lines = Vec::new();
+    let lyrics_start = parse_song_file(path, file_contents).map_or(3, |file| file.lyrics_start);
+    for (index, line) in file_contents.split('\n').enumerate() {
+        let mut report =
+            |rule, message| diagnostics.push(Diagnostic::new(path, Some(index + 1), rule, message));
+
+        // the album and song names, or the front matter, have no markers and may contain `$`
+        if index + 1 < lyrics_start {
+            fixed_lines.push(fix_invalid_chars(line, &mut report));
+            continue;
+        }
+        if line.trim_start().starts_with('[') {
+            fixed_lines.push(line.to_owned());
+            continue;
+        }
+
+        let (text, markers) = split_markers(line);
+        let fixed_text = fix_words(&fix_invalid_chars(text, &mut report), &mut report);
+
+        let (markers, _) = parse_markers(markers.unwrap_or_default());
+        if markers.is_empty() && text.contains(['(', ')']) {
+            report(
+                Rule::Parentheses,
+                "parentheses should be removed, or the line marked as a bad prompt".to_owned(),
+            );
+        }
+
+        fixed_lines.push(format!("{}{}", fixed_text, &line[text.len()..]));
+    }
+    (diagnostics, fixed_lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixes_keep_markers_intact() {
+        let contents = "Red\nI Knew You Were Trouble\n[Verse 1]\nOnce upon a time, a few mistakes ago\nI was standin' in your sights, tryna keep it cool\n'Fore you’re gone (gone)$<misc_bad too short>\nI’m drivin'\n";
+        let (diagnostics, fixed) = lint_file("a.txt", contents);
+        assert_eq!(
+            fixed,
+            "Red\nI Knew You Were Trouble\n[Verse 1]\nOnce upon a time, a few mistakes ago\nI was standing in your sights, trying to keep it cool\nBefore you're gone (gone)$<misc_bad too short>\nI'm driving\n"
+        );
+        let reported: Vec<(usize, Rule)> = diagnostics
+            .iter()
+            .map(|d| (d.line.unwrap(), d.rule))
+            .collect();
+        assert_eq!(
+            reported,
+            [
+                (5, Rule::InApostrophe),
+                (5, Rule::Tryna),
+                (6, Rule::InvalidChar),
+                (6, Rule::Fore),
+                (7, Rule::InvalidChar),
+                (7, Rule::InApostrophe),
+            ]
+        );
+
+        // only the parentheses of lines which may be used as prompts are left to fix by hand
+        let (diagnostics, _) = lint_file("a.txt", "Red\nRed\nLoving him (red)\n");
+        assert_eq!(diagnostics[0].rule, Rule::Parentheses);
+        assert_eq!(lint_file("a.txt", &fixed).0.len(), 0);
+    }
+
+    #[test]
+    fn embedded_lyrics_follow_the_house_rules() {
+        let (files, _) = crate::loader_v2::read_files(&crate::loader_v2::LyricsSource::Embedded);
+        for (path, contents) in &files.songs {
+            let (_, fixed) = lint_file(path, contents);
+            assert_eq!(&fixed, contents, "{} has fixable violations", path);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! Checks lyrics files for problems, without panicking on the first one.
+//!
+//! Every file is checked against every rule, so that a contributor gets one complete report of what needs
+//! fixing. The report is printed by the `validate-lyrics` subcommand, and the same checks guard
+//! [`load_library`](crate::loader_v2::load_library): a source with any [`Severity::Error`] is never loaded.
+
+use crate::loader_v2::{album_dir_name, parse_album_file, parse_song_file, LyricsFiles};
+use crate::song::{
+    calculate_is_exclamatory_heuristic, find_invalid_char, parse_markers, split_markers, Marker,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The file can't be loaded until the problem is fixed.
+    Error,
+    /// The file can be loaded, but is worth a second look.
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// The file or directory could not be read, or is not valid UTF-8.
+    UnreadableFile,
+    /// The front matter is not valid TOML, or has an unknown or malformed field.
+    InvalidFrontMatter,
+    /// Line 1 is empty or missing, or the front matter has no `album`.
+    MissingAlbum,
+    /// Line 2 is empty or missing, or the front matter has no `title`.
+    MissingTitle,
+    /// A lyric contains a character from [`INVALID_CHARS`](crate::song::INVALID_CHARS).
+    InvalidChar,
+    /// The text after the `$` of a lyric is not a list of known markers.
+    UnknownMarker,
+    /// A lyric is marked `<exclamatory>` but the heuristic disagrees, or the other way around.
+    ExclamatoryHeuristic,
+    /// The file has no lyrics, only section headers or blank lines.
+    EmptySong,
+    /// Another file has the same album and song name.
+    DuplicateSong,
+    /// Another song file in the same album directory names a different album.
+    MismatchedAlbum,
+    /// The `album.toml` of an album directory is not valid TOML, or has an unknown or malformed field.
+    InvalidAlbumFile,
+    /// A word ends in `-in'` rather than `-ing`. Checked by [`lyrics_lint`](crate::lyrics_lint).
+    InApostrophe,
+    /// `tryna` rather than `trying to`. Checked by [`lyrics_lint`](crate::lyrics_lint).
+    Tryna,
+    /// `'fore` rather than `before`. Checked by [`lyrics_lint`](crate::lyrics_lint).
+    Fore,
+    /// A lyric which may be used as a prompt contains parentheses. Checked by [`lyrics_lint`](crate::lyrics_lint).
+    Parentheses,
+}
+
+impl Rule {
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnreadableFile => "unreadable-file",
+            Rule::InvalidFrontMatter => "invalid-front-matter",
+            Rule::MissingAlbum => "missing-album",
+            Rule::MissingTitle => "missing-title",
+            Rule::InvalidChar => "invalid-char",
+            Rule::UnknownMarker => "unknown-marker",
+            Rule::ExclamatoryHeuristic => "exclamatory-heuristic",
+            Rule::EmptySong => "empty-song",
+            Rule::DuplicateSong => "duplicate-song",
+            Rule::MismatchedAlbum => "mismatched-album",
+            Rule::InvalidAlbumFile => "invalid-album-file",
+            Rule::InApostrophe => "in-apostrophe",
+            Rule::Tryna => "tryna",
+            Rule::Fore => "fore",
+            Rule::Parentheses => "parentheses",
+        }
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            Rule::ExclamatoryHeuristic
+            | Rule::InApostrophe
+            | Rule::Tryna
+            | Rule::Fore
+            | Rule::Parentheses => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// A problem found in a lyrics file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub path: String,
+    /// The 1-based line number of the problem, if it concerns a single line.
+    pub line: Option<usize>,
+    pub rule: Rule,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(path: &str, line: Option<usize>, rule: Rule, message: String) -> Self {
+        Diagnostic {
+            path: path.to_owned(),
+            line,
+            rule,
+            message,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.rule.severity()
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+}
+
+/// Formats as e.g. `lyrics_data/01_Taylor_Swift/01.txt:7: error[invalid-char]: ...`.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        let severity = match self.severity() {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, ": {}[{}]: {}", severity, self.rule.name(), self.message)
+    }
+}
+
+/// Check a single song file against every rule, except [`Rule::DuplicateSong`] which needs all files.
+pub fn validate_file(path: &str, file_contents: &str) -> Vec<Diagnostic> {
+    let file = match parse_song_file(path, file_contents) {
+        Ok(file) => file,
+        Err(diagnostic) => return vec![diagnostic],
+    };
+
+    let mut diagnostics = Vec::new();
+    let (album_line, title_line) = if file.has_front_matter {
+        (None, None)
+    } else {
+        (Some(1), Some(2))
+    };
+    if file.album.trim().is_empty() {
+        let message = if file.has_front_matter {
+            "front matter should contain `album`"
+        } else {
+            "line 1 should contain the album name"
+        };
+        diagnostics.push(Diagnostic::new(
+            path,
+            album_line,
+            Rule::MissingAlbum,
+            message.to_owned(),
+        ));
+    }
+    if file.name.trim().is_empty() {
+        let message = if file.has_front_matter {
+            "front matter should contain `title`"
+        } else {
+            "line 2 should contain the song name"
+        };
+        diagnostics.push(Diagnostic::new(
+            path,
+            title_line,
+            Rule::MissingTitle,
+            message.to_owned(),
+        ));
+    }
+
+    let mut num_lyric_lines = 0;
+    for (index, line) in file.lyrics.split('\n').enumerate() {
+        let line_num = Some(index + file.lyrics_start);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('[') {
+            continue;
+        }
+        num_lyric_lines += 1;
+
+        let (text, markers) = split_markers(line);
+        if let Some(invalid_char) = find_invalid_char(text) {
+            diagnostics.push(Diagnostic::new(
+                path,
+                line_num,
+                Rule::InvalidChar,
+                format!(
+                    "invalid character `{}` (U+{:04X})",
+                    invalid_char, invalid_char as u32
+                ),
+            ));
+        }
+
+        let (markers, problems) = parse_markers(markers.unwrap_or_default());
+        for problem in problems {
+            diagnostics.push(Diagnostic::new(
+                path,
+                line_num,
+                Rule::UnknownMarker,
+                problem,
+            ));
+        }
+
+        let is_exclamatory = markers.contains(&Marker::Exclamatory);
+        if is_exclamatory != calculate_is_exclamatory_heuristic(text) {
+            let message = if is_exclamatory {
+                "line is marked <exclamatory>, but the heuristic considers it a normal line"
+            } else {
+                "line is not marked <exclamatory>, but the heuristic considers it exclamatory"
+            };
+            diagnostics.push(Diagnostic::new(
+                path,
+                line_num,
+                Rule::ExclamatoryHeuristic,
+                message.to_owned(),
+            ));
+        }
+    }
+    if num_lyric_lines == 0 {
+        diagnostics.push(Diagnostic::new(
+            path,
+            None,
+            Rule::EmptySong,
+            "song has no lyrics".to_owned(),
+        ));
+    }
+
+    diagnostics
+}
+
+/// Check every song file and album file of a lyrics source.
+pub fn validate(files: &LyricsFiles) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (album_path, album_file) in &files.albums {
+        if let Some(contents) = album_file {
+            diagnostics.extend(parse_album_file(album_path, contents).err());
+        }
+    }
+    diagnostics.extend(validate_files(&files.songs));
+    diagnostics
+}
+
+/// Check every song file, given as (path, contents) pairs, including that no song appears twice and that the
+/// songs of each album directory name the same album.
+pub fn validate_files(files: &[(String, String)]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut seen = HashSet::new();
+    let mut album_names: HashMap<&str, String> = HashMap::new();
+    for (path, contents) in files {
+        diagnostics.extend(validate_file(path, contents));
+
+        let Ok(file) = parse_song_file(path, contents) else {
+            continue;
+        };
+        if !file.album.trim().is_empty()
+            && !file.name.trim().is_empty()
+            && !seen.insert((file.album.clone(), file.name.clone()))
+        {
+            diagnostics.push(Diagnostic::new(
+                path,
+                None,
+                Rule::DuplicateSong,
+                format!("duplicate song `{}` in album `{}`", file.name, file.album),
+            ));
+        }
+        if let Some(dir_name) = album_dir_name(path) {
+            let album_name = album_names.entry(dir_name).or_insert(file.album.clone());
+            if *album_name != file.album {
+                diagnostics.push(Diagnostic::new(
+                    path,
+                    None,
+                    Rule::MismatchedAlbum,
+                    format!(
+                        "album `{}` differs from album `{}` of the other songs in `{}`",
+                        file.album, album_name, dir_name
+                    ),
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader_v2::{read_files, LyricsSource};
+
+    #[test]
+    fn embedded_lyrics_have_no_errors() {
+        let (files, mut diagnostics) = read_files(&LyricsSource::Embedded);
+        diagnostics.extend(validate(&files));
+        let errors: Vec<String> = diagnostics
+            .iter()
+            .filter(|d| d.is_error())
+            .map(|d| d.to_string())
+            .collect();
+        assert!(errors.is_empty(), "{}", errors.join("\n"));
+    }
+
+    #[test]
+    fn every_problem_is_reported_with_its_line() {
+        let files = vec![
+            (
+                "a.txt".to_owned(),
+                "Album\n\n[Verse 1]\nWe’re happy, free, confused and lonely\nOh, oh, oh\nLonely at the same time$<exclamatory>\nSo shame on me now$<misc_bad>\nI knew you were trouble$<bad>\n".to_owned(),
+            ),
+            ("b.txt".to_owned(), "Red\nRed\n[Chorus]\n".to_owned()),
+            ("c.txt".to_owned(), "Red\nRed\nLoving him is red\n".to_owned()),
+        ];
+        let reported: Vec<String> = validate_files(&files)
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            reported,
+            [
+                "a.txt:2: error[missing-title]: line 2 should contain the song name",
+                "a.txt:4: error[invalid-char]: invalid character `’` (U+2019)",
+                "a.txt:5: warning[exclamatory-heuristic]: line is not marked <exclamatory>, but the heuristic considers it exclamatory",
+                "a.txt:6: warning[exclamatory-heuristic]: line is marked <exclamatory>, but the heuristic considers it a normal line",
+                "a.txt:8: error[unknown-marker]: unknown marker `<bad>`",
+                "b.txt: error[empty-song]: song has no lyrics",
+                "c.txt: error[duplicate-song]: duplicate song `Red` in album `Red`",
+            ]
+        );
+    }
+}
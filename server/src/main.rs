@@ -1,92 +1,1580 @@
+pub mod accounts;
+pub mod admin;
+pub mod album;
+pub mod daily;
 pub mod diff;
+pub mod error;
 pub mod feedback;
 pub mod game;
+pub mod game_rng;
+pub mod game_secret;
 pub mod guess_generating;
 pub mod history;
+pub mod legacy;
 pub mod lifelines;
 pub mod loader_v2;
+pub mod lyrics_lint;
+pub mod lyrics_validator;
+pub mod persistence;
+pub mod players;
+pub mod presets;
+pub mod reaper;
+pub mod revisions;
 pub mod rss;
+pub mod rules;
+pub mod selection;
 pub mod song;
+pub mod songlists;
 pub mod stats;
+pub mod storage;
 
+use crate::admin::AdminConfig;
+use crate::daily::DailyConfig;
+use crate::legacy::LegacyConfig;
+use crate::loader_v2::{LyricsSource, SongLibrary};
 use crate::rss::RecentVotesCache;
-use crate::song::Song;
-use crate::stats::{get_stats, StatsResponse};
+use crate::stats::StatsResponse;
+use crate::storage::{DynStorage, Storage};
 use dotenv::dotenv;
-use sqlx::mysql::MySqlPoolOptions;
+use game::GameState;
+use rocket::{Build, Rocket};
+use rocket_okapi::{openapi, openapi_get_routes};
 use std::collections::HashMap;
-
-use feedback::{downvote_line, get_feedback, upvote_line};
-use game::{
-    claim_game, game_lifelines, init_game, next_question, reduce_multiple_choice, take_guess,
-    GameState,
-};
-use history::line_history::get_line;
-use history::{get_game, get_games};
-use rss::{get_recent_feedback_rss, get_recent_votes_rss};
-use song::{get_all_songlists, get_song, get_song_list, get_song_list_with_id};
 use std::sync::{Arc, Mutex};
 
 #[macro_use]
 extern crate rocket;
 
+/// API endpoint to check that the server is up.
+#[openapi]
 #[get("/")]
 fn index() -> &'static str {
     "Hello, world!"
 }
 
-#[rocket::main]
-async fn main() -> Result<(), rocket::Error> {
-    std::env::set_var("RUST_BACKTRACE", "1");
-    dotenv().ok();
-    let db_user = std::env::var("DATABASE_USER").expect("DATABASE_USER must be set.");
-    let db_pw = std::env::var("DATABASE_PASSWORD").expect("DATABASE_PASSWORD must be set.");
-
-    let songs: Vec<Song> = loader_v2::load_songs_from_files();
-    let my_hashmap: HashMap<String, GameState> = HashMap::new();
-    let game_state = Arc::new(Mutex::new(my_hashmap));
+/// Build the rocket instance, with all routes mounted and all state managed.
+///
+/// An OpenAPI document describing every route is served from `/openapi.json`. The deprecated GET endpoints
+/// (see [`legacy`]) are mounted only if `legacy_config` allows them, and are not documented.
+fn build_rocket(
+    songs: SongLibrary,
+    storage: DynStorage,
+    game_state: Arc<Mutex<HashMap<String, GameState>>>,
+    admin_config: AdminConfig,
+    daily_config: DailyConfig,
+    legacy_config: LegacyConfig,
+) -> Rocket<Build> {
     let votes_cache = Arc::new(Mutex::new(RecentVotesCache::new()));
     let stats_cache: Arc<Mutex<Option<StatsResponse>>> = Default::default();
 
-    let database_url = format!("mysql://{}:{}@localhost:3306/mydb", db_user, db_pw);
-    println!("Connecting to MySql Database...");
-    let pool = MySqlPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await
-        .expect("Failed to connect to database");
-    println!("Connection established!");
-
     let rocket = rocket::build()
         .manage(game_state)
         .manage(songs)
-        .manage(pool)
+        .manage(storage)
         .manage(votes_cache)
         .manage(stats_cache)
-        .mount("/", routes![index])
-        .mount("/", routes![get_stats])
-        .mount("/", routes![get_song_list])
-        .mount("/", routes![get_song_list_with_id])
-        .mount("/", routes![get_all_songlists])
-        .mount("/", routes![get_song])
-        .mount("/", routes![init_game])
-        .mount("/", routes![game_lifelines])
-        .mount("/", routes![reduce_multiple_choice])
-        .mount("/", routes![next_question])
-        .mount("/", routes![claim_game])
-        .mount("/", routes![take_guess])
-        .mount("/", routes![get_games])
-        .mount("/", routes![get_game])
-        .mount("/", routes![get_line])
-        .mount("/", routes![upvote_line])
-        .mount("/", routes![downvote_line])
-        .mount("/", routes![get_feedback])
-        .mount("/", routes![get_recent_feedback_rss])
-        .mount("/", routes![get_recent_votes_rss])
-        .ignite()
-        .await?;
+        .manage(admin_config)
+        .manage(daily_config)
+        .mount(
+            "/",
+            openapi_get_routes![
+                index,
+                stats::get_stats,
+                players::get_player,
+                album::get_albums,
+                song::get_song_list,
+                song::get_all_songlists,
+                song::get_song,
+                songlists::get_songlists,
+                rules::get_rules,
+                accounts::register,
+                accounts::login,
+                accounts::logout,
+                accounts::get_account,
+                accounts::get_account_games,
+                accounts::link_game,
+                game::init_game,
+                game::init_game_from_songlist,
+                game::init_game_from_preset,
+                daily::start_daily_challenge,
+                presets::create_preset,
+                presets::get_preset,
+                game::game_lifelines,
+                game::reduce_multiple_choice,
+                game::next_question,
+                game::claim_game,
+                game::take_guess,
+                game::take_choice,
+                history::get_games,
+                history::get_game,
+                daily::get_daily_leaderboard,
+                history::line_history::get_line,
+                feedback::upvote_line,
+                feedback::downvote_line,
+                feedback::get_feedback,
+                rss::get_recent_feedback_rss,
+                rss::get_recent_votes_rss,
+                admin::reload_lyrics,
+                game::replay_game,
+                songlists::create_songlist,
+                songlists::update_songlist,
+            ],
+        )
+        .register(
+            "/",
+            catchers![
+                error::bad_request,
+                error::unauthorized,
+                error::not_found,
+                error::unprocessable_entity,
+                error::internal_server_error
+            ],
+        );
+    if legacy_config.get_endpoints {
+        rocket.mount("/", legacy::routes())
+    } else {
+        rocket
+    }
+}
+
+/// The `migrate` subcommand.
+///
+/// `migrate` applies all pending migrations to the MySQL database.
+/// `migrate --baseline <version>` marks every migration up to `<version>` as applied without running it,
+/// and then applies the rest.
+async fn run_migrate_command(args: &[String]) {
+    let storage = storage::connect_mysql_from_env().await;
+    match args {
+        [] => {}
+        [flag, version] if flag == "--baseline" => {
+            let version = version
+                .parse()
+                .expect("The baseline version must be a migration number");
+            let num_marked = storage
+                .baseline(version)
+                .await
+                .expect("Failed to mark migrations as applied");
+            println!("Marked {} migrations as applied", num_marked);
+        }
+        _ => panic!("Usage: migrate [--baseline <version>]"),
+    }
+    storage
+        .migrate()
+        .await
+        .expect("Failed to apply database migrations");
+    println!("All migrations applied");
+}
+
+/// Print every problem in the lyrics, and exit with status 1 if any of them is an error.
+fn run_validate_lyrics_command(args: &[String]) {
+    let lyrics_source = LyricsSource::from_args_and_env(args);
+    let (files, mut diagnostics) = loader_v2::read_files(&lyrics_source);
+    diagnostics.extend(lyrics_validator::validate(&files));
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+
+    let num_errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    println!(
+        "Checked {} files from {}: {} errors, {} warnings",
+        files.songs.len(),
+        lyrics_source,
+        num_errors,
+        diagnostics.len() - num_errors
+    );
+    if num_errors > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Print every violation of the house rules for lyrics, rewriting the files with fixable violations fixed
+/// if `--fix` is given. Lints the `lyrics_data` directory unless another directory is given.
+///
+/// Exits with status 1 if a file can't be read, or without `--fix`, if there is any violation.
+fn run_lyrics_lint_command(args: &[String]) {
+    let fix = args.iter().any(|arg| arg == "--fix");
+    let lyrics_source = match LyricsSource::from_args_and_env(args) {
+        LyricsSource::Embedded => LyricsSource::Directory(lyrics_lint::default_lyrics_dir()),
+        source => source,
+    };
+    let (files, unreadable) = loader_v2::read_files(&lyrics_source);
+    for diagnostic in &unreadable {
+        println!("{}", diagnostic);
+    }
+
+    let mut num_violations = 0;
+    let mut num_fixed_files = 0;
+    for (path, contents) in &files.songs {
+        let (mut diagnostics, fixed) = lyrics_lint::lint_file(path, contents);
+        if fix && &fixed != contents {
+            std::fs::write(path, &fixed).expect("Failed to write fixed lyrics file");
+            num_fixed_files += 1;
+            diagnostics = lyrics_lint::lint_file(path, &fixed).0;
+        }
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+        num_violations += diagnostics.len();
+    }
+
+    println!(
+        "Linted {} files from {}: {} violations",
+        files.songs.len(),
+        lyrics_source,
+        num_violations
+    );
+    if fix {
+        println!("Fixed {} files", num_fixed_files);
+    }
+    if !unreadable.is_empty() || (!fix && num_violations > 0) {
+        std::process::exit(1);
+    }
+}
+
+#[rocket::main]
+async fn main() -> Result<(), Box<rocket::Error>> {
+    std::env::set_var("RUST_BACKTRACE", "1");
+    dotenv().ok();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("migrate") {
+        run_migrate_command(&args[1..]).await;
+        return Ok(());
+    }
+    if args.first().map(String::as_str) == Some("lyrics-lint") {
+        run_lyrics_lint_command(&args[1..]);
+        return Ok(());
+    }
+    if args.first().map(String::as_str) == Some("validate-lyrics") {
+        run_validate_lyrics_command(&args[1..]);
+        return Ok(());
+    }
+
+    let lyrics_source = LyricsSource::from_args_and_env(&args);
+    println!("Loading songs from {}...", lyrics_source);
+    let songs = match SongLibrary::load(lyrics_source.clone()) {
+        Ok(songs) => songs,
+        Err(errors) => {
+            for error in &errors {
+                println!("{}", error);
+            }
+            panic!(
+                "Failed to load songs from {}, run `validate-lyrics` for a full report",
+                lyrics_source
+            );
+        }
+    };
+    let storage = storage::connect_from_env().await;
+    storage.migrate().await.expect(
+        "Failed to apply database migrations. If the schema of this database was created before \
+        migrations existed, mark the existing migrations as applied with `migrate --baseline <version>`",
+    );
+    let num_backfilled = storage
+        .backfill_line_ids(&|album, song_name, text| songs.line_id(album, song_name, text))
+        .await
+        .expect("Failed to backfill line ids");
+    if num_backfilled > 0 {
+        println!("Backfilled the line ids of {} rows", num_backfilled);
+    }
+    let num_converted = storage
+        .backfill_selections()
+        .await
+        .expect("Failed to convert the selected songs of games");
+    if num_converted > 0 {
+        println!("Converted the selected songs of {} games", num_converted);
+    }
+    let num_revised = revisions::record_revisions(&storage, &songs.current())
+        .await
+        .expect("Failed to record lyric revisions");
+    if num_revised > 0 {
+        println!("Recorded revisions of {} lines", num_revised);
+    }
+
+    let my_hashmap: HashMap<String, GameState> =
+        persistence::load_game_states(&storage, &songs.current()).await;
+    println!("Restored {} in-progress games", my_hashmap.len());
+    let game_state = Arc::new(Mutex::new(my_hashmap));
+
+    rocket::tokio::spawn(reaper::run(
+        storage.clone(),
+        game_state.clone(),
+        reaper::ttl_from_env(),
+    ));
+
+    let rocket = build_rocket(
+        songs,
+        storage,
+        game_state,
+        AdminConfig::from_env(),
+        DailyConfig::from_env(),
+        LegacyConfig::from_env(),
+    )
+    .ignite()
+    .await?;
 
     let _ = rocket.launch().await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_secret::SECRET_HEADER;
+    use crate::storage::memory::MemoryStorage;
+    use rocket::http::Header;
+    use rocket::http::{ContentType, RawStr, Status};
+    use rocket::local::asynchronous::{Client, LocalResponse};
+    use serde_json::Value;
+
+    const ADMIN_TOKEN: &str = "admin-token";
+
+    async fn client() -> Client {
+        client_with(LegacyConfig {
+            get_endpoints: false,
+        })
+        .await
+    }
+
+    async fn client_with(legacy_config: LegacyConfig) -> Client {
+        let songs = SongLibrary::load(LyricsSource::Embedded).unwrap();
+        let storage: DynStorage = Arc::new(MemoryStorage::new());
+        let admin_config = AdminConfig {
+            token: Some(ADMIN_TOKEN.to_owned()),
+        };
+        Client::tracked(build_rocket(
+            songs,
+            storage,
+            Default::default(),
+            admin_config,
+            DailyConfig {
+                seed_key: "daily-seed-key".to_owned(),
+            },
+            legacy_config,
+        ))
+        .await
+        .expect("valid rocket instance")
+    }
+
+    async fn get_json(client: &Client, uri: &str) -> Value {
+        let response = client.get(uri).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        serde_json::from_str(&response.into_string().await.unwrap()).unwrap()
+    }
+
+    /// Post a JSON body, with the secret of a game if it is given.
+    async fn post<'c>(
+        client: &'c Client,
+        uri: &str,
+        secret: Option<&str>,
+        body: Value,
+    ) -> LocalResponse<'c> {
+        let mut request = client
+            .post(uri.to_owned())
+            .header(ContentType::JSON)
+            .body(body.to_string());
+        if let Some(secret) = secret {
+            request = request.header(Header::new(SECRET_HEADER, secret.to_owned()));
+        }
+        request.dispatch().await
+    }
+
+    /// Start a game with every song.
+    async fn start_game(client: &Client) -> Value {
+        post_json(client, "/game/start", None, serde_json::json!([])).await
+    }
+
+    async fn post_json(client: &Client, uri: &str, secret: Option<&str>, body: Value) -> Value {
+        let response = post(client, uri, secret, body).await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        serde_json::from_str(&response.into_string().await.unwrap()).unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn full_game_is_recorded_in_history() {
+        let client = client().await;
+
+        let response = client
+            .post("/game/start")
+            .header(ContentType::JSON)
+            .body("[]")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let id = game["id"].as_str().unwrap().to_owned();
+
+        // skip the first question, and then answer the second one incorrectly
+        let skipped = post_json(
+            &client,
+            "/game/use-lifeline",
+            game["secret"].as_str(),
+            serde_json::json!({"id": id, "lifeline": "skip"}),
+        )
+        .await;
+        assert_eq!(skipped["completed_question"], true);
+        // the skipped question has been completed, so it can't be answered anymore
+        let response = post(
+            &client,
+            "/game/submit-guess",
+            game["secret"].as_str(),
+            serde_json::json!({"id": id, "guess": "too late"}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::Conflict);
+        post_json(
+            &client,
+            "/game/next",
+            game["secret"].as_str(),
+            serde_json::json!({ "id": id }),
+        )
+        .await;
+        multiple_choices(&client, std::slice::from_ref(&game)).await;
+        let result = post_json(
+            &client,
+            "/game/submit-guess",
+            game["secret"].as_str(),
+            serde_json::json!({"id": id, "guess": "not a choice"}),
+        )
+        .await;
+        assert!(result["guess_res"]["Incorrect"].is_object());
+        assert_eq!(result["game_state"]["terminated"], true);
+
+        let response = post(
+            &client,
+            "/game/claim",
+            game["secret"].as_str(),
+            serde_json::json!({"id": id, "name": "Tester"}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::NoContent);
+
+        let games = get_json(&client, "/history/all").await;
+        assert_eq!(games.as_array().unwrap().len(), 1);
+        assert_eq!(games[0]["player_name"], "Tester");
+        assert_eq!(games[0]["num_guesses"], 2);
+        assert_eq!(games[0]["end_reason"], "incorrect");
+
+        let details = get_json(&client, &format!("/history/game?id={}", id)).await;
+        let guesses = details["guesses"].as_array().unwrap();
+        assert_eq!(guesses[0]["result"], "skipped");
+        assert_eq!(guesses[1]["result"], "incorrect");
+
+        let encode = |s: &Value| {
+            RawStr::new(s.as_str().unwrap())
+                .percent_encode()
+                .to_string()
+        };
+        let line = get_json(
+            &client,
+            &format!(
+                "/history/line?album={}&song={}&prompt={}",
+                encode(&guesses[0]["album"]),
+                encode(&guesses[0]["song_name"]),
+                encode(&guesses[0]["prompt"])
+            ),
+        )
+        .await;
+        assert!(line["guesses"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|guess| guess["game_uuid"] == id.as_str()));
+    }
+
+    #[rocket::async_test]
+    async fn games_in_progress_are_restored_from_storage() {
+        let client = client().await;
+        let game = start_game(&client).await;
+        let secret = game["secret"].as_str();
+
+        // skip the first question, and leave the second one after using every other lifeline on it
+        for (uri, body) in [
+            (
+                "/game/use-lifeline",
+                serde_json::json!({"id": game["id"], "lifeline": "skip"}),
+            ),
+            ("/game/next", serde_json::json!({"id": game["id"]})),
+            (
+                "/game/use-lifeline",
+                serde_json::json!({"id": game["id"], "lifeline": "show_title_album"}),
+            ),
+            (
+                "/game/use-lifeline",
+                serde_json::json!({"id": game["id"], "lifeline": "show_prev_lines"}),
+            ),
+            (
+                "/game/reduce-multiple-choice",
+                serde_json::json!({"id": game["id"]}),
+            ),
+        ] {
+            post_json(&client, uri, secret, body).await;
+        }
+
+        // the state is saved on every change, so it is what a restarted server would load
+        let rocket = client.rocket();
+        let id = game["id"].as_str().unwrap();
+        let original = rocket
+            .state::<Arc<Mutex<HashMap<String, GameState>>>>()
+            .unwrap()
+            .lock()
+            .unwrap()[id]
+            .clone();
+        let storage = rocket.state::<DynStorage>().unwrap();
+        let songs = rocket.state::<SongLibrary>().unwrap().current();
+        let restored = persistence::load_game_states(storage, &songs).await;
+        assert_eq!(restored.len(), 1);
+
+        let persisted = original.to_persisted();
+        assert!(!persisted.choices.is_empty());
+        assert_eq!(persisted.hints_shown.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&persisted.lifeline_inv).unwrap(),
+            serde_json::to_value(lifelines::LifelineInventory::of_each(0)).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(restored[id].to_persisted()).unwrap(),
+            serde_json::to_value(persisted).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(restored[id].into_public(id.to_owned())).unwrap(),
+            serde_json::to_value(original.into_public(id.to_owned())).unwrap()
+        );
+    }
+
+    #[rocket::async_test]
+    async fn votes_and_feedback_are_stored() {
+        let client = client().await;
+
+        let response = post(
+            &client,
+            "/feedback/upvote_line",
+            None,
+            serde_json::json!({"album": "Red", "song_name": "Red", "line": "Loving him"}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::NoContent);
+        let line = get_json(
+            &client,
+            "/history/line?album=Red&song=Red&prompt=Loving%20him",
+        )
+        .await;
+        assert_eq!(line["num_upvotes"], 1);
+        assert_eq!(line["num_downvotes"], 0);
+
+        // lines are matched by id, so wildcards in a lyric match nothing else
+        let response = post(
+            &client,
+            "/feedback/downvote_line",
+            None,
+            serde_json::json!({"album": "Red", "song_name": "Red", "line": "Loving%"}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::NoContent);
+        let line = get_json(
+            &client,
+            "/history/line?album=Red&song=Red&prompt=Loving%20him",
+        )
+        .await;
+        assert_eq!(line["num_downvotes"], 0);
+
+        let response = client
+            .post("/feedback/general")
+            .header(ContentType::JSON)
+            .body(r#"{"album":"Red","song":"Red","lyric":"","message":"hi","contact":""}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+        let response = client
+            .get("/feedback/get_recent_feedback_rss")
+            .dispatch()
+            .await;
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("application", "atom+xml"))
+        );
+        let feed = response.into_string().await.unwrap();
+        assert!(feed.contains("Message:hi"));
+    }
+
+    #[rocket::async_test]
+    async fn errors_have_statuses_and_json_bodies() {
+        let client = client().await;
+
+        let cases = [
+            (
+                "/history/game?id=no-such-game",
+                Status::NotFound,
+                "not_found",
+            ),
+            ("/songs?id=12345", Status::NotFound, "not_found"),
+            ("/songs/Red/No%20Such%20Song", Status::NotFound, "not_found"),
+            ("/no/such/route", Status::NotFound, "not_found"),
+            (
+                "/history/all?sort=length",
+                Status::BadRequest,
+                "bad_request",
+            ),
+        ];
+        for (uri, status, error) in cases {
+            let response = client.get(uri).dispatch().await;
+            assert_eq!(response.status(), status, "{}", uri);
+            assert_eq!(response.content_type(), Some(ContentType::JSON), "{}", uri);
+            let body: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+            assert_eq!(body["error"], error, "{}", uri);
+        }
+
+        let response = client
+            .post("/game/start")
+            .header(ContentType::JSON)
+            .body("[]")
+            .dispatch()
+            .await;
+        let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let response = post(
+            &client,
+            "/game/use-lifeline",
+            game["secret"].as_str(),
+            serde_json::json!({"id": game["id"], "lifeline": "phone_a_friend"}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = post(
+            &client,
+            "/game/next",
+            None,
+            serde_json::json!({"id": "no-such-game"}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::NotFound);
+        // bodies which are not valid requests are rejected
+        let response = post(
+            &client,
+            "/game/next",
+            None,
+            serde_json::json!({"game": "no-such-game"}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[rocket::async_test]
+    async fn songs_are_listed_by_album_in_release_order() {
+        let client = client().await;
+
+        let albums = get_json(&client, "/albums").await;
+        let albums = albums.as_array().unwrap();
+        assert_eq!(albums[0]["id"], "taylor-swift");
+        assert_eq!(albums[0]["release_date"], "2006-10-24");
+        assert_eq!(albums[3]["name"], "Red");
+
+        let listings = get_json(&client, "/songs").await;
+        let listings = listings.as_array().unwrap();
+        assert_eq!(listings.len(), albums.len());
+        let ordinals: Vec<u64> = listings
+            .iter()
+            .map(|listing| listing["ordinal"].as_u64().unwrap())
+            .collect();
+        assert!(ordinals.windows(2).all(|pair| pair[0] < pair[1]));
+        let red = &listings[3];
+        assert_eq!(red["cover_art"], "red");
+        assert_eq!(red["songs"][3]["name"], "I Knew You Were Trouble");
+        assert_eq!(red["songs"][3]["track"], 4);
+    }
+
+    #[rocket::async_test]
+    async fn openapi_document_describes_every_route() {
+        let client = client().await;
+        let spec = get_json(&client, "/openapi.json").await;
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+
+        for route in client.rocket().routes() {
+            // `/songs/<album>/<name>` is documented as `/songs/{album}/{name}`
+            let path = route
+                .uri
+                .path()
+                .to_string()
+                .replace('<', "{")
+                .replace('>', "}");
+            if path == "/openapi.json" {
+                continue;
+            }
+            let method = route.method.as_str().to_lowercase();
+            assert!(
+                spec["paths"][&path][&method].is_object(),
+                "{} {} is not documented",
+                method,
+                path
+            );
+        }
+
+        let schemas = &spec["components"]["schemas"];
+        for name in [
+            "GameStatePublic",
+            "Hint",
+            "GuessResult",
+            "GuessResultPublic",
+            "LifelineInventory",
+            "Guess",
+            "Game",
+            "StatsResponse",
+            "ErrorBody",
+        ] {
+            assert!(schemas[name].is_object(), "schema {} is missing", name);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn reloading_lyrics_requires_the_admin_token() {
+        let client = client().await;
+
+        let response = client.post("/admin/reload-lyrics").dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .post("/admin/reload-lyrics")
+            .header(Header::new("Authorization", "Bearer wrong-token"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .post("/admin/reload-lyrics")
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", ADMIN_TOKEN),
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let result: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        assert_eq!(
+            result["num_songs"],
+            loader_v2::load_songs_from_files().len()
+        );
+    }
+
+    #[rocket::async_test]
+    async fn games_start_from_curated_songlists() {
+        let client = client().await;
+        let admin = Header::new("Authorization", format!("Bearer {}", ADMIN_TOKEN));
+
+        let body = r#"{"name":"Red","songs":[["Red","Red"],["Red","State Of Grace"]]}"#;
+        let response = client
+            .post("/admin/songlists")
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .post("/admin/songlists")
+            .header(ContentType::JSON)
+            .header(admin.clone())
+            .body(body)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let songlist: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let id = songlist["id"].as_i64().unwrap();
+
+        let cases = [
+            (
+                r#"{"name":"red","songs":[["Red","Red"]]}"#,
+                Status::Conflict,
+            ),
+            (
+                r#"{"name":"Other","songs":[["Red","No Such Song"]]}"#,
+                Status::BadRequest,
+            ),
+            (r#"{"name":"Other","songs":[]}"#, Status::BadRequest),
+        ];
+        for (body, status) in cases {
+            let response = client
+                .post("/admin/songlists")
+                .header(ContentType::JSON)
+                .header(admin.clone())
+                .body(body)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), status, "{}", body);
+        }
+
+        let response = client
+            .put(format!("/admin/songlists/{}", id))
+            .header(ContentType::JSON)
+            .header(admin.clone())
+            .body(r#"{"name":"Red (Short)","description":"Just one","songs":[["Red","Red"]]}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let songlists = get_json(&client, "/songlists").await;
+        assert_eq!(songlists.as_array().unwrap().len(), 1);
+        assert_eq!(songlists[0]["name"], "Red (Short)");
+        assert_eq!(songlists[0]["num_songs"], 1);
+
+        let response = client.post(format!("/game/start/{}", id)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let details = get_json(
+            &client,
+            &format!("/history/game?id={}", game["id"].as_str().unwrap()),
+        )
+        .await;
+        assert_eq!(
+            details["game"]["selected_songs"],
+            serde_json::json!([["Red", "Red"]])
+        );
+
+        // games are recorded against unnamed songlists, which can't be edited
+        let songlist_id = details["game"]["songlist_id"].as_i64().unwrap();
+        assert_ne!(songlist_id, id);
+
+        // the selection of a game starts another game with the same songs
+        let response = client
+            .post(format!(
+                "/game/start/{}?selection={}",
+                songlist_id,
+                details["game"]["selection"].as_str().unwrap()
+            ))
+            .dispatch()
+            .await;
+        let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let other_details = get_json(
+            &client,
+            &format!("/history/game?id={}", game["id"].as_str().unwrap()),
+        )
+        .await;
+        assert_eq!(
+            other_details["game"]["selected_songs"],
+            details["game"]["selected_songs"]
+        );
+        let response = client
+            .post(format!("/game/start/{}?selection=AQAFEg", songlist_id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client
+            .put(format!("/admin/songlists/{}", songlist_id))
+            .header(ContentType::JSON)
+            .header(admin)
+            .body(r#"{"name":"Everything","songs":[["Red","Red"]]}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Conflict);
+    }
+
+    #[rocket::async_test]
+    async fn games_start_from_presets() {
+        let client = client().await;
+
+        let response = client
+            .post("/presets?rules=casual")
+            .header(ContentType::JSON)
+            .body(r#"[["Red","Red"],["Red","State Of Grace"]]"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let preset: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let code = preset["code"].as_str().unwrap().to_owned();
+        assert_eq!(
+            get_json(&client, &format!("/presets/{}", code)).await,
+            preset
+        );
+
+        // codes are case-insensitive
+        let response = client
+            .post(format!("/game/start?preset={}", code.to_lowercase()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let details = get_json(
+            &client,
+            &format!("/history/game?id={}", game["id"].as_str().unwrap()),
+        )
+        .await;
+        assert_eq!(details["game"]["selected_songs"], preset["songs"]);
+        assert_eq!(details["game"]["rules"]["name"], "casual");
+
+        let response = client
+            .post("/presets")
+            .header(ContentType::JSON)
+            .body(r#"[["Red","No Such Song"]]"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client.post("/game/start?preset=NOPE22").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        // a preset of a song which has since been removed can't start a game
+        let storage = client.rocket().state::<DynStorage>().unwrap();
+        let content = vec![
+            ("Red".to_owned(), "Red".to_owned()),
+            ("Red".to_owned(), "Removed Song".to_owned()),
+        ];
+        let songlist_id = storage
+            .get_or_create_songlist("removed", &content)
+            .await
+            .unwrap();
+        storage
+            .insert_preset(&storage::Preset {
+                code: "GONE22".to_owned(),
+                songlist_id,
+                selection: selection::Selection::from_songs(
+                    &content,
+                    &[("Red", "Red"), ("Red", "Removed Song")],
+                )
+                .encode(),
+                rules: "classic".to_owned(),
+            })
+            .await
+            .unwrap();
+        let resolved = get_json(&client, "/presets/GONE22").await;
+        assert_eq!(resolved["songs"], serde_json::json!([["Red", "Red"]]));
+        assert_eq!(
+            resolved["missing_songs"],
+            serde_json::json!([["Red", "Removed Song"]])
+        );
+        let response = client.post("/game/start?preset=GONE22").dispatch().await;
+        assert_eq!(response.status(), Status::Conflict);
+        assert!(response
+            .into_string()
+            .await
+            .unwrap()
+            .contains("Removed Song"));
+
+        // starting a game from a list of songs still works
+        let response = client
+            .post("/game/start")
+            .header(ContentType::JSON)
+            .body("[]")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn daily_challenge_is_shared_and_played_once() {
+        let client = client().await;
+
+        let mut first_lines = Vec::new();
+        let mut games = Vec::new();
+        for name in ["Alice", "Bob"] {
+            let response = client
+                .post(format!("/daily/start?name={}", name))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+            let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+            first_lines.push(game["current_question"]["shown_line"].clone());
+            games.push(game);
+        }
+        // both players are asked the same question, and offered the same choices
+        assert_eq!(first_lines[0], first_lines[1]);
+        let choices: Vec<Value> = multiple_choices(&client, &games).await;
+        assert_eq!(choices[0], choices[1]);
+
+        // names are matched case-insensitively
+        let response = client.post("/daily/start?name=alice").dispatch().await;
+        assert_eq!(response.status(), Status::Conflict);
+        let response = client.post("/daily/start?name=%20").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        // of two attempts at once, only one is let through
+        let (first, second) = rocket::tokio::join!(
+            client.post("/daily/start?name=Carol").dispatch(),
+            client.post("/daily/start?name=carol").dispatch(),
+        );
+        let mut statuses = [first.status(), second.status()];
+        statuses.sort_by_key(|status| status.code);
+        assert_eq!(statuses, [Status::Ok, Status::Conflict]);
+
+        // Alice ends her game, and is the only one on the leaderboard
+        let result = post_json(
+            &client,
+            "/game/submit-guess",
+            games[0]["secret"].as_str(),
+            serde_json::json!({"id": games[0]["id"], "guess": "wrong"}),
+        )
+        .await;
+        assert_eq!(result["game_state"]["terminated"], true);
+        let leaderboard = get_json(&client, "/daily/leaderboard").await;
+        assert_eq!(leaderboard.as_array().unwrap().len(), 1);
+        assert_eq!(leaderboard[0]["player_name"], "Alice");
+        assert_eq!(leaderboard[0]["rank"], 1);
+        // the seed of the day's challenge is kept secret until the day is over, even from players who are done
+        let details = get_json(
+            &client,
+            &format!("/history/game?id={}", games[0]["id"].as_str().unwrap()),
+        )
+        .await;
+        assert_eq!(details["game"]["seed"], Value::Null);
+        assert_eq!(
+            get_json(&client, "/daily/leaderboard?date=2000-01-01").await,
+            serde_json::json!([])
+        );
+
+        // daily challenge games are named when they start
+        let response = post(
+            &client,
+            "/game/claim",
+            games[0]["secret"].as_str(),
+            serde_json::json!({"id": games[0]["id"], "name": "Mallory"}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::Conflict);
+        let uri = format!("/history/game?id={}", games[0]["id"].as_str().unwrap());
+        let details = get_json(&client, &uri).await;
+        assert_eq!(details["game"]["player_name"], "Alice");
+        assert!(details["game"]["daily_date"].is_string());
+    }
+
+    #[rocket::async_test]
+    async fn daily_leaderboard_pages_rank_ties_together() {
+        let client = client().await;
+        for name in ["Alice", "Bob", "Carol"] {
+            let uri = format!("/daily/start?name={}", name);
+            let response = client.post(uri).dispatch().await;
+            let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+            lose_game(&client, &game).await;
+        }
+
+        // every game scored 0, so the game on the second page shares the first rank
+        let page = get_json(&client, "/daily/leaderboard?limit=1&page_num=2").await;
+        assert_eq!(page.as_array().unwrap().len(), 1);
+        assert_eq!(page[0]["rank"], 1);
+        let page = get_json(&client, "/daily/leaderboard?limit=2&page_num=2").await;
+        assert_eq!(page.as_array().unwrap().len(), 1);
+
+        for uri in [
+            "/daily/leaderboard?limit=101".to_owned(),
+            format!("/daily/leaderboard?limit=100&page_num={}", usize::MAX),
+        ] {
+            let response = client.get(uri).dispatch().await;
+            assert_eq!(response.status(), Status::BadRequest);
+        }
+    }
+
+    /// Turn the current question of each game into multiple choice, and get its choices.
+    /// `games` are the responses which started the games.
+    async fn multiple_choices(client: &Client, games: &[Value]) -> Vec<Value> {
+        let mut choices = Vec::new();
+        for game in games {
+            let game = post_json(
+                client,
+                "/game/reduce-multiple-choice",
+                game["secret"].as_str(),
+                serde_json::json!({"id": game["id"]}),
+            )
+            .await;
+            choices.push(game["choices"].clone());
+        }
+        choices
+    }
+
+    /// End a game by answering its current question, as a multiple-choice question, with no choice.
+    async fn lose_game(client: &Client, game: &Value) {
+        multiple_choices(client, std::slice::from_ref(game)).await;
+        let guess = serde_json::json!({"id": game["id"], "guess": "not a choice"});
+        let result = post_json(client, "/game/submit-guess", game["secret"].as_str(), guess).await;
+        assert_eq!(result["game_state"]["terminated"], true);
+    }
+
+    #[rocket::async_test]
+    async fn games_are_replayed_from_their_seed() {
+        let client = client().await;
+        let admin = Header::new("Authorization", format!("Bearer {}", ADMIN_TOKEN));
+
+        let response = client
+            .post("/game/start")
+            .header(ContentType::JSON)
+            .body(r#"[["Red","Red"],["Red","State Of Grace"],["Lover","Lover"]]"#)
+            .dispatch()
+            .await;
+        let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let id = game["id"].as_str().unwrap().to_owned();
+        // the seed gives away every answer, so it is only listed once the game has ended
+        let details = get_json(&client, &format!("/history/game?id={}", id)).await;
+        assert_eq!(details["game"]["seed"], Value::Null);
+
+        let uri = format!("/admin/games/{}/replay", id);
+        let response = client.post(uri.clone()).dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client.post(uri).header(admin).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let replay: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let replay_id = replay["id"].as_str().unwrap().to_owned();
+        assert_ne!(replay_id, id);
+        assert_eq!(
+            replay["current_question"]["shown_line"],
+            game["current_question"]["shown_line"]
+        );
+        let choices = multiple_choices(&client, &[game.clone(), replay.clone()]).await;
+        assert_eq!(choices[0], choices[1]);
+
+        lose_game(&client, &game).await;
+        lose_game(&client, &replay).await;
+        let details = get_json(&client, &format!("/history/game?id={}", id)).await;
+        assert!(details["game"]["seed"]
+            .as_str()
+            .unwrap()
+            .parse::<u64>()
+            .is_ok());
+        let replay_details = get_json(&client, &format!("/history/game?id={}", replay_id)).await;
+        assert_eq!(replay_details["game"]["seed"], details["game"]["seed"]);
+        assert_eq!(
+            replay_details["game"]["selected_songs"],
+            details["game"]["selected_songs"]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn games_are_played_with_the_chosen_rules() {
+        let client = client().await;
+
+        let rules = get_json(&client, "/rules").await;
+        let names: Vec<&str> = rules
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|rules| rules["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["classic", "hard", "casual"]);
+
+        let response = client
+            .post("/game/start?rules=hard")
+            .header(ContentType::JSON)
+            .body("[]")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        assert_eq!(game["rules"]["name"], "hard");
+        assert_eq!(
+            game["lifeline_inv"],
+            serde_json::json!({"show_title_album": 0, "show_prev_lines": 0, "skip": 0})
+        );
+        // the hard rules have more choices
+        let id = game["id"].as_str().unwrap();
+        let choices = multiple_choices(&client, std::slice::from_ref(&game)).await;
+        assert_eq!(choices[0].as_array().unwrap().len(), 25);
+        let details = get_json(&client, &format!("/history/game?id={}", id)).await;
+        assert_eq!(details["game"]["rules"]["name"], "hard");
+
+        let response = client
+            .post("/game/start")
+            .header(ContentType::JSON)
+            .body("[]")
+            .dispatch()
+            .await;
+        let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        assert_eq!(game["rules"]["name"], "classic");
+
+        let response = client
+            .post("/game/start?rules=impossible")
+            .header(ContentType::JSON)
+            .body("[]")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[rocket::async_test]
+    async fn multiple_choice_questions_are_answered_by_index() {
+        let client = client().await;
+        let games = [start_game(&client).await, start_game(&client).await];
+
+        // only multiple-choice questions can be answered by index
+        let response = post(
+            &client,
+            "/game/submit-choice",
+            games[0]["secret"].as_str(),
+            serde_json::json!({"id": games[0]["id"], "choice": 0}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::Conflict);
+        let choices = multiple_choices(&client, &games).await;
+        let num_choices = choices[0].as_array().unwrap().len();
+        let response = post(
+            &client,
+            "/game/submit-choice",
+            games[0]["secret"].as_str(),
+            serde_json::json!({"id": games[0]["id"], "choice": num_choices}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let result = post_json(
+            &client,
+            "/game/submit-choice",
+            games[0]["secret"].as_str(),
+            serde_json::json!({"id": games[0]["id"], "choice": 1}),
+        )
+        .await;
+        let is_correct = result["guess_res"]["Correct"].is_object();
+        let guess_res = &result["guess_res"][if is_correct { "Correct" } else { "Incorrect" }];
+        assert_eq!(guess_res["user_guess"], choices[0][1]);
+        assert_eq!(is_correct, guess_res["answer"] == choices[0][1]);
+
+        // a choice sent as text is still matched when its case and spacing drift
+        let drifted = format!(
+            "  {} ",
+            choices[1][2]
+                .as_str()
+                .unwrap()
+                .to_lowercase()
+                .replace(' ', "  ")
+        );
+        let result = post_json(
+            &client,
+            "/game/submit-guess",
+            games[1]["secret"].as_str(),
+            serde_json::json!({"id": games[1]["id"], "guess": drifted}),
+        )
+        .await;
+        let is_correct = result["guess_res"]["Correct"].is_object();
+        let guess_res = &result["guess_res"][if is_correct { "Correct" } else { "Incorrect" }];
+        assert_eq!(guess_res["user_guess"], choices[1][2]);
+        assert_eq!(is_correct, guess_res["answer"] == choices[1][2]);
+
+        for (game, (choices, index)) in games.iter().zip([(&choices[0], 1), (&choices[1], 2)]) {
+            let uri = format!("/history/game?id={}", game["id"].as_str().unwrap());
+            let details = get_json(&client, &uri).await;
+            let guess = &details["guesses"][0];
+            assert_eq!(&guess["options"], choices);
+            assert_eq!(guess["selected_choice"], index);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn deprecated_get_endpoints_can_be_turned_off() {
+        let legacy_client = client_with(LegacyConfig {
+            get_endpoints: true,
+        })
+        .await;
+        let game = start_game(&legacy_client).await;
+        let id = game["id"].as_str().unwrap();
+        let secret = Header::new(SECRET_HEADER, game["secret"].as_str().unwrap().to_owned());
+        let response = legacy_client
+            .get(format!("/game/reduce-multiple-choice?id={}", id))
+            .header(secret.clone())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Deprecation"), Some("true"));
+        let response = legacy_client
+            .get(format!("/game/submit-guess?id={}&guess=wrong", id))
+            .header(secret.clone())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Deprecation"), Some("true"));
+        let response = legacy_client
+            .get(format!("/game/claim?id={}&name=Tester", id))
+            .header(secret)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+        let details = get_json(&legacy_client, &format!("/history/game?id={}", id)).await;
+        assert_eq!(details["game"]["player_name"], "Tester");
+
+        let client = client().await;
+        let game = start_game(&client).await;
+        let response = client
+            .get(format!(
+                "/game/submit-guess?id={}&guess=wrong",
+                game["id"].as_str().unwrap()
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+        // the deprecated endpoints are not documented
+        let spec = get_json(&client, "/openapi.json").await;
+        assert!(spec["paths"]["/game/submit-guess"]["get"].is_null());
+        assert!(spec["paths"]["/game/submit-guess"]["post"].is_object());
+        assert!(spec["paths"]["/game/reduce-multiple-choice"]["get"].is_null());
+    }
+
+    #[rocket::async_test]
+    async fn games_are_only_changed_with_their_secret() {
+        let client = client().await;
+        let game = start_game(&client).await;
+        let id = &game["id"];
+        let secret = game["secret"].as_str();
+        // the secret is only returned when the game starts
+        let details = get_json(
+            &client,
+            &format!("/history/game?id={}", id.as_str().unwrap()),
+        )
+        .await;
+        assert!(details["game"]["secret"].is_null());
+
+        let guess = serde_json::json!({"id": id, "guess": "not a choice"});
+        for wrong_secret in [None, Some("stolen")] {
+            let response = post(&client, "/game/submit-guess", wrong_secret, guess.clone()).await;
+            assert_eq!(response.status(), Status::Unauthorized);
+        }
+        let request = serde_json::json!({ "id": id });
+        let response = post(&client, "/game/reduce-multiple-choice", None, request).await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        multiple_choices(&client, std::slice::from_ref(&game)).await;
+        let result = post_json(&client, "/game/submit-guess", secret, guess).await;
+        assert_eq!(result["game_state"]["terminated"], true);
+        assert!(result["game_state"]["secret"].is_null());
+
+        let claim = |name: &str| serde_json::json!({"id": id, "name": name});
+        let response = post(&client, "/game/claim", None, claim("Mallory")).await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = post(&client, "/game/claim", secret, claim("Tester")).await;
+        assert_eq!(response.status(), Status::NoContent);
+        // a game can only be claimed once, even by its player
+        let response = post(&client, "/game/claim", secret, claim("Someone Else")).await;
+        assert_eq!(response.status(), Status::Conflict);
+        let details = get_json(
+            &client,
+            &format!("/history/game?id={}", id.as_str().unwrap()),
+        )
+        .await;
+        assert_eq!(details["game"]["player_name"], "Tester");
+    }
+
+    #[rocket::async_test]
+    async fn accounts_link_games_to_their_player() {
+        let client = client().await;
+        let credentials =
+            |name: &str, password: &str| serde_json::json!({"name": name, "password": password});
+        let session = |token: &str| Header::new("Authorization", format!("Bearer {}", token));
+
+        let response = post(
+            &client,
+            "/accounts/register",
+            None,
+            credentials("Al", "short"),
+        )
+        .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        post_json(
+            &client,
+            "/accounts/register",
+            None,
+            credentials("Alice", "correct horse"),
+        )
+        .await;
+        let response = post(
+            &client,
+            "/accounts/register",
+            None,
+            credentials("alice", "battery staple"),
+        )
+        .await;
+        assert_eq!(response.status(), Status::Conflict);
+        let (first, second) = rocket::tokio::join!(
+            post(
+                &client,
+                "/accounts/register",
+                None,
+                credentials("Bob", "correct horse")
+            ),
+            post(
+                &client,
+                "/accounts/register",
+                None,
+                credentials("bob", "correct horse")
+            ),
+        );
+        let mut statuses = [first.status(), second.status()];
+        statuses.sort_by_key(|status| status.code);
+        assert_eq!(statuses, [Status::Ok, Status::Conflict]);
+        let response = post(
+            &client,
+            "/accounts/login",
+            None,
+            credentials("Nobody", "correct horse"),
+        )
+        .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = post(
+            &client,
+            "/accounts/login",
+            None,
+            credentials("Alice", "battery staple"),
+        )
+        .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let login = post_json(
+            &client,
+            "/accounts/login",
+            None,
+            credentials("alice", "correct horse"),
+        )
+        .await;
+        assert_eq!(login["name"], "Alice");
+        let token = login["token"].as_str().unwrap();
+
+        // a game started with a session is named after the account
+        let response = client
+            .post("/game/start")
+            .header(ContentType::JSON)
+            .header(session(token))
+            .body("[]")
+            .dispatch()
+            .await;
+        let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        lose_game(&client, &game).await;
+
+        // a game played without one can't be claimed with the name of the account, but can be linked to it
+        let game = start_game(&client).await;
+        lose_game(&client, &game).await;
+        let claim = serde_json::json!({"id": game["id"], "name": "ALICE"});
+        let response = post(&client, "/game/claim", game["secret"].as_str(), claim).await;
+        assert_eq!(response.status(), Status::Conflict);
+        let link = serde_json::json!({"id": game["id"]});
+        let response = post(&client, "/accounts/games", None, link.clone()).await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .post("/accounts/games")
+            .header(ContentType::JSON)
+            .header(session(token))
+            .header(Header::new(
+                SECRET_HEADER,
+                game["secret"].as_str().unwrap().to_owned(),
+            ))
+            .body(link.to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+
+        // a third game, played and claimed anonymously
+        let game = start_game(&client).await;
+        lose_game(&client, &game).await;
+        let claim = serde_json::json!({"id": game["id"], "name": "Bob"});
+        post(&client, "/game/claim", game["secret"].as_str(), claim).await;
+
+        let response = client
+            .get("/accounts/me/games")
+            .header(session(token))
+            .dispatch()
+            .await;
+        let games: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let games = games.as_array().unwrap();
+        assert_eq!(games.len(), 2);
+        assert!(games
+            .iter()
+            .all(|game| game["player_name"] == "Alice" && game["verified"] == true));
+        let games = get_json(&client, "/history/all").await;
+        assert_eq!(games.as_array().unwrap().len(), 3);
+        let games = get_json(&client, "/history/all?verified=true").await;
+        assert_eq!(games.as_array().unwrap().len(), 2);
+
+        let response = client.post("/daily/start?name=alice").dispatch().await;
+        assert_eq!(response.status(), Status::Conflict);
+        let response = client
+            .post("/daily/start")
+            .header(session(token))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let leaderboard = get_json(&client, "/daily/leaderboard?verified=true").await;
+        assert!(leaderboard.as_array().unwrap().is_empty());
+
+        // of two daily challenge games linked to an account at once, only one is linked
+        let carol = post_json(
+            &client,
+            "/accounts/register",
+            None,
+            credentials("Carol", "correct horse"),
+        )
+        .await;
+        let mut links = Vec::new();
+        for name in ["Dave", "Erin"] {
+            let response = client
+                .post(format!("/daily/start?name={}", name))
+                .dispatch()
+                .await;
+            let game: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+            links.push(
+                client
+                    .post("/accounts/games")
+                    .header(ContentType::JSON)
+                    .header(session(carol["token"].as_str().unwrap()))
+                    .header(Header::new(
+                        SECRET_HEADER,
+                        game["secret"].as_str().unwrap().to_owned(),
+                    ))
+                    .body(serde_json::json!({"id": game["id"]}).to_string()),
+            );
+        }
+        let second = links.pop().unwrap();
+        let first = links.pop().unwrap();
+        let (first, second) = rocket::tokio::join!(first.dispatch(), second.dispatch());
+        let mut statuses = [first.status(), second.status()];
+        statuses.sort_by_key(|status| status.code);
+        assert_eq!(statuses, [Status::NoContent, Status::Conflict]);
+
+        // the profile of an account is about its games only
+        let profile = get_json(&client, "/players/alice").await;
+        assert_eq!(profile["player_name"], "Alice");
+        assert_eq!(profile["verified"], true);
+        assert_eq!(profile["num_games"], 2);
+
+        let response = client
+            .post("/accounts/logout")
+            .header(session(token))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+        let response = client
+            .get("/accounts/me")
+            .header(session(token))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[rocket::async_test]
+    async fn player_profiles_are_computed_from_their_games() {
+        let client = client().await;
+        let response = client.get("/players/Tester").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        for _ in 0..3 {
+            let game = start_game(&client).await;
+            lose_game(&client, &game).await;
+            let claim = serde_json::json!({"id": game["id"], "name": "Tester"});
+            post(&client, "/game/claim", game["secret"].as_str(), claim).await;
+        }
+        // an unclaimed game isn't anyone's
+        let game = start_game(&client).await;
+        lose_game(&client, &game).await;
+
+        let profile = get_json(&client, "/players/tester").await;
+        assert_eq!(profile["verified"], false);
+        assert_eq!(profile["num_games"], 3);
+        assert_eq!(profile["num_guesses"], 3);
+        assert_eq!(profile["best_score"], 0);
+        assert_eq!(profile["median_score"], 0.0);
+        // every guess was a wrong multiple-choice answer
+        assert!(profile["perfect_match_rate"].is_null());
+        let albums = profile["albums"].as_array().unwrap();
+        let num_answered: u64 = albums
+            .iter()
+            .map(|album| album["num_answered"].as_u64().unwrap())
+            .sum();
+        assert_eq!(num_answered, 3);
+        assert!(albums.iter().all(|album| album["accuracy"] == 0.0));
+        let num_misses: u64 = profile["most_missed_lines"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|line| line["num_misses"].as_u64().unwrap())
+            .sum();
+        assert_eq!(num_misses, 3);
+        assert_eq!(profile["lifelines"]["num_earned"], 0);
+        let trend = profile["score_trend"].as_array().unwrap();
+        assert_eq!(trend.len(), 1);
+        assert_eq!(trend[0]["num_games"], 3);
+    }
+}
@@ -0,0 +1,95 @@
+//! Persists the state of in-progress games to the database, so that running games survive
+//! a server restart (for example, when a fix is deployed).
+//!
+//! Every transition of a [`GameState`] in `game.rs` is written through to storage (the `active_games` table),
+//! and the saved states are read back into memory when the server starts. Once a game ends, its row is deleted,
+//! since finished games are fully described by the `games` and `guesses` tables.
+
+use crate::game::{GameState, Hint};
+use crate::lifelines::LifelineInventory;
+use crate::rules::GameRules;
+use crate::song::Song;
+use crate::storage::DynStorage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A [`GameState`] in a form that can be stored in the database.
+///
+/// A [`GameState`] borrows its strings from the loaded songs, so the persisted form holds owned copies
+/// and only refers to the question's song by its album and name.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PersistedGameState {
+    pub score: i32,
+    pub guesses_made: i32,
+    pub current_question: PersistedQuestion,
+    pub lifeline_inv: LifelineInventory,
+    pub hints_shown: Vec<Hint>,
+    pub choices: Vec<String>,
+    pub terminated: bool,
+    pub completed_question: bool,
+    pub included_songs: Vec<(String, String)>,
+    /// States saved before games had seeds have none.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// States saved before rules could be chosen were played with the classic rules.
+    #[serde(default)]
+    pub rules: GameRules,
+    /// States saved before games had secrets have none.
+    #[serde(default)]
+    pub secret_hash: Option<String>,
+}
+
+/// The persisted form of a [`crate::guess_generating::Question`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PersistedQuestion {
+    pub album: String,
+    pub song_name: String,
+    pub shown_line: String,
+    pub answers: Vec<String>,
+}
+
+/// Writes the current state of the game with uuid `id` to storage,
+/// replacing any previously saved state.
+pub async fn save_game_state(storage: &DynStorage, id: &str, game_state: &GameState) {
+    if let Err(e) = storage
+        .save_game_state(id, &game_state.to_persisted())
+        .await
+    {
+        println!("Warning, failed to persist the state of game {}: {}", id, e);
+    }
+}
+
+/// Removes the saved state of the game with uuid `id`. Called once a game has ended.
+pub async fn delete_game_state(storage: &DynStorage, id: &str) {
+    if let Err(e) = storage.delete_game_state(id).await {
+        println!(
+            "Warning, failed to delete the saved state of game {}: {}",
+            id, e
+        );
+    }
+}
+
+/// Loads the states of all in-progress games from storage.
+///
+/// Games which can no longer be resumed (because the song of the current question was removed
+/// from the lyrics data) are skipped and left in storage.
+pub async fn load_game_states(storage: &DynStorage, songs: &[Song]) -> HashMap<String, GameState> {
+    let rows = storage
+        .get_game_states()
+        .await
+        .expect("Failed to load in-progress games from storage");
+
+    let mut game_states = HashMap::new();
+    for (uuid, state) in rows {
+        match GameState::from_persisted(state, songs) {
+            Some(game_state) => {
+                game_states.insert(uuid, game_state);
+            }
+            None => {
+                println!("Warning, could not restore the state of game {}", uuid);
+            }
+        }
+    }
+
+    game_states
+}
@@ -0,0 +1,384 @@
+//! Player profiles: statistics about the finished games of one player.
+//!
+//! A profile is about the games of an account if there is an account with the player's name (see
+//! [`accounts`](crate::accounts)), and about every game claimed with the name otherwise. It is computed from the
+//! games and guesses on every request, like the monthly report of `report_generator`.
+
+use crate::error::{ApiError, ApiResult};
+use crate::guess_generating::optimal_truncated_dist;
+use crate::history::GameSchema;
+use crate::loader_v2::SongLibrary;
+use crate::revisions::line_ids_with_history;
+use crate::song::Song;
+use crate::storage::{DynStorage, GuessRecord, LineRevision, PlayerKey};
+use rocket::serde::json::Json;
+use rocket::time::format_description;
+use rocket::State;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// The number of lines listed in [`PlayerProfile::most_missed_lines`].
+const NUM_MOST_MISSED_LINES: usize = 10;
+
+/// Statistics about the finished games of a player.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PlayerProfile {
+    player_name: String,
+    /// Whether the profile is about the games of an account, rather than games claimed with a typed-in name.
+    verified: bool,
+    num_games: usize,
+    num_guesses: usize,
+    best_score: i32,
+    median_score: f64,
+    mean_score: f64,
+    /// The share of free-response guesses, other than skips, which matched a line exactly (ignoring case and
+    /// punctuation), or `None` if the player made none.
+    perfect_match_rate: Option<f64>,
+    /// The accuracy of the player on the questions of each album which they answered, most answered first.
+    albums: Vec<AlbumAccuracy>,
+    /// The lines that the player answered wrong most often, most missed first.
+    most_missed_lines: Vec<MissedLine>,
+    lifelines: LifelineUsage,
+    /// The scores of the player's games in each month that they played, oldest first.
+    score_trend: Vec<MonthlyScores>,
+}
+
+/// The accuracy of a player on the questions of an album. Skipped questions are left out.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AlbumAccuracy {
+    album: String,
+    num_answered: usize,
+    num_correct: usize,
+    /// `num_correct / num_answered`.
+    accuracy: f64,
+}
+
+/// A line that a player answered wrong, with its current text if it has been corrected since.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MissedLine {
+    album: String,
+    song_name: String,
+    prompt: String,
+    correct_answer: String,
+    num_misses: usize,
+}
+
+/// The lifelines that a player earned and used.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct LifelineUsage {
+    num_earned: usize,
+    /// The number of times that the player used each lifeline, e.g. `skip`.
+    num_used: BTreeMap<String, usize>,
+}
+
+/// The scores of a player's games which started in a month.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MonthlyScores {
+    /// e.g. `"2024-03"`.
+    month: String,
+    num_games: usize,
+    best_score: i32,
+    mean_score: f64,
+}
+
+/// The median of `values`, which must not be empty.
+fn median(values: &mut [i32]) -> f64 {
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[middle] as f64
+    } else {
+        (values[middle - 1] + values[middle]) as f64 / 2.0
+    }
+}
+
+fn mean(values: &[i32]) -> f64 {
+    values.iter().sum::<i32>() as f64 / values.len() as f64
+}
+
+/// The id and text of the current version of each line of the songs which `guesses` were made on, by the id of the
+/// line and of each of its earlier versions (see [`line_ids_with_history`]).
+fn current_lines(
+    songs: &[Song],
+    revisions: &[LineRevision],
+    guesses: &[&GuessRecord],
+) -> HashMap<String, (String, &'static str)> {
+    let guessed_songs: HashSet<(&str, &str)> = guesses
+        .iter()
+        .map(|guess| (guess.album.as_str(), guess.song_name.as_str()))
+        .collect();
+    let mut current_lines = HashMap::new();
+    for song in songs
+        .iter()
+        .filter(|song| guessed_songs.contains(&(song.album, song.name)))
+    {
+        for &text in &song.lines_unique {
+            let line_id = song.line_id_of(text);
+            for earlier_line_id in line_ids_with_history(revisions, &line_id) {
+                current_lines.insert(earlier_line_id, (line_id.clone(), text));
+            }
+        }
+    }
+    current_lines
+}
+
+/// The lines of the `answered` guesses that were answered wrong most often, most missed first.
+/// Guesses on earlier versions of a line are counted with the line, whose current text is listed.
+fn most_missed_lines(
+    answered: &[&GuessRecord],
+    current_lines: &HashMap<String, (String, &'static str)>,
+) -> Vec<MissedLine> {
+    let mut misses: HashMap<&str, MissedLine> = HashMap::new();
+    for guess in answered.iter().filter(|guess| guess.result == "incorrect") {
+        let current_line = current_lines.get(&guess.line_id);
+        let line_id = current_line.map_or(guess.line_id.as_str(), |(line_id, _)| line_id.as_str());
+        misses
+            .entry(line_id)
+            .or_insert_with(|| MissedLine {
+                album: guess.album.clone(),
+                song_name: guess.song_name.clone(),
+                prompt: current_line
+                    .map_or_else(|| guess.prompt.clone(), |(_, text)| text.to_string()),
+                correct_answer: guess.correct_answer.clone(),
+                num_misses: 0,
+            })
+            .num_misses += 1;
+    }
+    let mut most_missed_lines: Vec<MissedLine> = misses.into_values().collect();
+    most_missed_lines.sort_by(|a, b| {
+        b.num_misses.cmp(&a.num_misses).then_with(|| {
+            (&a.album, &a.song_name, &a.prompt).cmp(&(&b.album, &b.song_name, &b.prompt))
+        })
+    });
+    most_missed_lines.truncate(NUM_MOST_MISSED_LINES);
+    most_missed_lines
+}
+
+impl PlayerProfile {
+    /// Compute the profile of a player from their finished games, oldest first, and the guesses of those games.
+    /// There must be at least one game. `current_lines` is the current version of each guessed line (see
+    /// [`current_lines`]).
+    fn compute(
+        player_name: String,
+        verified: bool,
+        games: &[GameSchema],
+        guesses: &[GuessRecord],
+        current_lines: &HashMap<String, (String, &'static str)>,
+    ) -> Self {
+        let mut scores: Vec<i32> = games
+            .iter()
+            .map(|game| game.terminal_score.unwrap_or_default())
+            .collect();
+        let best_score = scores.iter().copied().max().unwrap_or_default();
+        let mean_score = mean(&scores);
+        let median_score = median(&mut scores);
+
+        let answered: Vec<&GuessRecord> = guesses
+            .iter()
+            .filter(|guess| guess.result != "skipped")
+            .collect();
+
+        let free_responses: Vec<&GuessRecord> = answered
+            .iter()
+            .copied()
+            .filter(|guess| guess.options.is_empty())
+            .collect();
+        let num_perfect_matches = free_responses
+            .iter()
+            .filter(|guess| {
+                guess.result == "correct"
+                    && optimal_truncated_dist(&guess.user_guess, &guess.correct_answer).1 == 0
+            })
+            .count();
+        let perfect_match_rate = (!free_responses.is_empty())
+            .then(|| num_perfect_matches as f64 / free_responses.len() as f64);
+
+        let mut albums: Vec<AlbumAccuracy> = Vec::new();
+        for guess in &answered {
+            let index = match albums.iter().position(|album| album.album == guess.album) {
+                Some(index) => index,
+                None => {
+                    albums.push(AlbumAccuracy {
+                        album: guess.album.clone(),
+                        num_answered: 0,
+                        num_correct: 0,
+                        accuracy: 0.0,
+                    });
+                    albums.len() - 1
+                }
+            };
+            albums[index].num_answered += 1;
+            if guess.result == "correct" {
+                albums[index].num_correct += 1;
+            }
+        }
+        for album in &mut albums {
+            album.accuracy = album.num_correct as f64 / album.num_answered as f64;
+        }
+        albums.sort_by(|a, b| {
+            b.num_answered
+                .cmp(&a.num_answered)
+                .then_with(|| a.album.cmp(&b.album))
+        });
+
+        let most_missed_lines = most_missed_lines(&answered, current_lines);
+
+        let mut num_used = BTreeMap::new();
+        for lifeline in guesses.iter().flat_map(|guess| &guess.lifelines_used) {
+            *num_used.entry(lifeline.clone()).or_insert(0) += 1;
+        }
+        let lifelines = LifelineUsage {
+            num_earned: guesses
+                .iter()
+                .filter(|guess| guess.lifeline_earned.is_some())
+                .count(),
+            num_used,
+        };
+
+        let month_format = format_description::parse("[year]-[month]").unwrap();
+        let mut monthly_scores: Vec<(String, Vec<i32>)> = Vec::new();
+        for game in games {
+            let month = game.start_time.format(&month_format).unwrap();
+            let score = game.terminal_score.unwrap_or_default();
+            match monthly_scores.last_mut() {
+                Some((last_month, scores)) if *last_month == month => scores.push(score),
+                _ => monthly_scores.push((month, vec![score])),
+            }
+        }
+        let score_trend = monthly_scores
+            .into_iter()
+            .map(|(month, scores)| MonthlyScores {
+                month,
+                num_games: scores.len(),
+                best_score: scores.iter().copied().max().unwrap_or_default(),
+                mean_score: mean(&scores),
+            })
+            .collect();
+
+        PlayerProfile {
+            player_name,
+            verified,
+            num_games: games.len(),
+            num_guesses: guesses.len(),
+            best_score,
+            median_score,
+            mean_score,
+            perfect_match_rate,
+            albums,
+            most_missed_lines,
+            lifelines,
+            score_trend,
+        }
+    }
+}
+
+/// API endpoint to get the statistics of the player `name`, over their finished games.
+///
+/// If `name` is the name of an account, the statistics are about the games of the account. Otherwise, they are
+/// about the games claimed with the name, ignoring case.
+#[openapi(tag = "Stats")]
+#[get("/players/<name>")]
+pub async fn get_player(
+    storage: &State<DynStorage>,
+    songs: &State<SongLibrary>,
+    name: &str,
+) -> ApiResult<Json<PlayerProfile>> {
+    let (player, player_name, verified) = match storage.get_account(name).await? {
+        Some(account) => (PlayerKey::Account(account.id), account.name, true),
+        None => (PlayerKey::Name(name.to_owned()), name.to_owned(), false),
+    };
+    let games = storage.get_player_games(&player).await?;
+    if games.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "no finished games of player `{}`",
+            name
+        )));
+    }
+    let guesses = storage.get_player_guesses(&player).await?;
+    let misses: Vec<&GuessRecord> = guesses
+        .iter()
+        .filter(|guess| guess.result == "incorrect")
+        .collect();
+    let revisions = storage.get_line_revisions().await?;
+    let current_lines = current_lines(&songs.current(), &revisions, &misses);
+
+    Ok(Json(PlayerProfile::compute(
+        player_name,
+        verified,
+        &games,
+        &guesses,
+        &current_lines,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::song::SongMetadata;
+    use crate::storage::now;
+
+    fn miss(line_id: &str, prompt: &str) -> GuessRecord {
+        GuessRecord {
+            game_uuid: "game".to_owned(),
+            order_num: 0,
+            album: "Red".to_owned(),
+            song_name: "All Too Well".to_owned(),
+            line_id: line_id.to_owned(),
+            prompt: prompt.to_owned(),
+            correct_answer: "But something 'bout it felt like home somehow".to_owned(),
+            result: "incorrect".to_owned(),
+            user_guess: "And I remember it all too well".to_owned(),
+            points_earned: 0,
+            lifeline_earned: None,
+            lifelines_used: vec![],
+            options: vec![],
+            selected_choice: None,
+            submit_time: now(),
+            player_name: None,
+        }
+    }
+
+    #[test]
+    fn medians_of_even_and_odd_lengths() {
+        assert_eq!(median(&mut [3, 1, 2]), 2.0);
+        assert_eq!(median(&mut [4, 1, 3, 2]), 2.5);
+        assert_eq!(median(&mut [7]), 7.0);
+    }
+
+    #[test]
+    fn misses_of_earlier_versions_of_a_line_are_counted_with_it() {
+        let song = Song::new(
+            "red/all-too-well".to_owned(),
+            "Red",
+            "All Too Well",
+            5,
+            "I walked through the door with you\nThe air was cold\nBut something 'bout it felt like home somehow\n",
+            SongMetadata::default(),
+        );
+        let line_id = song.line_id_of("The air was cold");
+        let revisions = [LineRevision {
+            old_line_id: "earlier-line-id".to_owned(),
+            new_line_id: line_id.clone(),
+            old_text: "The air was freezing".to_owned(),
+            new_text: "The air was cold".to_owned(),
+        }];
+        let guesses = [
+            miss("earlier-line-id", "The air was freezing"),
+            miss(&line_id, "The air was cold"),
+            miss(
+                &song.line_id_of("I walked through the door with you"),
+                "I walked through the door with you",
+            ),
+        ];
+        let answered: Vec<&GuessRecord> = guesses.iter().collect();
+
+        let current_lines = current_lines(&[song], &revisions, &answered);
+        let most_missed_lines = most_missed_lines(&answered, &current_lines);
+        assert_eq!(most_missed_lines.len(), 2);
+        assert_eq!(most_missed_lines[0].prompt, "The air was cold");
+        assert_eq!(most_missed_lines[0].num_misses, 2);
+        assert_eq!(most_missed_lines[1].num_misses, 1);
+    }
+}
@@ -0,0 +1,204 @@
+//! Presets: selections of songs and rules saved under a short code, such as `K7QX3M`, which players share so
+//! that others can start a game with the same songs and rules.
+//!
+//! A preset is created with `POST /presets`, and a game is started from it with `POST /game/start?preset=<code>`.
+//! Like a game, a preset stores its songs as a [`Selection`] of the unnamed songlist of every song available when
+//! it was created, so it is resolved against the current songs whenever it is used. Songs which have since been
+//! removed are reported rather than silently left out.
+
+use crate::error::{ApiError, ApiResult};
+use crate::loader_v2::SongLibrary;
+use crate::rules::GameRules;
+use crate::selection::Selection;
+use crate::song::Song;
+use crate::songlists::unnamed_songlist;
+use crate::storage::{DynStorage, Preset};
+use rand::Rng;
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The characters of preset codes. Characters which are easily mistaken for one another (`0`/`O`, `1`/`I`/`L`)
+/// are left out.
+const CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKMNPQRSTUVWXYZ";
+const CODE_LENGTH: usize = 6;
+
+/// Generate a random preset code.
+fn generate_code(rng: &mut impl Rng) -> String {
+    (0..CODE_LENGTH)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// The canonical form of a code entered by a player.
+fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+/// List songs for an error message.
+pub fn describe_songs(songs: &[(String, String)]) -> String {
+    songs
+        .iter()
+        .map(|(album, name)| format!("`{}` from `{}`", name, album))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// The songs and rules of a preset, as of the current songs.
+#[derive(Serialize, JsonSchema)]
+pub struct ResolvedPreset {
+    pub code: String,
+    pub rules: GameRules,
+    /// The (album, song name) pairs of the preset which are part of the game.
+    pub songs: Vec<(String, String)>,
+    /// The (album, song name) pairs of the preset which are no longer part of the game.
+    pub missing_songs: Vec<(String, String)>,
+}
+
+/// Look up the preset with code `code`, and split its songs into those which are in `songs` and those which
+/// are not.
+pub async fn resolve(
+    code: &str,
+    songs: &[Song],
+    storage: &DynStorage,
+) -> ApiResult<ResolvedPreset> {
+    let code = normalize_code(code);
+    let not_found = || ApiError::NotFound(format!("no preset with code `{}`", code));
+    let Some(preset) = storage.get_preset(&code).await? else {
+        return Err(not_found());
+    };
+    let Some(songlist) = storage.get_songlist(preset.songlist_id).await? else {
+        return Err(not_found());
+    };
+    let rules = GameRules::preset(&preset.rules).ok_or_else(|| {
+        ApiError::Internal(format!(
+            "preset `{}` has unknown rules `{}`",
+            code, preset.rules
+        ))
+    })?;
+    let selected_songs = Selection::decode(&preset.selection)
+        .and_then(|selection| selection.songs(&songlist.content))
+        .map_err(|e| {
+            ApiError::Internal(format!(
+                "the selection of preset `{}` is invalid: {}",
+                code, e
+            ))
+        })?;
+
+    let (songs, missing_songs) = selected_songs
+        .into_iter()
+        .cloned()
+        .partition(|(album, name)| {
+            songs
+                .iter()
+                .any(|song| song.album == album && song.name == name)
+        });
+    Ok(ResolvedPreset {
+        code: preset.code,
+        rules,
+        songs,
+        missing_songs,
+    })
+}
+
+/// API endpoint to save a selection of songs and rules as a preset, and get its code.
+///
+/// The body lists (album, song name) pairs, which must all be part of the game.
+/// `rules` names the preset of rules to play with (see `GET /rules`), `classic` by default.
+#[openapi(tag = "Game")]
+#[post(
+    "/presets?<rules>",
+    format = "application/json",
+    data = "<songs_to_include>"
+)]
+pub async fn create_preset(
+    songs_to_include: Json<Vec<(String, String)>>,
+    rules: Option<&str>,
+    songs: &State<SongLibrary>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<ResolvedPreset>> {
+    let rules = GameRules::preset_or_classic(rules)?;
+    if songs_to_include.is_empty() {
+        return Err(ApiError::BadRequest(
+            "a preset needs at least one song".to_owned(),
+        ));
+    }
+    let current_songs = songs.current();
+    let unknown_songs: Vec<(String, String)> = songs_to_include
+        .iter()
+        .filter(|(album, name)| {
+            !current_songs
+                .iter()
+                .any(|song| song.album == album && song.name == name)
+        })
+        .cloned()
+        .collect();
+    if !unknown_songs.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "unknown songs: {}",
+            describe_songs(&unknown_songs)
+        )));
+    }
+
+    let (songlist_id, songlist) = unnamed_songlist(&current_songs, storage).await?;
+    let songs_to_include: Vec<(&str, &str)> = songs_to_include
+        .iter()
+        .map(|(album, name)| (album.as_str(), name.as_str()))
+        .collect();
+    let selection = Selection::from_songs(&songlist, &songs_to_include);
+
+    let mut code = generate_code(&mut rand::thread_rng());
+    while storage.get_preset(&code).await?.is_some() {
+        code = generate_code(&mut rand::thread_rng());
+    }
+    storage
+        .insert_preset(&Preset {
+            code: code.clone(),
+            songlist_id,
+            selection: selection.encode(),
+            rules: rules.name.clone(),
+        })
+        .await?;
+
+    Ok(Json(ResolvedPreset {
+        code,
+        rules,
+        songs: selection
+            .songs(&songlist)
+            .map_err(|e| ApiError::Internal(e.to_string()))?
+            .into_iter()
+            .cloned()
+            .collect(),
+        missing_songs: Vec::new(),
+    }))
+}
+
+/// API endpoint to get the songs and rules of a preset, including those which are no longer part of the game.
+#[openapi(tag = "Game")]
+#[get("/presets/<code>")]
+pub async fn get_preset(
+    code: &str,
+    songs: &State<SongLibrary>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<ResolvedPreset>> {
+    Ok(Json(resolve(code, &songs.current(), storage).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+
+    #[test]
+    fn codes_use_unambiguous_characters() {
+        let code = generate_code(&mut rand::thread_rng());
+        assert_eq!(code.len(), CODE_LENGTH);
+        assert!(code.bytes().all(|c| CODE_ALPHABET.contains(&c)));
+        assert!(!code.contains(['0', 'O', '1', 'I', 'L']));
+
+        assert_eq!(generate_code(&mut StepRng::new(0, 0)), "222222");
+        assert_eq!(normalize_code(" k7qx3m\n"), "K7QX3M");
+    }
+}
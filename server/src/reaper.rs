@@ -0,0 +1,196 @@
+//! A background task which ends games that have been abandoned by their players.
+//!
+//! A game is considered abandoned once nothing has happened in it for longer than the configured TTL.
+//! Abandoned games are evicted from the in-memory game states, and their rows in `games` are marked as
+//! terminated with an `end_reason` of [`END_REASON_ABANDONED`], so that they show up in the history.
+
+use crate::game::GameState;
+use crate::persistence::delete_game_state;
+use crate::storage::{now, DynStorage, StorageResult};
+use rocket::tokio;
+use sqlx::types::time::PrimitiveDateTime;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The `end_reason` of a game which ended because the player guessed incorrectly.
+pub const END_REASON_INCORRECT: &str = "incorrect";
+/// The `end_reason` of a game which ended because the player stopped playing.
+pub const END_REASON_ABANDONED: &str = "abandoned";
+
+/// Games idle for longer than this many days are ended, unless overridden by `STALE_GAME_TTL_DAYS`.
+const DEFAULT_TTL_DAYS: u64 = 7;
+/// How often the reaper looks for abandoned games.
+const REAP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Reads the TTL of idle games from the `STALE_GAME_TTL_DAYS` environment variable.
+pub fn ttl_from_env() -> Duration {
+    let days = match std::env::var("STALE_GAME_TTL_DAYS") {
+        Ok(days) => days
+            .parse()
+            .expect("STALE_GAME_TTL_DAYS must be a whole number of days"),
+        Err(_) => DEFAULT_TTL_DAYS,
+    };
+    Duration::from_secs(days * 24 * 60 * 60)
+}
+
+/// Runs forever, periodically ending games which have been idle for longer than `ttl`.
+pub async fn run(
+    storage: DynStorage,
+    game_state: Arc<Mutex<HashMap<String, GameState>>>,
+    ttl: Duration,
+) {
+    let mut interval = tokio::time::interval(REAP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match reap_stale_games(&storage, &game_state, now() - ttl).await {
+            Ok(0) => {}
+            Ok(n) => println!("Ended {} abandoned games", n),
+            Err(e) => println!("Warning, failed to end abandoned games: {}", e),
+        }
+    }
+}
+
+/// Ends every unterminated game in which nothing has happened since `idle_since`, returning how many were ended.
+/// The terminal score of an abandoned game is the sum of the points earned by its guesses.
+async fn reap_stale_games(
+    storage: &DynStorage,
+    game_state: &Arc<Mutex<HashMap<String, GameState>>>,
+    idle_since: PrimitiveDateTime,
+) -> StorageResult<usize> {
+    let stale_games = storage.get_stale_games(idle_since).await?;
+
+    let mut num_ended = 0;
+    for uuid in &stale_games {
+        {
+            let mut guard = game_state.lock().unwrap();
+            // the player may have made a move since the query, which is saved only after the state is updated
+            if (*guard)
+                .get(uuid)
+                .is_some_and(|game| game.is_active_since(idle_since))
+            {
+                continue;
+            }
+            (*guard).remove(uuid);
+        }
+        storage
+            .end_game_with_recorded_score(uuid, END_REASON_ABANDONED)
+            .await?;
+        delete_game_state(storage, uuid).await;
+        num_ended += 1;
+    }
+
+    Ok(num_ended)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader_v2::load_songs_from_files;
+    use crate::storage::memory::MemoryStorage;
+    use crate::storage::{NewGame, NewGuess};
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    async fn insert_game(storage: &DynStorage, uuid: &str) {
+        storage
+            .insert_game(&NewGame {
+                uuid: uuid.to_owned(),
+                songlist_id: 1,
+                selection: "AQAA".to_owned(),
+                seed: 0,
+                rules: Default::default(),
+                secret_hash: None,
+                account_id: None,
+                daily_date: None,
+                player_name: None,
+            })
+            .await
+            .unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn idle_games_are_ended_as_abandoned() {
+        let storage: DynStorage = Arc::new(MemoryStorage::new());
+        insert_game(&storage, "idle").await;
+        storage
+            .insert_guess(&NewGuess {
+                game_uuid: "idle".to_owned(),
+                order_num: 0,
+                album: "Red".to_owned(),
+                song_name: "Red".to_owned(),
+                line_id: "".to_owned(),
+                prompt: "".to_owned(),
+                correct_answer: "".to_owned(),
+                result: "correct".to_owned(),
+                user_guess: "".to_owned(),
+                points_earned: 26,
+                lifeline_earned: None,
+                lifelines_used: vec![],
+                options: vec![],
+                selected_choice: None,
+            })
+            .await
+            .unwrap();
+        let game_state = Arc::new(Mutex::new(HashMap::new()));
+        let start_time = storage.get_game("idle").await.unwrap().unwrap().start_time;
+
+        let num_ended = reap_stale_games(&storage, &game_state, start_time - HOUR)
+            .await
+            .unwrap();
+        assert_eq!(num_ended, 0);
+
+        let num_ended = reap_stale_games(&storage, &game_state, start_time + HOUR)
+            .await
+            .unwrap();
+        assert_eq!(num_ended, 1);
+
+        let game = storage.get_game("idle").await.unwrap().unwrap();
+        assert!(game.has_terminated);
+        assert_eq!(game.terminal_score, Some(26));
+        assert_eq!(game.end_reason.as_deref(), Some(END_REASON_ABANDONED));
+    }
+
+    #[rocket::async_test]
+    async fn games_played_since_the_query_are_not_ended() {
+        let storage: DynStorage = Arc::new(MemoryStorage::new());
+        insert_game(&storage, "resumed").await;
+        let start_time = storage
+            .get_game("resumed")
+            .await
+            .unwrap()
+            .unwrap()
+            .start_time;
+
+        // the player makes a move, which is not saved yet
+        let songs = load_songs_from_files();
+        let mut game = GameState::new(&songs, &mut vec![], 0, Default::default());
+        game.touch(start_time + 2 * HOUR);
+        let game_state = Arc::new(Mutex::new(HashMap::from([("resumed".to_owned(), game)])));
+        let idle_since = start_time + HOUR;
+        assert_eq!(
+            storage.get_stale_games(idle_since).await.unwrap(),
+            vec!["resumed"]
+        );
+        let num_ended = reap_stale_games(&storage, &game_state, idle_since)
+            .await
+            .unwrap();
+        assert_eq!(num_ended, 0);
+        assert!(game_state.lock().unwrap().contains_key("resumed"));
+        assert!(
+            !storage
+                .get_game("resumed")
+                .await
+                .unwrap()
+                .unwrap()
+                .has_terminated
+        );
+
+        // and then leaves
+        let num_ended = reap_stale_games(&storage, &game_state, start_time + 3 * HOUR)
+            .await
+            .unwrap();
+        assert_eq!(num_ended, 1);
+        assert!(game_state.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+//! Tracking of corrections to lyrics, so that the history of a line survives them.
+//!
+//! Whenever the lyrics are loaded, the lines of every song are compared with the lines that were recorded the
+//! last time. If a song changed, the two versions are diffed line by line with the [`diff`](crate::diff) module,
+//! and every line which was rewritten, or whose line breaks were moved, is mapped to the line which replaced it.
+//! [`line_ids_with_history`] follows these mappings backwards, so that the history of a corrected line also
+//! contains the guesses and votes of its earlier versions.
+//!
+//! Changes which keep the id of a line, such as fixing its punctuation, need no mapping
+//! (see [`line_id`](crate::song::line_id)).
+
+use crate::diff::diff_greedy;
+use crate::song::{line_id, line_ids, Song};
+use crate::storage::{DynStorage, LineRevision, StorageResult};
+use std::collections::{HashMap, HashSet};
+
+/// The first of the private use characters which stand for lines when diffing.
+const FIRST_LINE_SYMBOL: u32 = 0xF0000;
+
+/// The words of a line, ignoring case and punctuation.
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Pair the lines of a hunk of changes, given the indices of the removed lines of the old version and of the
+/// added lines of the new version.
+///
+/// A hunk with as many added lines as removed lines is a rewrite of each line in turn. Otherwise, line breaks
+/// were moved, and each removed line is paired with the added line which shares the most words with it.
+fn pair_hunk(
+    old: &[&str],
+    new: &[&str],
+    removed: &[usize],
+    added: &[usize],
+) -> Vec<(usize, usize)> {
+    if removed.len() == added.len() {
+        return removed.iter().copied().zip(added.iter().copied()).collect();
+    }
+    removed
+        .iter()
+        .filter_map(|&i| {
+            let old_words = words(old[i]);
+            added
+                .iter()
+                .map(|&j| (words(new[j]).intersection(&old_words).count(), j))
+                .filter(|&(shared, _)| shared > 0)
+                // the first of the best matches
+                .min_by_key(|&(shared, j)| (usize::MAX - shared, j))
+                .map(|(_, j)| (i, j))
+        })
+        .collect()
+}
+
+/// Diff two versions of the lines of a song, and pair each changed line of the old version with the line of the
+/// new version which replaced it. Lines which were only removed, or only added, are not paired.
+///
+/// Lines are compared ignoring case and punctuation. Returns (old index, new index) pairs.
+pub fn diff_lines(song_id: &str, old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    if old.is_empty() || new.is_empty() {
+        return Vec::new();
+    }
+
+    // diff_greedy diffs strings, so each distinct line is turned into a character
+    let mut symbols: HashMap<String, char> = HashMap::new();
+    let mut encode = |lines: &[&str]| -> String {
+        lines
+            .iter()
+            .map(|text| {
+                let next_symbol = char::from_u32(FIRST_LINE_SYMBOL + symbols.len() as u32).unwrap();
+                *symbols
+                    .entry(line_id(song_id, text, 0))
+                    .or_insert(next_symbol)
+            })
+            .collect()
+    };
+    let old_symbols = encode(old);
+    let new_symbols = encode(new);
+    let Ok((_, edits)) = diff_greedy(&old_symbols, &new_symbols) else {
+        return Vec::new();
+    };
+    let indices = |key: &str| -> HashSet<usize> {
+        edits[key]
+            .iter()
+            .flat_map(|edit| edit.at..=edit.to)
+            .collect()
+    };
+    let (removed, added) = (indices("delete"), indices("insert"));
+
+    // walk both versions, pairing the lines of each hunk between lines which are common to both
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        let hunk_removed: Vec<usize> = (i..old.len()).take_while(|i| removed.contains(i)).collect();
+        let hunk_added: Vec<usize> = (j..new.len()).take_while(|j| added.contains(j)).collect();
+        pairs.extend(pair_hunk(old, new, &hunk_removed, &hunk_added));
+        i += hunk_removed.len() + 1;
+        j += hunk_added.len() + 1;
+    }
+    pairs
+}
+
+/// The revisions of the lines of a song which were changed from `old`, the text of its previous lines.
+pub fn revisions_of(song: &Song, old: &[&str]) -> Vec<LineRevision> {
+    let new: Vec<&str> = song.lines.iter().map(|line| line.text).collect();
+    let old_ids = line_ids(&song.id, old);
+    diff_lines(&song.id, old, &new)
+        .into_iter()
+        .filter(|&(i, j)| old_ids[i] != song.lines[j].id)
+        .map(|(i, j)| LineRevision {
+            old_line_id: old_ids[i].clone(),
+            new_line_id: song.lines[j].id.clone(),
+            old_text: old[i].to_owned(),
+            new_text: new[j].to_owned(),
+        })
+        .collect()
+}
+
+/// Compare the lines of every song with the lines recorded when the lyrics were last loaded, record the
+/// revisions of every changed line, and record the current lines.
+/// Returns the number of lines which were revised.
+pub async fn record_revisions(storage: &DynStorage, songs: &[Song]) -> StorageResult<usize> {
+    let recorded = storage.get_song_revisions().await?;
+    let mut num_revised = 0;
+    for song in songs {
+        let lines: Vec<&str> = song.lines.iter().map(|line| line.text).collect();
+        if let Some(old) = recorded.get(&song.id) {
+            let old: Vec<&str> = old.iter().map(String::as_str).collect();
+            if old == lines {
+                continue;
+            }
+            let revisions = revisions_of(song, &old);
+            storage.insert_line_revisions(&revisions).await?;
+            num_revised += revisions.len();
+        }
+        storage.save_song_revision(&song.id, &lines).await?;
+    }
+    Ok(num_revised)
+}
+
+/// The id of a line, followed by the ids of every earlier version of the line.
+pub fn line_ids_with_history(revisions: &[LineRevision], line_id: &str) -> Vec<String> {
+    let mut line_ids = vec![line_id.to_owned()];
+    let mut index = 0;
+    while index < line_ids.len() {
+        for revision in revisions {
+            // a line may have been changed back to an earlier version
+            if revision.new_line_id == line_ids[index] && !line_ids.contains(&revision.old_line_id)
+            {
+                line_ids.push(revision.old_line_id.clone());
+            }
+        }
+        index += 1;
+    }
+    line_ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::song::SongMetadata;
+    use crate::storage::memory::MemoryStorage;
+    use std::sync::Arc;
+
+    fn song(lyrics: &'static str) -> Song {
+        Song::new(
+            "speak-now/better-than-revenge".to_owned(),
+            "Speak Now",
+            "Better Than Revenge",
+            10,
+            lyrics,
+            SongMetadata::default(),
+        )
+    }
+
+    #[test]
+    fn rewritten_lines_and_moved_line_breaks_are_paired() {
+        let old = [
+            "Now go stand in the corner",
+            "She's better known for the things that she does on the mattress",
+            "I lived, I learned, had you, got burned",
+            "Held out and held on",
+        ];
+        let new = [
+            "Now go stand in the corner",
+            "He was a moth to the flame, she was holding the matches",
+            "I lived, I learned",
+            "Had you, got burned",
+            "Held out and held on",
+        ];
+        // the split line is paired with the half which shares the most words with it, which is also the half
+        // followed by the same line
+        assert_eq!(diff_lines("song", &old, &new), [(1, 1), (2, 3)]);
+
+        let old = ["Once upon a time", "Mistakes ago", "I was in your sights"];
+        let new = [
+            "Once upon a time",
+            "A few mistakes ago",
+            "I was in your sights",
+        ];
+        assert_eq!(diff_lines("song", &old, &new), [(1, 1)]);
+        // punctuation and case are ignored
+        assert_eq!(diff_lines("song", &old, &["once upon a time!"]), []);
+    }
+
+    #[rocket::async_test]
+    async fn history_follows_every_revision() {
+        let storage: DynStorage = Arc::new(MemoryStorage::new());
+        let versions = [
+            song("The story of us\nShe's better known for the things that she does on the mattress, whoa\nThe end\n"),
+            song("The story of us\nShe's better known for the things that she does on the mattress\nThe end\n"),
+            song("The story of us\nHe was a moth to the flame, she was holding the matches\nThe end\n"),
+        ];
+        let mut num_revised = Vec::new();
+        for version in [0, 1, 1, 2] {
+            let songs = &versions[version..version + 1];
+            num_revised.push(record_revisions(&storage, songs).await.unwrap());
+        }
+        // nothing is revised when the lyrics are first recorded, or when they haven't changed
+        assert_eq!(num_revised, [0, 1, 0, 1]);
+
+        let revisions = storage.get_line_revisions().await.unwrap();
+        let ids_of_line = |line: usize| -> Vec<String> {
+            versions
+                .iter()
+                .rev()
+                .map(|song| song.lines[line].id.clone())
+                .collect()
+        };
+        assert_eq!(
+            line_ids_with_history(&revisions, &versions[2].lines[1].id),
+            ids_of_line(1)
+        );
+        assert_eq!(
+            line_ids_with_history(&revisions, &versions[2].lines[0].id),
+            ids_of_line(0)[..1]
+        );
+    }
+}
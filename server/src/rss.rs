@@ -1,32 +1,24 @@
+use crate::error::ApiResult;
+use crate::storage::DynStorage;
 use atom_syndication::{Content, Entry, Feed};
 use chrono::prelude::*;
+use rocket::http::ContentType;
 use rocket::time::format_description;
-use sqlx::{types::time::PrimitiveDateTime, MySql, Pool};
+use rocket_okapi::openapi;
 use std::{
     collections::VecDeque,
     sync::{Arc, Mutex},
 };
 
-#[derive(sqlx::FromRow, Debug)]
-pub struct GameSchema {
-    pub time: PrimitiveDateTime,
-    pub album: String,
-    pub song_name: String,
-    pub lyric: String,
-    pub message: String,
-    pub contact: String,
-}
-
 /// API endpoint to get a RSS feed of the most recent feedback
 /// Returns the (up to) 20 most recent feedbacks by reading from the database and constructing the RSS feed
 /// This endpoint is for my personal use, to see what people have been saying.
+#[openapi(tag = "Feedback")]
 #[get("/feedback/get_recent_feedback_rss")]
-pub async fn get_recent_feedback_rss(pool: &rocket::State<Pool<MySql>>) -> String {
-    let recent_feedbacks: Vec<GameSchema> =
-        sqlx::query_as("SELECT * FROM feedback ORDER BY time DESC LIMIT 20;")
-            .fetch_all(pool.inner())
-            .await
-            .unwrap();
+pub async fn get_recent_feedback_rss(
+    storage: &rocket::State<DynStorage>,
+) -> ApiResult<(ContentType, String)> {
+    let recent_feedbacks = storage.get_recent_feedback(20).await?;
 
     let mut feed = Feed::default();
     feed.set_title("TSLC Feedback");
@@ -56,7 +48,7 @@ pub async fn get_recent_feedback_rss(pool: &rocket::State<Pool<MySql>>) -> Strin
         feed_entries.push(entry);
     }
     feed.set_entries(feed_entries);
-    feed.to_string()
+    Ok((atom_content_type(), feed.to_string()))
 }
 
 pub struct VoteEvent {
@@ -96,10 +88,11 @@ impl Default for RecentVotesCache {
 /// Returns the (up to) 20 most recent vote events
 /// Note that this endpoint reads from the RecentVotesCache, which will be cleared whenever
 /// the server restarts.
+#[openapi(tag = "Feedback")]
 #[get("/feedback/get_recent_votes_rss")]
 pub async fn get_recent_votes_rss(
     vote_cache: &rocket::State<Arc<Mutex<RecentVotesCache>>>,
-) -> String {
+) -> (ContentType, String) {
     let guard = vote_cache.lock().unwrap();
 
     let mut feed = Feed::default();
@@ -128,5 +121,10 @@ pub async fn get_recent_votes_rss(
     }
 
     feed.set_entries(feed_entries);
-    feed.to_string()
+    (atom_content_type(), feed.to_string())
+}
+
+/// The content type of an atom feed, `application/atom+xml`.
+fn atom_content_type() -> ContentType {
+    ContentType::new("application", "atom+xml")
 }
@@ -0,0 +1,135 @@
+//! The rules of a game, such as how close a guess must be to an answer, and how many lifelines a game starts with.
+//!
+//! A game is played with one of the named presets of [`GameRules`], which is chosen when the game starts
+//! (`classic` by default) and recorded with the game. `GET /rules` lists the presets.
+
+use crate::error::{ApiError, ApiResult};
+use crate::lifelines::LifelineInventory;
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// The names of the presets of [`GameRules`].
+pub const RULE_PRESETS: &[&str] = &["classic", "hard", "casual"];
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct GameRules {
+    /// The name of the preset, e.g. `classic`.
+    pub name: String,
+    /// If a guess's dist is greater than `max_acceptable_dist` from the answer, then the game ends.
+    pub max_acceptable_dist: usize,
+    /// A bonus awarded when the guess matches the answer perfectly.
+    pub points_for_perfect_match: i32,
+    /// The number of wrong choices of a multiple-choice question.
+    pub num_distractors: usize,
+    /// The number of lines before the shown line which are shown by the `ShowPrevLines` lifeline.
+    pub prev_lines_to_show: usize,
+    /// The lifelines that a game starts with.
+    pub starting_lifelines: LifelineInventory,
+    /// Guesses with more characters than this are refused.
+    pub max_guess_length: usize,
+    /// A guess which is shorter than an answer is "asking for more" (AFM), rather than wrong, if it differs from
+    /// the start of the answer by at most one character for every `afm_chars_per_error` characters of the guess.
+    pub afm_chars_per_error: usize,
+}
+
+impl GameRules {
+    /// The rules the game has always been played with.
+    pub fn classic() -> Self {
+        GameRules {
+            name: "classic".to_owned(),
+            max_acceptable_dist: 13,
+            points_for_perfect_match: 26,
+            num_distractors: 16,
+            prev_lines_to_show: 2,
+            starting_lifelines: LifelineInventory::of_each(1),
+            max_guess_length: 150,
+            afm_chars_per_error: 5,
+        }
+    }
+
+    /// Stricter matching, more choices, fewer hints, and no lifelines to start with.
+    pub fn hard() -> Self {
+        GameRules {
+            name: "hard".to_owned(),
+            max_acceptable_dist: 8,
+            points_for_perfect_match: 30,
+            num_distractors: 24,
+            prev_lines_to_show: 1,
+            starting_lifelines: LifelineInventory::of_each(0),
+            afm_chars_per_error: 8,
+            ..GameRules::classic()
+        }
+    }
+
+    /// Looser matching, fewer choices, more hints, and more lifelines to start with.
+    pub fn casual() -> Self {
+        GameRules {
+            name: "casual".to_owned(),
+            max_acceptable_dist: 18,
+            num_distractors: 8,
+            prev_lines_to_show: 3,
+            starting_lifelines: LifelineInventory::of_each(2),
+            afm_chars_per_error: 4,
+            ..GameRules::classic()
+        }
+    }
+
+    /// The preset named `name`, if there is one.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(GameRules::classic()),
+            "hard" => Some(GameRules::hard()),
+            "casual" => Some(GameRules::casual()),
+            _ => None,
+        }
+    }
+
+    /// The preset named `name`, or the classic rules if `name` is `None`.
+    pub fn preset_or_classic(name: Option<&str>) -> ApiResult<Self> {
+        match name {
+            None => Ok(GameRules::classic()),
+            Some(name) => GameRules::preset(name).ok_or_else(|| {
+                ApiError::BadRequest(format!(
+                    "unknown rules `{}`, expected one of {}",
+                    name,
+                    RULE_PRESETS.join(", ")
+                ))
+            }),
+        }
+    }
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules::classic()
+    }
+}
+
+/// API endpoint to list the presets of rules that games can be played with.
+#[openapi(tag = "Game")]
+#[get("/rules")]
+pub fn get_rules() -> Json<Vec<GameRules>> {
+    Json(
+        RULE_PRESETS
+            .iter()
+            .filter_map(|name| GameRules::preset(name))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_preset_is_named_after_itself() {
+        for name in RULE_PRESETS {
+            assert_eq!(GameRules::preset(name).unwrap().name, *name);
+        }
+        assert!(GameRules::preset("impossible").is_none());
+        assert_eq!(GameRules::preset_or_classic(None).unwrap().name, "classic");
+        assert!(GameRules::preset_or_classic(Some("Classic")).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+//! A compact encoding of the songs selected for a game, which is stored with the game and can be shared in a URL.
+//!
+//! A [`Selection`] is a bitset over the songs of a [`Songlist`](crate::history::Songlist): bit `i` is set if the
+//! `i`-th song of the songlist was selected. It is encoded as URL-safe base64 (without padding) of:
+//! * a version byte, currently [`SELECTION_VERSION`],
+//! * the number of songs of the songlist, as a big-endian `u16`,
+//! * the bits, eight songs to a byte, starting from the least significant bit.
+//!
+//! Games used to store their selection as a JSON map from album names to one boolean per song of the album,
+//! which [`Selection::from_album_flags`] converts from.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// The version of the encoding produced by [`Selection::encode`].
+pub const SELECTION_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectionError {
+    /// The token is not URL-safe base64.
+    InvalidBase64,
+    /// The token was encoded with a version of the encoding which is not known.
+    UnsupportedVersion(u8),
+    /// The token is too short for the number of songs it claims.
+    Truncated,
+    /// The selection is over a different number of songs than the songlist it is applied to.
+    WrongLength { expected: usize, actual: usize },
+}
+
+impl fmt::Display for SelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectionError::InvalidBase64 => write!(f, "the selection is not valid base64"),
+            SelectionError::UnsupportedVersion(version) => {
+                write!(f, "unsupported selection version {}", version)
+            }
+            SelectionError::Truncated => write!(f, "the selection is truncated"),
+            SelectionError::WrongLength { expected, actual } => write!(
+                f,
+                "the selection is over {} songs, but the songlist has {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+/// Which songs of a songlist were selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    selected: Vec<bool>,
+}
+
+impl Selection {
+    /// Select the songs of `songlist` which are in `songs`.
+    pub fn from_songs(songlist: &[(String, String)], songs: &[(&str, &str)]) -> Self {
+        Selection {
+            selected: songlist
+                .iter()
+                .map(|(album, name)| songs.contains(&(album.as_str(), name.as_str())))
+                .collect(),
+        }
+    }
+
+    /// Convert a selection in the old format, where the k-th boolean of an album says whether the k-th song of
+    /// the album in `songlist` was selected. Albums without a key had none of their songs selected.
+    pub fn from_album_flags(
+        songlist: &[(String, String)],
+        album_flags: &HashMap<String, Vec<bool>>,
+    ) -> Result<Self, SelectionError> {
+        let mut positions_in_album: HashMap<&str, usize> = HashMap::new();
+        let selected = songlist
+            .iter()
+            .map(|(album, _)| {
+                let position = positions_in_album.entry(album).or_default();
+                *position += 1;
+                album_flags
+                    .get(album)
+                    .and_then(|flags| flags.get(*position - 1))
+                    .copied()
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        for (album, flags) in album_flags {
+            let num_songs = positions_in_album.get(album.as_str()).copied();
+            if flags.len() > num_songs.unwrap_or(0) {
+                return Err(SelectionError::WrongLength {
+                    expected: num_songs.unwrap_or(0),
+                    actual: flags.len(),
+                });
+            }
+        }
+        Ok(Selection { selected })
+    }
+
+    /// The number of songs of the songlist the selection is over.
+    pub fn len(&self) -> usize {
+        self.selected.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.selected.is_empty()
+    }
+
+    /// The selected songs of `songlist`, in the order of the songlist.
+    pub fn songs<'a>(
+        &self,
+        songlist: &'a [(String, String)],
+    ) -> Result<Vec<&'a (String, String)>, SelectionError> {
+        if songlist.len() != self.len() {
+            return Err(SelectionError::WrongLength {
+                expected: songlist.len(),
+                actual: self.len(),
+            });
+        }
+        Ok(songlist
+            .iter()
+            .zip(&self.selected)
+            .filter(|(_, &is_selected)| is_selected)
+            .map(|(song, _)| song)
+            .collect())
+    }
+
+    pub fn encode(&self) -> String {
+        let mut bytes = vec![SELECTION_VERSION];
+        bytes.extend((self.len() as u16).to_be_bytes());
+        for chunk in self.selected.chunks(8) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (bit, &is_selected)| {
+                    byte | ((is_selected as u8) << bit)
+                });
+            bytes.push(byte);
+        }
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(token: &str) -> Result<Self, SelectionError> {
+        let bytes = base64::decode_config(token, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| SelectionError::InvalidBase64)?;
+        match bytes.first() {
+            Some(&SELECTION_VERSION) => {}
+            Some(&version) => return Err(SelectionError::UnsupportedVersion(version)),
+            None => return Err(SelectionError::Truncated),
+        }
+        let (Some(&high), Some(&low)) = (bytes.get(1), bytes.get(2)) else {
+            return Err(SelectionError::Truncated);
+        };
+        let len = u16::from_be_bytes([high, low]) as usize;
+        let bits = &bytes[3..];
+        if bits.len() < len.div_ceil(8) {
+            return Err(SelectionError::Truncated);
+        }
+        Ok(Selection {
+            selected: (0..len)
+                .map(|i| bits[i / 8] & (1 << (i % 8)) != 0)
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn songlist() -> Vec<(String, String)> {
+        [
+            ("Red", "State Of Grace"),
+            ("Red", "Red"),
+            ("Lover", "Lover"),
+            ("Red", "Treacherous"),
+            ("Folklore", "Cardigan"),
+        ]
+        .iter()
+        .map(|(album, name)| (album.to_string(), name.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn selections_round_trip() {
+        for len in [0, 1, 7, 8, 9, 250] {
+            let selection = Selection {
+                selected: (0..len).map(|i| i % 3 == 0 || i == len - 1).collect(),
+            };
+            assert_eq!(Selection::decode(&selection.encode()), Ok(selection));
+        }
+
+        let songlist = songlist();
+        let selection =
+            Selection::from_songs(&songlist, &[("Red", "Red"), ("Folklore", "Cardigan")]);
+        assert_eq!(selection.encode(), "AQAFEg");
+        assert_eq!(
+            selection.songs(&songlist).unwrap(),
+            [&songlist[1], &songlist[4]]
+        );
+        assert_eq!(
+            selection.songs(&songlist[1..]),
+            Err(SelectionError::WrongLength {
+                expected: 4,
+                actual: 5
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_tokens_are_rejected() {
+        assert_eq!(Selection::decode("!!"), Err(SelectionError::InvalidBase64));
+        assert_eq!(
+            Selection::decode("AgAFEg"),
+            Err(SelectionError::UnsupportedVersion(2))
+        );
+        assert_eq!(Selection::decode("AQAJEg"), Err(SelectionError::Truncated));
+        assert_eq!(Selection::decode(""), Err(SelectionError::Truncated));
+    }
+
+    #[test]
+    fn album_flags_are_converted() {
+        let songlist = songlist();
+        let album_flags = HashMap::from([
+            ("Red".to_owned(), vec![false, true, true]),
+            ("Folklore".to_owned(), vec![true]),
+        ]);
+        let selection = Selection::from_album_flags(&songlist, &album_flags).unwrap();
+        let selected: Vec<&str> = selection
+            .songs(&songlist)
+            .unwrap()
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect();
+        assert_eq!(selected, ["Red", "Treacherous", "Cardigan"]);
+
+        let album_flags = HashMap::from([("Lover".to_owned(), vec![true, true])]);
+        assert!(Selection::from_album_flags(&songlist, &album_flags).is_err());
+    }
+}
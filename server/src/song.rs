@@ -1,47 +1,160 @@
+use rocket::serde::json::Json;
 use rocket::State;
-use serde::Serialize;
-use sqlx::{FromRow, MySql, Pool};
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 use std::collections::{HashMap, HashSet};
 
-use crate::history::{Songlist, SonglistSchema};
+use crate::album::{list_by_album, AlbumListing};
+use crate::error::{ApiError, ApiResult};
+use crate::history::Songlist;
+use crate::loader_v2::SongLibrary;
+use crate::revisions::line_ids_with_history;
+use crate::storage::DynStorage;
+
+/// Characters which may not appear in lyrics.
+/// Each of them looks like a plain character (e.g. `’` looks like `'`), which players can't be expected to type.
+#[rustfmt::skip]
+pub const INVALID_CHARS: &[char] = &[
+    'е',  // this is a cyrillic e
+    '”',
+    '“',
+    '’',
+    '‘',
+    '\u{2005}',
+    '\u{205f}',
+    '\u{200b}',
+    '—',  // this is a long dash
+    '–',  // this is a different long dash
+    '…'
+];
+
+/// Returns the first character of `text` which is in [`INVALID_CHARS`], if any.
+pub fn find_invalid_char(text: &str) -> Option<char> {
+    text.chars().find(|c| INVALID_CHARS.contains(c))
+}
 
 /// Represents a song with an album and songname.
 /// lyrics_raw is a string of all lines (separated by `\n`),
 /// and lines is a vector of [`Line`] structs containing data about whether lines are good prompts.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct Song {
+    /// A stable identifier of the song (see [`song_id`]).
+    pub id: String,
     pub album: &'static str,
     pub name: &'static str,
+    /// The position of the song on its album, starting from 1.
+    pub track: u32,
     pub lyrics_raw: &'static str,
     pub lines: Vec<Line>,
     pub lines_unique: Vec<&'static str>,
+    pub metadata: SongMetadata,
+}
+
+/// Information about a song, given in the front matter of its lyrics file.
+/// Songs loaded from files without front matter have no metadata.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct SongMetadata {
+    /// The date the song was released, as `YYYY-MM-DD`.
+    pub release_date: Option<String>,
+    pub featured_artists: Vec<String>,
+    pub version_tags: Vec<VersionTag>,
+    /// Other names the song is known by, such as its name before being re-recorded.
+    pub alternate_titles: Vec<String>,
+}
+
+/// Which version of a song this is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionTag {
+    /// A re-recording, e.g. `Red (Taylor's Version)`.
+    TaylorsVersion,
+    /// A song released "From The Vault" alongside a re-recording.
+    Vault,
+    /// A bonus track of a deluxe edition.
+    Deluxe,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct ISong {
+    pub id: String,
     pub album: &'static str,
     pub name: &'static str,
     pub lyrics_raw: &'static str,
     pub lines: Vec<ILine>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct Line {
+    /// A stable identifier of the line (see [`line_id`]), assigned by [`Song::new`].
+    pub id: String,
     pub text: &'static str,
     pub is_exclamatory: bool,
     /// If the Option is `Some`, that means that the line is a bad prompt,
     /// and the string is a reason why the line is not appropriate to use as a prompt.
     pub is_bad_prompt: Option<&'static str>,
 }
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct ILine {
+    pub id: String,
     pub text: &'static str,
     pub is_bad_prompt: Option<&'static str>,
     /// `num_guesses` is the number of times the line has been played in a game. Used by the client to display a subscript.
     pub num_guesses: usize,
 }
 
-fn calculate_is_exclamatory_heuristic(text: &str) -> bool {
+/// Turn `text` into a lowercase identifier of letters, digits and single dashes, e.g. `Tim Mcgraw` into
+/// `tim-mcgraw`.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_owned()
+}
+
+/// The stable identifier of a song, e.g. `red/i-knew-you-were-trouble`.
+pub fn song_id(album_id: &str, song_name: &str) -> String {
+    format!("{}/{}", album_id, slugify(song_name))
+}
+
+/// The stable identifier of a line, e.g. `red/i-knew-you-were-trouble/5b1c3a0e-0`.
+///
+/// It is made of the id of its song, a hash of its text, and how many lines of the song before it have the same
+/// text. Only the letters and digits of the text are hashed, ignoring case, so that correcting the punctuation
+/// or quotes of a lyric does not change its id. Since the id is computed from the text, the id of a line that is
+/// no longer part of the game can still be computed, e.g. to look up its history.
+pub fn line_id(song_id: &str, text: &str, occurrence: usize) -> String {
+    let normalized: String = text
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let mut hasher = Sha1::new();
+    hasher.update(normalized.as_bytes());
+    let hash = format!("{:x}", hasher.finalize());
+    format!("{}/{}-{}", song_id, &hash[..8], occurrence)
+}
+
+/// The ids of the lines of a song, given the text of each line in order.
+pub fn line_ids(song_id: &str, texts: &[&str]) -> Vec<String> {
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    texts
+        .iter()
+        .map(|text| {
+            let occurrence = occurrences.entry(line_id(song_id, text, 0)).or_default();
+            *occurrence += 1;
+            line_id(song_id, text, *occurrence - 1)
+        })
+        .collect()
+}
+
+pub fn calculate_is_exclamatory_heuristic(text: &str) -> bool {
     // The goal here is to calculate whether a line is "exclamatory". A line like "Oh, oh, oh, whoa" is exclamatory, since it contains many exclamatory words.
     // We don't want the guessing game's questions to involve exclamatory words, because they are generally difficult to recall or type properly.
     let exclamatory_words = [
@@ -71,43 +184,68 @@ fn calculate_is_exclamatory_heuristic(text: &str) -> bool {
     false
 }
 
+/// A marker of a line in a lyrics file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Marker<'a> {
+    /// `<exclamatory>`: the line is exclamatory, and so neither it nor the line before it are used as prompts.
+    Exclamatory,
+    /// `<misc_bad note>`: the line is not used as a prompt, for the reason given in the (optional) note.
+    MiscBad(&'a str),
+}
+
+/// Split a raw line from a lyrics file into its text, and its markers if it has any.
+/// The text is everything before the first `$`, and the markers are everything after the last `$`.
+pub fn split_markers(raw_line: &str) -> (&str, Option<&str>) {
+    match (raw_line.split_once('$'), raw_line.rsplit_once('$')) {
+        (Some((text, _)), Some((_, markers))) => (text, Some(markers)),
+        _ => (raw_line, None),
+    }
+}
+
+/// Parse the markers of a line, such as `<exclamatory>` or `<misc_bad too short>`.
+/// Returns the markers which were understood, and a description of every part which was not.
+pub fn parse_markers(markers: &str) -> (Vec<Marker<'_>>, Vec<String>) {
+    let mut parsed = Vec::new();
+    let mut problems = Vec::new();
+    let mut rest = markers.trim();
+    while !rest.is_empty() {
+        let Some(marker) = rest.strip_prefix('<') else {
+            problems.push(format!("unexpected text `{}` in markers", rest));
+            break;
+        };
+        let Some((marker, after)) = marker.split_once('>') else {
+            problems.push(format!("marker `{}` is missing its closing `>`", rest));
+            break;
+        };
+        let (name, note) = marker.split_once(' ').unwrap_or((marker, ""));
+        match name {
+            "exclamatory" if note.is_empty() => parsed.push(Marker::Exclamatory),
+            "misc_bad" => parsed.push(Marker::MiscBad(note)),
+            _ => problems.push(format!("unknown marker `<{}>`", marker)),
+        }
+        rest = after.trim_start();
+    }
+    (parsed, problems)
+}
+
 impl Line {
     pub fn new(raw_text: &'static str) -> Line {
         let mut is_exclamatory = false;
         let mut is_bad_prompt = None;
 
-        let text = if raw_text.contains('$') {
-            // everything before the `$` is part of the line
-            let _num_segments = raw_text.split('$').count();
-            raw_text.split('$').take(1).next().unwrap()
-        } else {
-            raw_text
-        };
-
-        if raw_text.contains('$') {
-            let markers = raw_text.split('$').next_back().unwrap();
-            if markers.contains("<exclamatory>") {
-                is_exclamatory = true;
-            }
-            if markers.contains("<misc_bad") {
-                let bad_desc = markers
-                    .split_once("<misc_bad ")
-                    .unwrap()
-                    .1
-                    .split_once('>')
-                    .unwrap()
-                    .0;
-
-                is_bad_prompt = Some(bad_desc);
+        let (text, markers) = split_markers(raw_text);
+        if let Some(markers) = markers {
+            for marker in parse_markers(markers).0 {
+                match marker {
+                    Marker::Exclamatory => is_exclamatory = true,
+                    Marker::MiscBad("") => is_bad_prompt = Some("marked as bad"),
+                    Marker::MiscBad(note) => is_bad_prompt = Some(note),
+                }
             }
         }
 
-        if is_exclamatory != calculate_is_exclamatory_heuristic(text) {
-            println!("Warning, the following line's exclamatory status does not match the calculated heuristic:");
-            println!("{}", &text);
-        }
-
         Line {
+            id: String::new(),
             text,
             is_exclamatory,
             is_bad_prompt,
@@ -116,7 +254,14 @@ impl Line {
 }
 
 impl Song {
-    pub fn new(album: &'static str, name: &'static str, lyrics_raw: &'static str) -> Self {
+    pub fn new(
+        id: String,
+        album: &'static str,
+        name: &'static str,
+        track: u32,
+        lyrics_raw: &'static str,
+        metadata: SongMetadata,
+    ) -> Self {
         let mut lyrics_raw_processed = String::new();
         let mut lines: Vec<Line> = Vec::new();
         let mut lines_unique: HashSet<&'static str> = HashSet::new();
@@ -138,26 +283,18 @@ impl Song {
             lines.push(line);
         }
 
+        let texts: Vec<&str> = lines.iter().map(|line| line.text).collect();
+        for (line, line_id) in lines.iter_mut().zip(line_ids(&id, &texts)) {
+            line.id = line_id;
+        }
+
         // Check lines for invalid characters
         for line in &lines {
-            let line_text = line.text;
-            let invalid_chars = vec![
-                'е',
-                '”',
-                '“',
-                '’',
-                '\u{2005}',
-                '\u{205f}',
-                '\u{200b}',
-                '—',  // this is a long dash
-                '–',  // this is a different long dash
-                '…'
-            ];
-
-            for invalid_char in invalid_chars {
-                if line_text.contains(invalid_char) {
-                    panic!("line contains invalid character `{}` from song {}", invalid_char, name);
-                }
+            if let Some(invalid_char) = find_invalid_char(line.text) {
+                panic!(
+                    "line contains invalid character `{}` from song {}",
+                    invalid_char, name
+                );
             }
         }
 
@@ -196,151 +333,156 @@ impl Song {
         // }
 
         Song {
+            id,
             album,
             name,
+            track,
             lyrics_raw: Box::leak(lyrics_raw_processed.into_boxed_str()),
             lines,
             lines_unique: lines_unique.into_iter().collect(),
+            metadata,
         }
     }
-}
 
-/// API endpoint for getting a list of all songs.
-/// returns a hashmap, where keys are album names and values are song names.
-#[get("/songs")]
-pub fn get_song_list(songs: &State<Vec<Song>>) -> String {
-    let mut s: HashMap<&'static str, Vec<&'static str>> = HashMap::new();
-    for song in songs.iter() {
-        if let Some(v) = s.get(song.album) {
-            let mut v = v.clone();
-            v.push(song.name);
-            s.insert(song.album, v);
-        } else {
-            s.insert(song.album, vec![song.name]);
-        }
+    /// The id of the first line of the song with the text `text`.
+    ///
+    /// Prompts are picked by text, so this is the line that guesses of a prompt refer to. If the song has no such
+    /// line, e.g. because `text` comes from an older version of the lyrics, the id which the line would have is
+    /// returned.
+    pub fn line_id_of(&self, text: &str) -> String {
+        self.lines
+            .iter()
+            .find(|line| line.text == text)
+            .map_or_else(|| line_id(&self.id, text, 0), |line| line.id.clone())
     }
-
-    serde_json::to_string(&s).unwrap()
 }
 
-/// API endpoint for getting a list of all songs, from a particular songlist.
+/// API endpoint for getting a list of all songs.
+/// returns a list of albums in release order, each with the names of its songs in track order.
+///
+/// If `id` is given, the songs of that songlist are listed instead of the current songs.
 /// A songlist is a collection of all available songs, at a particular time.
 /// For example, there's a songlist that contains every album released before Midnights.
+#[openapi(tag = "Songs")]
 #[get("/songs?<id>")]
-pub async fn get_song_list_with_id(id: i32, pool: &rocket::State<Pool<MySql>>) -> String {
-    let result: Vec<SonglistSchema> = sqlx::query_as("SELECT * FROM songlists WHERE id LIKE ?")
-        .bind(id)
-        .fetch_all(pool.inner())
-        .await
-        .unwrap();
-
-    if result.is_empty() {
-        return "{}".to_string();
-    }
-
-    let songlists: Vec<Songlist> = result
-        .into_iter()
-        .map(|songlist| Songlist {
-            id: songlist.id,
-            sha1sum: songlist.sha1sum,
-            content: songlist.content.as_ref().clone(),
-        })
-        .collect();
-
-    let songs = &songlists.first().unwrap().content;
-
-    let mut s: HashMap<String, Vec<String>> = HashMap::new();
-    for (album, name) in songs.iter() {
-        if let Some(v) = s.get(album) {
-            let mut v = v.clone();
-            v.push(name.clone());
-            s.insert(album.clone(), v);
-        } else {
-            s.insert(album.clone(), vec![name.clone()]);
+pub async fn get_song_list(
+    id: Option<i32>,
+    songs: &State<SongLibrary>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<Vec<AlbumListing>>> {
+    let albums = songs.albums();
+    let songs = songs.current();
+    let entries: Vec<(String, String)> = match id {
+        Some(id) => {
+            let Some(songlist) = storage.get_songlist(id).await? else {
+                return Err(ApiError::NotFound(format!("no songlist with id {}", id)));
+            };
+            songlist.content
         }
-    }
+        None => songs
+            .iter()
+            .map(|song| (song.album.to_owned(), song.name.to_owned()))
+            .collect(),
+    };
 
-    serde_json::to_string(&s).unwrap()
+    Ok(Json(list_by_album(&albums, &songs, &entries)))
 }
 
 /// API endpoint to get a list of all songlists.
+#[openapi(tag = "Songs")]
 #[get("/all_songlists")]
-pub async fn get_all_songlists(pool: &rocket::State<Pool<MySql>>) -> String {
-    let all_songlists: Vec<SonglistSchema> = sqlx::query_as("SELECT * FROM songlists")
-        .fetch_all(pool.inner())
-        .await
-        .unwrap();
-    let all_songlists: Vec<Songlist> = all_songlists
-        .into_iter()
-        .map(|songlist| Songlist {
-            id: songlist.id,
-            sha1sum: songlist.sha1sum,
-            content: songlist.content.as_ref().clone(),
-        })
-        .collect();
+pub async fn get_all_songlists(
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<HashMap<i32, Vec<(String, String)>>>> {
+    let all_songlists: Vec<Songlist> = storage.get_songlists().await?;
 
-    let mut result: HashMap<i32, &Vec<(String, String)>> = HashMap::new();
+    let mut result: HashMap<i32, Vec<(String, String)>> = HashMap::new();
 
-    for songlist in all_songlists.iter() {
-        result.insert(songlist.id, &songlist.content);
+    for songlist in all_songlists.into_iter() {
+        result.insert(songlist.id, songlist.content);
     }
 
-    serde_json::to_string(&result).unwrap()
-}
-
-#[derive(FromRow, Debug)]
-struct Count {
-    total: i32,
+    Ok(Json(result))
 }
 
 /// API endpoint to get a song from album + name.
+#[openapi(tag = "Songs")]
 #[get("/songs/<album>/<name>")]
 pub async fn get_song(
-    pool: &rocket::State<Pool<MySql>>,
-    songs: &State<Vec<Song>>,
+    storage: &State<DynStorage>,
+    songs: &State<SongLibrary>,
     album: &str,
     name: &str,
-) -> String {
-    for song in songs.iter() {
+) -> ApiResult<Json<ISong>> {
+    for song in songs.current().iter() {
         if song.album == album && song.name == name {
             let mut my_song = ISong {
+                id: song.id.clone(),
                 album: song.album,
                 name: song.name,
                 lyrics_raw: song.lyrics_raw,
                 lines: vec![],
             };
+            let revisions = storage.get_line_revisions().await?;
             for line in &song.lines {
                 let is_bad_prompt = &line.is_bad_prompt;
                 let mut num_guesses = 0;
 
                 if is_bad_prompt.is_none() {
-                    let count: Count = sqlx::query_as(
-                        "SELECT count(1) as total from guesses 
-                        WHERE 
-                        album LIKE ?
-                        AND song_name LIKE ?
-                        AND prompt LIKE ?
-                        ",
-                    )
-                    .bind(album)
-                    .bind(name)
-                    .bind(line.text)
-                    .fetch_one(pool.inner())
-                    .await
-                    .unwrap();
-                    num_guesses = count.total as usize;
+                    for line_id in line_ids_with_history(&revisions, &song.line_id_of(line.text)) {
+                        num_guesses += storage.count_guesses_for_line(&line_id).await? as usize;
+                    }
                 }
 
                 my_song.lines.push(ILine {
+                    id: line.id.clone(),
                     text: line.text,
                     is_bad_prompt: *is_bad_prompt,
                     num_guesses,
                 })
             }
 
-            return serde_json::to_string(&my_song).unwrap();
+            return Ok(Json(my_song));
         }
     }
 
-    "{}".to_string()
+    Err(ApiError::NotFound(format!(
+        "no song named `{}` in album `{}`",
+        name, album
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_ids_ignore_punctuation_and_count_repeats() {
+        let song = Song::new(
+            song_id("red", "I Knew You Were Trouble."),
+            "Red",
+            "I Knew You Were Trouble.",
+            1,
+            "Trouble, trouble, trouble\nOh, oh\ntrouble trouble trouble!\n",
+            SongMetadata::default(),
+        );
+        assert_eq!(song.id, "red/i-knew-you-were-trouble");
+        assert_eq!(
+            song.lines[0].id,
+            line_id(&song.id, "Trouble trouble trouble", 0)
+        );
+        assert_eq!(
+            song.lines[2].id,
+            line_id(&song.id, "Trouble trouble trouble", 1)
+        );
+        assert_ne!(song.lines[0].id, song.lines[1].id);
+        assert_eq!(
+            song.line_id_of("trouble trouble trouble!"),
+            song.lines[2].id
+        );
+        assert_eq!(
+            song.line_id_of("Not a lyric"),
+            line_id(&song.id, "Not a lyric", 0)
+        );
+    }
 }
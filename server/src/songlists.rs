@@ -0,0 +1,194 @@
+//! Curated songlists: named selections of songs, such as `Pre-Midnights` or `Eras Tour setlist`.
+//!
+//! Every game records the songs it drew from as an unnamed [`Songlist`] of every song available when it started,
+//! which is created implicitly. Curated songlists are instead created and edited by the admin, and a game can be
+//! started from one with `POST /game/start/<id>`. Unnamed songlists can't be edited, since the selected songs of
+//! games refer to them by position.
+
+use crate::admin::Admin;
+use crate::error::{ApiError, ApiResult};
+use crate::history::Songlist;
+use crate::loader_v2::SongLibrary;
+use crate::song::Song;
+use crate::storage::{DynStorage, NewSonglist};
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+/// The hash which identifies the content of a songlist.
+pub fn songlist_sha1sum(content: &[(String, String)]) -> ApiResult<String> {
+    let mut hasher = Sha1::new();
+    hasher.update(serde_json::to_string(content)?.as_bytes());
+    Ok(format!("{:X}", hasher.finalize()))
+}
+
+/// The content of the unnamed songlist of every song in `songs`, and its id.
+/// The songlist is created if it doesn't exist yet.
+pub async fn unnamed_songlist(
+    songs: &[Song],
+    storage: &DynStorage,
+) -> ApiResult<(i32, Vec<(String, String)>)> {
+    let content: Vec<(String, String)> = songs
+        .iter()
+        .map(|song| (song.album.to_owned(), song.name.to_owned()))
+        .collect();
+    let id = storage
+        .get_or_create_songlist(&songlist_sha1sum(&content)?, &content)
+        .await?;
+    Ok((id, content))
+}
+
+/// A curated songlist, without its songs. Its songs are listed by `GET /songs?id=<id>`.
+#[derive(Serialize, JsonSchema)]
+pub struct SonglistSummary {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub num_songs: usize,
+}
+
+impl SonglistSummary {
+    fn new(id: i32, songlist: NewSonglist) -> Self {
+        SonglistSummary {
+            id,
+            name: songlist.name,
+            description: songlist.description,
+            num_songs: songlist.content.len(),
+        }
+    }
+
+    /// The summary of `songlist`, if it is a curated songlist.
+    fn of(songlist: Songlist) -> Option<Self> {
+        Some(SonglistSummary {
+            id: songlist.id,
+            name: songlist.name?,
+            description: songlist.description,
+            num_songs: songlist.content.len(),
+        })
+    }
+}
+
+/// The name, description and songs of a curated songlist to create or edit.
+#[derive(Deserialize, JsonSchema)]
+pub struct SonglistRequest {
+    name: String,
+    description: Option<String>,
+    /// (album, song name) pairs, which must all be part of the game.
+    songs: Vec<(String, String)>,
+}
+
+/// Check a request to create or edit the curated songlist `id` (`None` when creating one),
+/// and turn it into the songlist to store.
+async fn check_request(
+    request: SonglistRequest,
+    id: Option<i32>,
+    songs: &SongLibrary,
+    storage: &DynStorage,
+) -> ApiResult<NewSonglist> {
+    let name = request.name.trim().to_owned();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("a songlist needs a name".to_owned()));
+    }
+    if request.songs.is_empty() {
+        return Err(ApiError::BadRequest(
+            "a songlist needs at least one song".to_owned(),
+        ));
+    }
+    let current_songs = songs.current();
+    let unknown_songs: Vec<String> = request
+        .songs
+        .iter()
+        .filter(|(album, name)| {
+            !current_songs
+                .iter()
+                .any(|song| song.album == album && song.name == name)
+        })
+        .map(|(album, name)| format!("`{}` from `{}`", name, album))
+        .collect();
+    if !unknown_songs.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "unknown songs: {}",
+            unknown_songs.join(", ")
+        )));
+    }
+
+    let songlists = storage.get_songlists().await?;
+    if songlists.iter().any(|songlist| {
+        Some(songlist.id) != id
+            && songlist
+                .name
+                .as_deref()
+                .is_some_and(|other| other.eq_ignore_ascii_case(&name))
+    }) {
+        return Err(ApiError::Conflict(format!(
+            "there is already a songlist named `{}`",
+            name
+        )));
+    }
+
+    Ok(NewSonglist {
+        name,
+        description: request.description.filter(|d| !d.trim().is_empty()),
+        sha1sum: songlist_sha1sum(&request.songs)?,
+        content: request.songs,
+    })
+}
+
+/// API endpoint to list the curated songlists.
+#[openapi(tag = "Songs")]
+#[get("/songlists")]
+pub async fn get_songlists(storage: &State<DynStorage>) -> ApiResult<Json<Vec<SonglistSummary>>> {
+    let songlists = storage.get_songlists().await?;
+    Ok(Json(
+        songlists
+            .into_iter()
+            .filter_map(SonglistSummary::of)
+            .collect(),
+    ))
+}
+
+/// API endpoint to create a curated songlist.
+#[openapi(tag = "Admin")]
+#[post("/admin/songlists", format = "application/json", data = "<request>")]
+pub async fn create_songlist(
+    _admin: Admin,
+    request: Json<SonglistRequest>,
+    songs: &State<SongLibrary>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<SonglistSummary>> {
+    let songlist = check_request(request.into_inner(), None, songs, storage).await?;
+    let id = storage.create_songlist(&songlist).await?;
+    Ok(Json(SonglistSummary::new(id, songlist)))
+}
+
+/// API endpoint to replace the name, description and songs of a curated songlist.
+#[openapi(tag = "Admin")]
+#[put(
+    "/admin/songlists/<id>",
+    format = "application/json",
+    data = "<request>"
+)]
+pub async fn update_songlist(
+    _admin: Admin,
+    id: i32,
+    request: Json<SonglistRequest>,
+    songs: &State<SongLibrary>,
+    storage: &State<DynStorage>,
+) -> ApiResult<Json<SonglistSummary>> {
+    let Some(existing) = storage.get_songlist(id).await? else {
+        return Err(ApiError::NotFound(format!("no songlist with id {}", id)));
+    };
+    if existing.name.is_none() {
+        return Err(ApiError::Conflict(format!(
+            "songlist {} was recorded by games, and can't be edited",
+            id
+        )));
+    }
+
+    let songlist = check_request(request.into_inner(), Some(id), songs, storage).await?;
+    storage.update_songlist(id, &songlist).await?;
+    Ok(Json(SonglistSummary::new(id, songlist)))
+}
@@ -1,11 +1,15 @@
 use std::sync::{Arc, Mutex};
 
+use crate::error::ApiResult;
+use crate::storage::DynStorage;
 use chrono::{NaiveDateTime, TimeZone, Utc};
+use rocket::serde::json::Json;
 use rocket::State;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
 use serde::Serialize;
-use sqlx::{FromRow, MySql, Pool};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct StatsResponse {
     all_time: StatsData,
     last_365_days: StatsData,
@@ -14,29 +18,27 @@ pub struct StatsResponse {
     stats_generation_time: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct StatsData {
-    num_games: i32,
-    num_guesses: i32,
-    multiple_choice_guesses: i32,
-    free_response_guesses: i32,
-    skipped: i32,
-    num_lifelines_earned: i32,
-    num_lifelines_used: i32,
-}
-
-#[derive(FromRow, Debug)]
-struct Count {
-    total: Option<i32>,
+    pub num_games: i32,
+    /// The number of games which were ended by the reaper, because the player stopped playing.
+    pub num_abandoned_games: i32,
+    pub num_guesses: i32,
+    pub multiple_choice_guesses: i32,
+    pub free_response_guesses: i32,
+    pub skipped: i32,
+    pub num_lifelines_earned: i32,
+    pub num_lifelines_used: i32,
 }
 
 /// API endpoint for getting statistics from the database.
 /// returns a StatsResponse object
+#[openapi(tag = "Stats")]
 #[get("/stats")]
 pub async fn get_stats(
-    pool: &State<Pool<MySql>>,
+    storage: &State<DynStorage>,
     cache: &State<Arc<Mutex<Option<StatsResponse>>>>,
-) -> String {
+) -> ApiResult<Json<StatsResponse>> {
     let date_format_string = "%Y-%m-%d %H:%M"; // used for stats_generation_time
 
     {
@@ -59,15 +61,15 @@ pub async fn get_stats(
             };
 
             if duration.num_seconds().abs() < 3600 {
-                return serde_json::to_string(&inner).unwrap();
+                return Ok(Json(inner));
             }
         }
     }
 
-    let all_time = get_stats_from_recent_period(None, pool).await;
-    let last_365_days = get_stats_from_recent_period(Some(365), pool).await;
-    let last_30_days = get_stats_from_recent_period(Some(30), pool).await;
-    let last_7_days = get_stats_from_recent_period(Some(7), pool).await;
+    let all_time = get_stats_from_recent_period(None, storage).await?;
+    let last_365_days = get_stats_from_recent_period(Some(365), storage).await?;
+    let last_30_days = get_stats_from_recent_period(Some(30), storage).await?;
+    let last_7_days = get_stats_from_recent_period(Some(7), storage).await?;
 
     let time_now_utc = Utc::now();
     let stats_generation_time = format!("{}", time_now_utc.format(date_format_string));
@@ -86,125 +88,12 @@ pub async fn get_stats(
         *cache = Some(response.clone());
     }
 
-    return serde_json::to_string(&response).unwrap();
+    Ok(Json(response))
 }
 
 async fn get_stats_from_recent_period(
     period_in_days: Option<i32>,
-    pool: &rocket::State<Pool<MySql>>,
-) -> StatsData {
-    let num_games: Count = match period_in_days {
-        Some(n) => sqlx::query_as(
-            "SELECT COUNT(*) AS total FROM games WHERE start_time > NOW() - INTERVAL ? DAY",
-        )
-        .bind(n)
-        .fetch_one(pool.inner())
-        .await
-        .unwrap(),
-        None => sqlx::query_as("SELECT COUNT(*) AS total FROM games")
-            .fetch_one(pool.inner())
-            .await
-            .unwrap(),
-    };
-    let num_games = num_games.total;
-
-    let num_guesses: Count = match period_in_days {
-        Some(n) => sqlx::query_as(
-            "SELECT COUNT(*) AS total FROM guesses WHERE submit_time > NOW() - INTERVAL ? DAY",
-        )
-        .bind(n)
-        .fetch_one(pool.inner())
-        .await
-        .unwrap(),
-        None => sqlx::query_as("SELECT COUNT(*) AS total FROM guesses")
-            .fetch_one(pool.inner())
-            .await
-            .unwrap(),
-    };
-    let num_guesses = num_guesses.total;
-
-    let multiple_choice_guesses: Count = match period_in_days {
-        Some(n) => sqlx::query_as(
-            "SELECT COUNT(*) AS total FROM guesses WHERE JSON_UNQUOTE(options) != \"[]\" AND submit_time > NOW() - INTERVAL ? DAY",
-        )
-        .bind(n)
-        .fetch_one(pool.inner())
-        .await
-        .unwrap(),
-        None => sqlx::query_as("SELECT COUNT(*) AS total FROM guesses WHERE JSON_UNQUOTE(options) != \"[]\"")
-            .fetch_one(pool.inner())
-            .await
-            .unwrap(),
-    };
-    let multiple_choice_guesses = multiple_choice_guesses.total;
-
-    let free_response_guesses: Count = match period_in_days {
-        Some(n) => sqlx::query_as(
-            "SELECT COUNT(*) AS total FROM guesses WHERE JSON_UNQUOTE(options) = \"[]\" AND submit_time > NOW() - INTERVAL ? DAY",
-        )
-        .bind(n)
-        .fetch_one(pool.inner())
-        .await
-        .unwrap(),
-        None => sqlx::query_as("SELECT COUNT(*) AS total FROM guesses WHERE JSON_UNQUOTE(options) = \"[]\"")
-            .fetch_one(pool.inner())
-            .await
-            .unwrap(),
-    };
-    let free_response_guesses = free_response_guesses.total;
-
-    let skipped : Count = match period_in_days {
-        Some(n) => sqlx::query_as(
-            "SELECT COUNT(*) AS total FROM guesses WHERE result = \"skipped\" AND submit_time > NOW() - INTERVAL ? DAY",
-        )
-        .bind(n)
-        .fetch_one(pool.inner())
-        .await
-        .unwrap(),
-        None => sqlx::query_as("SELECT COUNT(*) AS total FROM guesses WHERE result = \"skipped\"")
-            .fetch_one(pool.inner())
-            .await
-            .unwrap(),
-    };
-    let skipped = skipped.total;
-
-    let num_lifelines_earned : Count = match period_in_days {
-        Some(n) => sqlx::query_as(
-            "SELECT COUNT(*) AS total FROM guesses WHERE lifeline_earned IS NOT NULL AND submit_time > NOW() - INTERVAL ? DAY",
-        )
-        .bind(n)
-        .fetch_one(pool.inner())
-        .await
-        .unwrap(),
-        None => sqlx::query_as("SELECT COUNT(*) AS total FROM guesses WHERE lifeline_earned IS NOT NULL")
-            .fetch_one(pool.inner())
-            .await
-            .unwrap(),
-    };
-    let num_lifelines_earned = num_lifelines_earned.total;
-
-    let num_lifelines_used : Count = match period_in_days {
-        Some(n) => sqlx::query_as(
-            "SELECT CAST(SUM(JSON_LENGTH(lifelines_used)) AS SIGNED) as total FROM guesses WHERE submit_time > NOW() - INTERVAL ? DAY",
-        )
-        .bind(n)
-        .fetch_one(pool.inner())
-        .await
-        .unwrap(),
-        None => sqlx::query_as("SELECT CAST(SUM(JSON_LENGTH(lifelines_used)) AS SIGNED) as total FROM guesses")
-            .fetch_one(pool.inner())
-            .await
-            .unwrap(),
-    };
-    let num_lifelines_used = num_lifelines_used.total;
-
-    StatsData {
-        num_games: num_games.unwrap_or_default(),
-        num_guesses: num_guesses.unwrap_or_default(),
-        multiple_choice_guesses: multiple_choice_guesses.unwrap_or_default(),
-        free_response_guesses: free_response_guesses.unwrap_or_default(),
-        skipped: skipped.unwrap_or_default(),
-        num_lifelines_earned: num_lifelines_earned.unwrap_or_default(),
-        num_lifelines_used: num_lifelines_used.unwrap_or_default(),
-    }
+    storage: &State<DynStorage>,
+) -> ApiResult<StatsData> {
+    Ok(storage.get_stats(period_in_days).await?)
 }
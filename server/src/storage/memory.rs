@@ -0,0 +1,694 @@
+//! An in-memory implementation of [`Storage`], for running the server without a MySQL server.
+//!
+//! It mirrors the behaviour of [`super::mysql::MySqlStorage`], including the case-insensitive
+//! matching of strings done by MySQL.
+
+use super::now;
+use super::{
+    Account, FeedbackRecord, GamesQuery, GuessRecord, LineIdResolver, LineKey, LineRevision,
+    NewGame, NewGuess, NewSonglist, PlayerKey, Preset, Storage, StorageResult,
+};
+use crate::history::{GameSchema, Songlist};
+use crate::persistence::PersistedGameState;
+use crate::reaper::END_REASON_ABANDONED;
+use crate::rules::GameRules;
+use crate::stats::StatsData;
+use async_trait::async_trait;
+use sqlx::types::time::{Date, PrimitiveDateTime};
+use sqlx::types::Json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+struct GameRow {
+    uuid: String,
+    start_time: PrimitiveDateTime,
+    songlist_id: i32,
+    selection: String,
+    seed: u64,
+    daily_date: Option<Date>,
+    rules: GameRules,
+    has_terminated: bool,
+    terminal_score: Option<i32>,
+    player_name: Option<String>,
+    end_reason: Option<String>,
+    secret_hash: Option<String>,
+    account_id: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+struct GuessRow {
+    guess: NewGuess,
+    submit_time: PrimitiveDateTime,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Votes {
+    num_upvotes: i32,
+    num_downvotes: i32,
+}
+
+#[derive(Default)]
+struct Tables {
+    songlists: Vec<Songlist>,
+    games: Vec<GameRow>,
+    presets: Vec<Preset>,
+    guesses: Vec<GuessRow>,
+    /// Maps line ids to the votes of the line.
+    votes: HashMap<String, Votes>,
+    feedback: Vec<FeedbackRecord>,
+    /// Maps song ids to the text of the lines of the song.
+    song_revisions: HashMap<String, Vec<String>>,
+    /// Maps the ids of replaced lines to their revision.
+    line_revisions: HashMap<String, LineRevision>,
+    /// Maps game uuids to the saved state of the game, and the time at which it was saved.
+    active_games: HashMap<String, (PersistedGameState, PrimitiveDateTime)>,
+    accounts: Vec<Account>,
+    /// Maps the hashes of session tokens to the id of their account, and the time at which they expire.
+    sessions: HashMap<String, (i32, PrimitiveDateTime)>,
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Whether `time` is within the last `period_in_days` days. Always true if `period_in_days` is `None`.
+fn is_within_period(time: PrimitiveDateTime, period_in_days: Option<i32>) -> bool {
+    match period_in_days {
+        Some(n) => time > now() - Duration::from_secs(n as u64 * 24 * 60 * 60),
+        None => true,
+    }
+}
+
+/// Case insensitive string equality, like `LIKE` without wildcards in MySQL.
+fn matches(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+impl Tables {
+    fn num_guesses(&self, game_uuid: &str) -> i32 {
+        self.guesses
+            .iter()
+            .filter(|g| g.guess.game_uuid == game_uuid)
+            .count() as i32
+    }
+
+    fn game_schema(&self, game: &GameRow) -> GameSchema {
+        GameSchema {
+            uuid: game.uuid.clone(),
+            start_time: game.start_time,
+            songlist_id: game.songlist_id,
+            selection: Some(game.selection.clone()),
+            seed: Some(game.seed),
+            daily_date: game.daily_date,
+            rules: Some(Json(game.rules.clone())),
+            has_terminated: game.has_terminated,
+            terminal_score: game.terminal_score,
+            player_name: game.player_name.clone(),
+            num_guesses: self.num_guesses(&game.uuid),
+            end_reason: game.end_reason.clone(),
+            secret_hash: game.secret_hash.clone(),
+            account_id: game.account_id,
+        }
+    }
+
+    fn guess_record(&self, row: &GuessRow) -> GuessRecord {
+        let player_name = self
+            .games
+            .iter()
+            .find(|game| game.uuid == row.guess.game_uuid)
+            .and_then(|game| game.player_name.clone());
+        let guess = row.guess.clone();
+        GuessRecord {
+            game_uuid: guess.game_uuid,
+            order_num: guess.order_num,
+            album: guess.album,
+            song_name: guess.song_name,
+            line_id: guess.line_id,
+            prompt: guess.prompt,
+            correct_answer: guess.correct_answer,
+            result: guess.result,
+            user_guess: guess.user_guess,
+            points_earned: guess.points_earned,
+            lifeline_earned: guess.lifeline_earned,
+            lifelines_used: guess.lifelines_used,
+            options: guess.options,
+            selected_choice: guess.selected_choice,
+            submit_time: row.submit_time,
+            player_name,
+        }
+    }
+
+    /// Whether `game` is a finished game of `player`.
+    fn is_finished_game_of(game: &GameRow, player: &PlayerKey) -> bool {
+        game.has_terminated
+            && match player {
+                PlayerKey::Account(account_id) => game.account_id == Some(*account_id),
+                PlayerKey::Name(name) => game
+                    .player_name
+                    .as_deref()
+                    .is_some_and(|player_name| matches(player_name, name)),
+            }
+    }
+
+    /// The game of the daily challenge of `date` whose player is named `player_name`, if any.
+    fn daily_game(&self, date: Date, player_name: &str) -> Option<&GameRow> {
+        self.games.iter().find(|game| {
+            game.daily_date == Some(date)
+                && game
+                    .player_name
+                    .as_deref()
+                    .is_some_and(|name| matches(name, player_name))
+        })
+    }
+
+    fn game_mut(&mut self, uuid: &str) -> Option<&mut GameRow> {
+        self.games.iter_mut().find(|game| game.uuid == uuid)
+    }
+
+    fn guesses_in_period(&self, period_in_days: Option<i32>) -> impl Iterator<Item = &NewGuess> {
+        self.guesses
+            .iter()
+            .filter(move |row| is_within_period(row.submit_time, period_in_days))
+            .map(|row| &row.guess)
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn migrate(&self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    async fn get_songlists(&self) -> StorageResult<Vec<Songlist>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.songlists.clone())
+    }
+
+    async fn get_songlist(&self, id: i32) -> StorageResult<Option<Songlist>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.songlists.iter().find(|s| s.id == id).cloned())
+    }
+
+    async fn get_or_create_songlist(
+        &self,
+        sha1sum: &str,
+        content: &[(String, String)],
+    ) -> StorageResult<i32> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(songlist) = tables
+            .songlists
+            .iter()
+            .find(|s| s.name.is_none() && s.sha1sum == sha1sum)
+        {
+            return Ok(songlist.id);
+        }
+        let id = tables.songlists.len() as i32 + 1;
+        tables.songlists.push(Songlist {
+            id,
+            sha1sum: sha1sum.to_owned(),
+            content: content.to_vec(),
+            name: None,
+            description: None,
+        });
+        Ok(id)
+    }
+
+    async fn create_songlist(&self, songlist: &NewSonglist) -> StorageResult<i32> {
+        let mut tables = self.tables.lock().unwrap();
+        let id = tables.songlists.len() as i32 + 1;
+        tables.songlists.push(Songlist {
+            id,
+            sha1sum: songlist.sha1sum.clone(),
+            content: songlist.content.clone(),
+            name: Some(songlist.name.clone()),
+            description: songlist.description.clone(),
+        });
+        Ok(id)
+    }
+
+    async fn update_songlist(&self, id: i32, songlist: &NewSonglist) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(existing) = tables.songlists.iter_mut().find(|s| s.id == id) {
+            existing.sha1sum = songlist.sha1sum.clone();
+            existing.content = songlist.content.clone();
+            existing.name = Some(songlist.name.clone());
+            existing.description = songlist.description.clone();
+        }
+        Ok(())
+    }
+
+    async fn insert_game(&self, game: &NewGame) -> StorageResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        if let (Some(date), Some(name)) = (game.daily_date, &game.player_name) {
+            if tables.daily_game(date, name).is_some() {
+                return Ok(false);
+            }
+        }
+        tables.games.push(GameRow {
+            uuid: game.uuid.clone(),
+            start_time: now(),
+            songlist_id: game.songlist_id,
+            selection: game.selection.clone(),
+            seed: game.seed,
+            daily_date: game.daily_date,
+            rules: game.rules.clone(),
+            has_terminated: false,
+            terminal_score: None,
+            player_name: game.player_name.clone(),
+            end_reason: None,
+            secret_hash: game.secret_hash.clone(),
+            account_id: game.account_id,
+        });
+        Ok(true)
+    }
+
+    async fn insert_preset(&self, preset: &Preset) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.presets.push(preset.clone());
+        Ok(())
+    }
+
+    async fn get_preset(&self, code: &str) -> StorageResult<Option<Preset>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .presets
+            .iter()
+            .find(|preset| matches(&preset.code, code))
+            .cloned())
+    }
+
+    async fn backfill_selections(&self) -> StorageResult<usize> {
+        // every game stored in memory was recorded with its selection
+        Ok(0)
+    }
+
+    async fn get_game(&self, uuid: &str) -> StorageResult<Option<GameSchema>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .games
+            .iter()
+            .find(|game| matches(&game.uuid, uuid))
+            .map(|game| tables.game_schema(game)))
+    }
+
+    async fn get_games(&self, query: &GamesQuery) -> StorageResult<Vec<GameSchema>> {
+        let tables = self.tables.lock().unwrap();
+        let search = query.search.to_lowercase();
+        let mut games: Vec<&GameRow> = tables
+            .games
+            .iter()
+            .filter(|game| game.has_terminated)
+            .filter(|game| query.daily_date.is_none() || game.daily_date == query.daily_date)
+            .filter(|game| match query.account_id {
+                Some(account_id) => game.account_id == Some(account_id),
+                None => !query.verified_only || game.account_id.is_some(),
+            })
+            .filter(|game| match &game.player_name {
+                Some(name) => name.to_lowercase().contains(&search),
+                None => query.include_nameless,
+            })
+            .collect();
+
+        if query.sort_by_score {
+            games.sort_by_key(|game| std::cmp::Reverse(game.terminal_score));
+        } else {
+            games.sort_by_key(|game| std::cmp::Reverse(game.start_time));
+        }
+
+        Ok(games
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit)
+            .map(|game| tables.game_schema(game))
+            .collect())
+    }
+
+    async fn count_better_daily_games(
+        &self,
+        date: Date,
+        score: i32,
+        verified_only: bool,
+    ) -> StorageResult<usize> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .games
+            .iter()
+            .filter(|game| {
+                game.daily_date == Some(date)
+                    && game.player_name.is_some()
+                    && game.has_terminated
+                    && game.terminal_score.unwrap_or_default() > score
+                    && (!verified_only || game.account_id.is_some())
+            })
+            .count())
+    }
+
+    async fn end_game(
+        &self,
+        uuid: &str,
+        terminal_score: i32,
+        end_reason: &str,
+    ) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(game) = tables.game_mut(uuid) {
+            game.has_terminated = true;
+            game.terminal_score = Some(terminal_score);
+            game.end_reason = Some(end_reason.to_owned());
+        }
+        Ok(())
+    }
+
+    async fn claim_game(&self, uuid: &str, player_name: &str) -> StorageResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        match tables.game_mut(uuid) {
+            Some(game) if game.player_name.is_none() => {
+                game.player_name = Some(player_name.to_owned());
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn link_game(&self, uuid: &str, account: &Account) -> StorageResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        let daily_date = tables
+            .games
+            .iter()
+            .find(|game| game.uuid == uuid)
+            .and_then(|game| game.daily_date);
+        if let Some(date) = daily_date {
+            if tables
+                .daily_game(date, &account.name)
+                .is_some_and(|game| game.uuid != uuid)
+            {
+                return Ok(false);
+            }
+        }
+        if let Some(game) = tables.game_mut(uuid) {
+            game.account_id = Some(account.id);
+            game.player_name = Some(account.name.clone());
+        }
+        Ok(true)
+    }
+
+    async fn insert_account(&self, name: &str, password_hash: &str) -> StorageResult<Option<i32>> {
+        let mut tables = self.tables.lock().unwrap();
+        if tables
+            .accounts
+            .iter()
+            .any(|account| matches(&account.name, name))
+        {
+            return Ok(None);
+        }
+        let id = tables.accounts.len() as i32 + 1;
+        tables.accounts.push(Account {
+            id,
+            name: name.to_owned(),
+            password_hash: password_hash.to_owned(),
+        });
+        Ok(Some(id))
+    }
+
+    async fn get_account(&self, name: &str) -> StorageResult<Option<Account>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .accounts
+            .iter()
+            .find(|account| matches(&account.name, name))
+            .cloned())
+    }
+
+    async fn insert_session(
+        &self,
+        token_hash: &str,
+        account_id: i32,
+        expires_time: PrimitiveDateTime,
+    ) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables
+            .sessions
+            .insert(token_hash.to_owned(), (account_id, expires_time));
+        Ok(())
+    }
+
+    async fn get_session_account(&self, token_hash: &str) -> StorageResult<Option<Account>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .sessions
+            .get(token_hash)
+            .filter(|(_, expires_time)| *expires_time > now())
+            .and_then(|(account_id, _)| {
+                tables
+                    .accounts
+                    .iter()
+                    .find(|account| account.id == *account_id)
+            })
+            .cloned())
+    }
+
+    async fn delete_session(&self, token_hash: &str) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.sessions.remove(token_hash);
+        Ok(())
+    }
+
+    async fn get_stale_games(&self, idle_since: PrimitiveDateTime) -> StorageResult<Vec<String>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .games
+            .iter()
+            .filter(|game| !game.has_terminated)
+            .filter(|game| {
+                let last_activity = match tables.active_games.get(&game.uuid) {
+                    Some((_, last_updated)) => *last_updated,
+                    None => tables
+                        .guesses
+                        .iter()
+                        .filter(|row| row.guess.game_uuid == game.uuid)
+                        .map(|row| row.submit_time)
+                        .max()
+                        .unwrap_or(game.start_time),
+                };
+                last_activity < idle_since
+            })
+            .map(|game| game.uuid.clone())
+            .collect())
+    }
+
+    async fn end_game_with_recorded_score(
+        &self,
+        uuid: &str,
+        end_reason: &str,
+    ) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        let score = tables
+            .guesses
+            .iter()
+            .filter(|row| row.guess.game_uuid == uuid)
+            .map(|row| row.guess.points_earned)
+            .sum();
+        if let Some(game) = tables.game_mut(uuid) {
+            game.has_terminated = true;
+            game.terminal_score = Some(score);
+            game.end_reason = Some(end_reason.to_owned());
+        }
+        Ok(())
+    }
+
+    async fn save_game_state(&self, uuid: &str, state: &PersistedGameState) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables
+            .active_games
+            .insert(uuid.to_owned(), (state.clone(), now()));
+        Ok(())
+    }
+
+    async fn delete_game_state(&self, uuid: &str) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.active_games.remove(uuid);
+        Ok(())
+    }
+
+    async fn get_game_states(&self) -> StorageResult<Vec<(String, PersistedGameState)>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .active_games
+            .iter()
+            .map(|(uuid, (state, _))| (uuid.clone(), state.clone()))
+            .collect())
+    }
+
+    async fn insert_guess(&self, guess: &NewGuess) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.guesses.push(GuessRow {
+            guess: guess.clone(),
+            submit_time: now(),
+        });
+        Ok(())
+    }
+
+    async fn get_guesses_for_game(&self, game_uuid: &str) -> StorageResult<Vec<GuessRecord>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .guesses
+            .iter()
+            .filter(|row| matches(&row.guess.game_uuid, game_uuid))
+            .map(|row| tables.guess_record(row))
+            .collect())
+    }
+
+    async fn get_player_games(&self, player: &PlayerKey) -> StorageResult<Vec<GameSchema>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .games
+            .iter()
+            .filter(|game| Tables::is_finished_game_of(game, player))
+            .map(|game| tables.game_schema(game))
+            .collect())
+    }
+
+    async fn get_player_guesses(&self, player: &PlayerKey) -> StorageResult<Vec<GuessRecord>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .guesses
+            .iter()
+            .filter(|row| {
+                tables.games.iter().any(|game| {
+                    game.uuid == row.guess.game_uuid && Tables::is_finished_game_of(game, player)
+                })
+            })
+            .map(|row| tables.guess_record(row))
+            .collect())
+    }
+
+    async fn get_guesses_for_line(&self, line_id: &str) -> StorageResult<Vec<GuessRecord>> {
+        let tables = self.tables.lock().unwrap();
+        let mut guesses: Vec<GuessRecord> = tables
+            .guesses
+            .iter()
+            .filter(|row| row.guess.line_id == line_id)
+            .map(|row| tables.guess_record(row))
+            .collect();
+        guesses.reverse();
+        Ok(guesses)
+    }
+
+    async fn count_guesses_for_line(&self, line_id: &str) -> StorageResult<i32> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .guesses
+            .iter()
+            .filter(|row| row.guess.line_id == line_id)
+            .count() as i32)
+    }
+
+    async fn add_vote(&self, line: &LineKey<'_>, is_upvote: bool) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        let votes = tables.votes.entry(line.id.to_owned()).or_default();
+        if is_upvote {
+            votes.num_upvotes += 1;
+        } else {
+            votes.num_downvotes += 1;
+        }
+        Ok(())
+    }
+
+    async fn get_votes(&self, line_id: &str) -> StorageResult<(i32, i32)> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .votes
+            .get(line_id)
+            .map_or((0, 0), |v| (v.num_upvotes, v.num_downvotes)))
+    }
+
+    async fn insert_feedback(
+        &self,
+        line: &LineKey<'_>,
+        message: &str,
+        contact: &str,
+    ) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.feedback.push(FeedbackRecord {
+            time: now(),
+            album: line.album.to_owned(),
+            song_name: line.song_name.to_owned(),
+            lyric: line.text.to_owned(),
+            line_id: Some(line.id.to_owned()),
+            message: message.to_owned(),
+            contact: contact.to_owned(),
+        });
+        Ok(())
+    }
+
+    async fn backfill_line_ids(&self, _line_id: &LineIdResolver<'_>) -> StorageResult<usize> {
+        // everything stored in memory was recorded with its line id
+        Ok(0)
+    }
+
+    async fn get_song_revisions(&self) -> StorageResult<HashMap<String, Vec<String>>> {
+        Ok(self.tables.lock().unwrap().song_revisions.clone())
+    }
+
+    async fn save_song_revision(&self, song_id: &str, lines: &[&str]) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.song_revisions.insert(
+            song_id.to_owned(),
+            lines.iter().map(|line| line.to_string()).collect(),
+        );
+        Ok(())
+    }
+
+    async fn insert_line_revisions(&self, revisions: &[LineRevision]) -> StorageResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        for revision in revisions {
+            tables
+                .line_revisions
+                .insert(revision.old_line_id.clone(), revision.clone());
+        }
+        Ok(())
+    }
+
+    async fn get_line_revisions(&self) -> StorageResult<Vec<LineRevision>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.line_revisions.values().cloned().collect())
+    }
+
+    async fn get_recent_feedback(&self, limit: usize) -> StorageResult<Vec<FeedbackRecord>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.feedback.iter().rev().take(limit).cloned().collect())
+    }
+
+    async fn get_stats(&self, period_in_days: Option<i32>) -> StorageResult<StatsData> {
+        let tables = self.tables.lock().unwrap();
+        let games: Vec<&GameRow> = tables
+            .games
+            .iter()
+            .filter(|game| is_within_period(game.start_time, period_in_days))
+            .collect();
+        let guesses: Vec<&NewGuess> = tables.guesses_in_period(period_in_days).collect();
+        let count = |f: &dyn Fn(&NewGuess) -> bool| guesses.iter().filter(|g| f(g)).count() as i32;
+
+        Ok(StatsData {
+            num_games: games.len() as i32,
+            num_abandoned_games: games
+                .iter()
+                .filter(|game| game.end_reason.as_deref() == Some(END_REASON_ABANDONED))
+                .count() as i32,
+            num_guesses: guesses.len() as i32,
+            multiple_choice_guesses: count(&|g| !g.options.is_empty()),
+            free_response_guesses: count(&|g| g.options.is_empty()),
+            skipped: count(&|g| g.result == "skipped"),
+            num_lifelines_earned: count(&|g| g.lifeline_earned.is_some()),
+            num_lifelines_used: guesses.iter().map(|g| g.lifelines_used.len() as i32).sum(),
+        })
+    }
+}
@@ -0,0 +1,389 @@
+//! The storage layer of the server.
+//!
+//! Every endpoint reads and writes data through the [`Storage`] trait, instead of talking to the database
+//! directly. There are two implementations:
+//!  - [`mysql::MySqlStorage`], which is used in production, and
+//!  - [`memory::MemoryStorage`], which keeps everything in memory. This allows the full server (and its tests)
+//!    to be run without a MySQL server. All data is lost when the server stops.
+//!
+//! The implementation is picked by the `STORAGE_BACKEND` environment variable (see [`connect_from_env`]).
+
+use crate::history::{GameSchema, Songlist};
+use crate::persistence::PersistedGameState;
+use crate::rules::GameRules;
+use crate::stats::StatsData;
+use async_trait::async_trait;
+use sqlx::types::time::{Date, OffsetDateTime, PrimitiveDateTime};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub mod memory;
+pub mod mysql;
+
+pub type StorageResult<T> = Result<T, sqlx::Error>;
+
+/// The current time in UTC, in the form in which times are stored.
+pub fn now() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    PrimitiveDateTime::new(now.date(), now.time())
+}
+
+/// The type of the storage managed by rocket.
+pub type DynStorage = Arc<dyn Storage>;
+
+/// A curated songlist which is about to be created or updated.
+#[derive(Debug, Clone)]
+pub struct NewSonglist {
+    pub name: String,
+    pub description: Option<String>,
+    pub sha1sum: String,
+    pub content: Vec<(String, String)>,
+}
+
+/// A game which is about to be recorded.
+#[derive(Debug, Clone)]
+pub struct NewGame {
+    pub uuid: String,
+    /// The id of the unnamed songlist of every song available when the game started.
+    pub songlist_id: i32,
+    /// The encoded [`Selection`](crate::selection::Selection) of the songs of the game.
+    pub selection: String,
+    /// The seed of the random draws of the game (see [`game_rng`](crate::game_rng)).
+    pub seed: u64,
+    /// The date of the daily challenge that the game is played in, if any.
+    pub daily_date: Option<Date>,
+    pub player_name: Option<String>,
+    pub rules: GameRules,
+    /// The hash of the secret of the game (see [`game_secret`](crate::game_secret)).
+    pub secret_hash: Option<String>,
+    /// The account of the player, if they are logged in.
+    pub account_id: Option<i32>,
+}
+
+/// Whose games a player profile is about (see [`players`](crate::players)).
+#[derive(Debug, Clone)]
+pub enum PlayerKey {
+    /// The games linked to an account.
+    Account(i32),
+    /// The games whose player name is this name, ignoring case.
+    Name(String),
+}
+
+/// A player account (see [`accounts`](crate::accounts)).
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub id: i32,
+    pub name: String,
+    pub password_hash: String,
+}
+
+/// A guess which is about to be recorded.
+#[derive(Debug, Clone)]
+pub struct NewGuess {
+    pub game_uuid: String,
+    pub order_num: i32,
+    pub album: String,
+    pub song_name: String,
+    /// The id of the prompt's line (see [`line_id`](crate::song::line_id)).
+    pub line_id: String,
+    pub prompt: String,
+    pub correct_answer: String,
+    pub result: String,
+    pub user_guess: String,
+    pub points_earned: i32,
+    pub lifeline_earned: Option<String>,
+    pub lifelines_used: Vec<String>,
+    pub options: Vec<String>,
+    /// The index in `options` of the choice that the player picked, if they picked one.
+    pub selected_choice: Option<i32>,
+}
+
+/// A guess which has been recorded.
+/// `player_name` is the name of the player of the game that the guess belongs to.
+#[derive(Debug, Clone)]
+pub struct GuessRecord {
+    pub game_uuid: String,
+    pub order_num: i32,
+    pub album: String,
+    pub song_name: String,
+    /// The id of the prompt's line (see [`line_id`](crate::song::line_id)).
+    pub line_id: String,
+    pub prompt: String,
+    pub correct_answer: String,
+    pub result: String,
+    pub user_guess: String,
+    pub points_earned: i32,
+    pub lifeline_earned: Option<String>,
+    pub lifelines_used: Vec<String>,
+    pub options: Vec<String>,
+    pub selected_choice: Option<i32>,
+    pub submit_time: PrimitiveDateTime,
+    pub player_name: Option<String>,
+}
+
+/// A piece of feedback submitted by a player.
+#[derive(Debug, Clone)]
+pub struct FeedbackRecord {
+    pub time: PrimitiveDateTime,
+    pub album: String,
+    pub song_name: String,
+    pub lyric: String,
+    /// The id of the line, if the feedback is about a line.
+    pub line_id: Option<String>,
+    pub message: String,
+    pub contact: String,
+}
+
+/// A line which votes or feedback are about.
+/// The album, song name and text are stored alongside the id, so that stored rows remain readable.
+#[derive(Debug, Clone, Copy)]
+pub struct LineKey<'a> {
+    pub id: &'a str,
+    pub album: &'a str,
+    pub song_name: &'a str,
+    pub text: &'a str,
+}
+
+/// A line which was replaced by another line when the lyrics of its song were edited
+/// (see [`revisions`](crate::revisions)).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineRevision {
+    pub old_line_id: String,
+    pub new_line_id: String,
+    pub old_text: String,
+    pub new_text: String,
+}
+
+/// A selection of songs saved under a short code (see [`presets`](crate::presets)).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preset {
+    pub code: String,
+    /// The id of the unnamed songlist of every song available when the preset was created.
+    pub songlist_id: i32,
+    /// The encoded [`Selection`](crate::selection::Selection) of the songs of the songlist.
+    pub selection: String,
+    /// The name of the preset of [`GameRules`] that games started from the preset are played with.
+    pub rules: String,
+}
+
+/// Gives the id of a line from its album, song name and text.
+pub type LineIdResolver<'a> = dyn Fn(&str, &str, &str) -> String + Sync + 'a;
+
+/// Options for listing past games. Only terminated games are listed.
+#[derive(Debug, Clone)]
+pub struct GamesQuery {
+    /// Sort by descending score if true, otherwise by descending start time.
+    pub sort_by_score: bool,
+    /// Only games whose player name contains this string are listed.
+    pub search: String,
+    /// Whether to list games without a player name.
+    pub include_nameless: bool,
+    /// Only the games of the daily challenge of this date are listed, if it is given.
+    pub daily_date: Option<Date>,
+    /// Only the games of this account are listed, if it is given.
+    pub account_id: Option<i32>,
+    /// Whether to list only the games of players who were logged in to an account.
+    pub verified_only: bool,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Bring the schema of the storage up to date, by applying any pending migrations.
+    async fn migrate(&self) -> StorageResult<()>;
+
+    /// Get all songlists.
+    async fn get_songlists(&self) -> StorageResult<Vec<Songlist>>;
+
+    /// Get the songlist with id `id`, if it exists.
+    async fn get_songlist(&self, id: i32) -> StorageResult<Option<Songlist>>;
+
+    /// Get the id of the unnamed songlist whose content has the hash `sha1sum`,
+    /// creating the songlist with `content` if it doesn't exist yet.
+    async fn get_or_create_songlist(
+        &self,
+        sha1sum: &str,
+        content: &[(String, String)],
+    ) -> StorageResult<i32>;
+
+    /// Create a curated songlist, and return its id.
+    async fn create_songlist(&self, songlist: &NewSonglist) -> StorageResult<i32>;
+
+    /// Replace the name, description and content of the songlist with id `id`.
+    async fn update_songlist(&self, id: i32, songlist: &NewSonglist) -> StorageResult<()>;
+
+    /// Record a newly started game.
+    /// Returns false, and records nothing, if the game is a daily challenge and another game of the same day has the
+    /// same player name, ignoring case.
+    async fn insert_game(&self, game: &NewGame) -> StorageResult<bool>;
+
+    /// Get a game by its uuid, if it exists.
+    async fn get_game(&self, uuid: &str) -> StorageResult<Option<GameSchema>>;
+
+    /// List past games.
+    async fn get_games(&self, query: &GamesQuery) -> StorageResult<Vec<GameSchema>>;
+
+    /// Count the finished, named games of the daily challenge of `date` which scored more than `score`, which
+    /// ranks a game on the daily leaderboard. If `verified_only` is true, only games linked to an account count.
+    async fn count_better_daily_games(
+        &self,
+        date: Date,
+        score: i32,
+        verified_only: bool,
+    ) -> StorageResult<usize>;
+
+    /// Convert the selected songs of every game recorded before selections were encoded
+    /// (see [`Selection::from_album_flags`](crate::selection::Selection::from_album_flags)).
+    /// Returns the number of games which were converted.
+    async fn backfill_selections(&self) -> StorageResult<usize>;
+
+    /// Save a preset. No preset may have its code yet.
+    async fn insert_preset(&self, preset: &Preset) -> StorageResult<()>;
+
+    /// Get the preset with code `code`, if it exists. Codes are matched case-insensitively.
+    async fn get_preset(&self, code: &str) -> StorageResult<Option<Preset>>;
+
+    /// Mark a game as terminated.
+    async fn end_game(
+        &self,
+        uuid: &str,
+        terminal_score: i32,
+        end_reason: &str,
+    ) -> StorageResult<()>;
+
+    /// Set the player name of a game, unless it already has one.
+    /// Returns whether the name was set.
+    async fn claim_game(&self, uuid: &str, player_name: &str) -> StorageResult<bool>;
+
+    /// Link a game to an account, and name it after the account.
+    /// Returns false, and changes nothing, if the game is a daily challenge and another game of the same day is
+    /// named after the account, ignoring case.
+    async fn link_game(&self, uuid: &str, account: &Account) -> StorageResult<bool>;
+
+    /// Create an account, and return its id.
+    /// Returns `None`, and creates nothing, if there is already an account named `name`, ignoring case.
+    async fn insert_account(&self, name: &str, password_hash: &str) -> StorageResult<Option<i32>>;
+
+    /// Get the account named `name`, if it exists. Names are matched case-insensitively.
+    async fn get_account(&self, name: &str) -> StorageResult<Option<Account>>;
+
+    /// Record a session of an account, which lasts until `expires_time`.
+    async fn insert_session(
+        &self,
+        token_hash: &str,
+        account_id: i32,
+        expires_time: PrimitiveDateTime,
+    ) -> StorageResult<()>;
+
+    /// Get the account of the unexpired session whose token has the hash `token_hash`, if any.
+    async fn get_session_account(&self, token_hash: &str) -> StorageResult<Option<Account>>;
+
+    /// End a session.
+    async fn delete_session(&self, token_hash: &str) -> StorageResult<()>;
+
+    /// Get the uuids of all unterminated games in which nothing has happened since `idle_since`.
+    ///
+    /// The last activity of a game is the last time its state was saved, or if it has no saved state,
+    /// the time of its last guess (or its start time if no guesses were made).
+    async fn get_stale_games(&self, idle_since: PrimitiveDateTime) -> StorageResult<Vec<String>>;
+
+    /// Mark a game as terminated, with the sum of the points earned by its guesses as its terminal score.
+    async fn end_game_with_recorded_score(&self, uuid: &str, end_reason: &str)
+        -> StorageResult<()>;
+
+    /// Save the state of an in-progress game, replacing any previously saved state.
+    async fn save_game_state(&self, uuid: &str, state: &PersistedGameState) -> StorageResult<()>;
+
+    /// Delete the saved state of a game.
+    async fn delete_game_state(&self, uuid: &str) -> StorageResult<()>;
+
+    /// Get the saved states of all in-progress games.
+    async fn get_game_states(&self) -> StorageResult<Vec<(String, PersistedGameState)>>;
+
+    /// Record a guess.
+    async fn insert_guess(&self, guess: &NewGuess) -> StorageResult<()>;
+
+    /// Get all guesses of a game.
+    async fn get_guesses_for_game(&self, game_uuid: &str) -> StorageResult<Vec<GuessRecord>>;
+
+    /// Get the finished games of a player, oldest first.
+    async fn get_player_games(&self, player: &PlayerKey) -> StorageResult<Vec<GameSchema>>;
+
+    /// Get the guesses of the finished games of a player, oldest first.
+    async fn get_player_guesses(&self, player: &PlayerKey) -> StorageResult<Vec<GuessRecord>>;
+
+    /// Get all guesses whose prompt is the line with id `line_id`, most recent first.
+    async fn get_guesses_for_line(&self, line_id: &str) -> StorageResult<Vec<GuessRecord>>;
+
+    /// Count the guesses whose prompt is the line with id `line_id`.
+    async fn count_guesses_for_line(&self, line_id: &str) -> StorageResult<i32>;
+
+    /// Add an upvote (if `is_upvote`) or downvote to a line.
+    async fn add_vote(&self, line: &LineKey<'_>, is_upvote: bool) -> StorageResult<()>;
+
+    /// Get the number of upvotes and downvotes of the line with id `line_id`.
+    async fn get_votes(&self, line_id: &str) -> StorageResult<(i32, i32)>;
+
+    /// Record a piece of feedback about a line, using the current time as its time.
+    async fn insert_feedback(
+        &self,
+        line: &LineKey<'_>,
+        message: &str,
+        contact: &str,
+    ) -> StorageResult<()>;
+
+    /// Set the line id of every guess, vote and piece of feedback recorded before line ids existed.
+    /// `line_id` gives the id of a line from its album, song name and text.
+    /// Returns the number of rows which were updated.
+    async fn backfill_line_ids(&self, line_id: &LineIdResolver<'_>) -> StorageResult<usize>;
+
+    /// Get the text of the lines of every song, as last saved with [`Storage::save_song_revision`],
+    /// keyed by song id.
+    async fn get_song_revisions(&self) -> StorageResult<HashMap<String, Vec<String>>>;
+
+    /// Save the text of the lines of a song, replacing any previously saved lines.
+    async fn save_song_revision(&self, song_id: &str, lines: &[&str]) -> StorageResult<()>;
+
+    /// Record that lines were replaced by other lines.
+    /// A line which was already recorded as replaced is recorded again with its new replacement.
+    async fn insert_line_revisions(&self, revisions: &[LineRevision]) -> StorageResult<()>;
+
+    /// Get every line which was replaced by another line.
+    async fn get_line_revisions(&self) -> StorageResult<Vec<LineRevision>>;
+
+    /// Get the `limit` most recent pieces of feedback, most recent first.
+    async fn get_recent_feedback(&self, limit: usize) -> StorageResult<Vec<FeedbackRecord>>;
+
+    /// Compute statistics over the last `period_in_days` days, or over all time if `None`.
+    async fn get_stats(&self, period_in_days: Option<i32>) -> StorageResult<StatsData>;
+}
+
+/// Create the storage backend selected by the `STORAGE_BACKEND` environment variable.
+///
+/// `STORAGE_BACKEND` may be `mysql` (the default) or `memory`.
+/// The MySQL backend additionally requires `DATABASE_USER` and `DATABASE_PASSWORD` to be set.
+pub async fn connect_from_env() -> DynStorage {
+    let backend = std::env::var("STORAGE_BACKEND").unwrap_or_else(|_| "mysql".to_string());
+    match backend.as_str() {
+        "mysql" => Arc::new(connect_mysql_from_env().await),
+        "memory" => {
+            println!("Using in-memory storage. All data will be lost when the server stops.");
+            Arc::new(memory::MemoryStorage::new())
+        }
+        other => panic!("Unknown STORAGE_BACKEND `{}`", other),
+    }
+}
+
+/// Connect to the MySQL database given by the `DATABASE_USER` and `DATABASE_PASSWORD` environment variables.
+pub async fn connect_mysql_from_env() -> mysql::MySqlStorage {
+    let db_user = std::env::var("DATABASE_USER").expect("DATABASE_USER must be set.");
+    let db_pw = std::env::var("DATABASE_PASSWORD").expect("DATABASE_PASSWORD must be set.");
+    let database_url = format!("mysql://{}:{}@localhost:3306/mydb", db_user, db_pw);
+    println!("Connecting to MySql Database...");
+    let storage = mysql::MySqlStorage::connect(&database_url)
+        .await
+        .expect("Failed to connect to database");
+    println!("Connection established!");
+    storage
+}
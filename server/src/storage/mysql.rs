@@ -0,0 +1,1019 @@
+//! The MySQL implementation of [`Storage`], used in production.
+
+use super::{
+    Account, FeedbackRecord, GamesQuery, GuessRecord, LineIdResolver, LineKey, LineRevision,
+    NewGame, NewGuess, NewSonglist, PlayerKey, Preset, Storage, StorageResult,
+};
+use crate::history::{GameSchema, Songlist};
+use crate::persistence::PersistedGameState;
+use crate::selection::Selection;
+use crate::stats::StatsData;
+use async_trait::async_trait;
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::mysql::{MySqlArguments, MySqlDatabaseError, MySqlPoolOptions};
+use sqlx::query::QueryAs;
+use sqlx::{
+    types::{
+        time::{Date, PrimitiveDateTime},
+        Json,
+    },
+    FromRow, MySql, Pool,
+};
+use std::collections::HashMap;
+
+/// The MySQL error number of a violation of a unique index.
+const ER_DUP_ENTRY: u16 = 1062;
+
+/// The schema migrations in `server/migrations`, embedded into the binary.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+pub struct MySqlStorage {
+    pool: Pool<MySql>,
+}
+
+impl MySqlStorage {
+    pub async fn connect(database_url: &str) -> Result<Self, sqlx::Error> {
+        let pool = MySqlPoolOptions::new()
+            .max_connections(5)
+            .connect(database_url)
+            .await?;
+        Ok(MySqlStorage { pool })
+    }
+
+    /// Mark every migration up to and including `version` as applied, without running it.
+    /// This is for databases whose schema was created by hand before migrations existed.
+    /// Returns the number of migrations that were marked.
+    pub async fn baseline(&self, version: i64) -> StorageResult<usize> {
+        let mut conn = self.pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        let applied: Vec<i64> = conn
+            .list_applied_migrations()
+            .await?
+            .iter()
+            .map(|migration| migration.version)
+            .collect();
+
+        let mut num_marked = 0;
+        for migration in MIGRATOR.iter() {
+            if migration.version > version || applied.contains(&migration.version) {
+                continue;
+            }
+            sqlx::query(
+                "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+                VALUES (?, ?, TRUE, ?, 0)",
+            )
+            .bind(migration.version)
+            .bind(&*migration.description)
+            .bind(&*migration.checksum)
+            .execute(&mut conn)
+            .await?;
+            println!(
+                "Marked migration {} ({}) as applied",
+                migration.version, migration.description
+            );
+            num_marked += 1;
+        }
+        Ok(num_marked)
+    }
+}
+
+#[derive(FromRow, Debug)]
+struct SonglistSchema {
+    id: i32,
+    sha1sum: String,
+    content: Json<Vec<(String, String)>>,
+    name: Option<String>,
+    description: Option<String>,
+}
+
+impl From<SonglistSchema> for Songlist {
+    fn from(songlist: SonglistSchema) -> Self {
+        Songlist {
+            id: songlist.id,
+            sha1sum: songlist.sha1sum,
+            content: songlist.content.0,
+            name: songlist.name,
+            description: songlist.description,
+        }
+    }
+}
+
+#[derive(FromRow, Debug)]
+struct PresetSchema {
+    code: String,
+    songlist_id: i32,
+    selection: String,
+    rules: String,
+}
+
+#[derive(FromRow, Debug)]
+struct GuessSchema {
+    game_uuid: String,
+    order_num: i32,
+    album: String,
+    song_name: String,
+    line_id: Option<String>,
+    prompt: String,
+    correct_answer: String,
+    result: String,
+    user_guess: String,
+    points_earned: i32,
+    lifeline_earned: Option<String>,
+    lifelines_used: Json<Vec<String>>,
+    options: Json<Vec<String>>,
+    selected_choice: Option<i32>,
+    submit_time: PrimitiveDateTime,
+    player_name: Option<String>,
+}
+
+impl From<GuessSchema> for GuessRecord {
+    fn from(guess: GuessSchema) -> Self {
+        GuessRecord {
+            game_uuid: guess.game_uuid,
+            order_num: guess.order_num,
+            album: guess.album,
+            song_name: guess.song_name,
+            // every guess has been given a line id by the time the server handles requests
+            line_id: guess.line_id.unwrap_or_default(),
+            prompt: guess.prompt,
+            correct_answer: guess.correct_answer,
+            result: guess.result,
+            user_guess: guess.user_guess,
+            points_earned: guess.points_earned,
+            lifeline_earned: guess.lifeline_earned,
+            lifelines_used: guess.lifelines_used.0,
+            options: guess.options.0,
+            selected_choice: guess.selected_choice,
+            submit_time: guess.submit_time,
+            player_name: guess.player_name,
+        }
+    }
+}
+
+#[derive(FromRow, Debug)]
+struct VotesSchema {
+    num_upvotes: i64,
+    num_downvotes: i64,
+}
+
+#[derive(FromRow, Debug)]
+struct LineRow {
+    album: String,
+    song_name: String,
+    text: String,
+}
+
+#[derive(FromRow, Debug)]
+struct FeedbackSchema {
+    time: PrimitiveDateTime,
+    album: String,
+    song_name: String,
+    lyric: String,
+    line_id: Option<String>,
+    message: String,
+    contact: String,
+}
+
+#[derive(FromRow, Debug)]
+struct SongRevisionSchema {
+    song_id: String,
+    line_texts: Json<Vec<String>>,
+}
+
+#[derive(FromRow, Debug)]
+struct LineRevisionSchema {
+    old_line_id: String,
+    new_line_id: String,
+    old_text: String,
+    new_text: String,
+}
+
+#[derive(FromRow, Debug)]
+struct ActiveGameSchema {
+    uuid: String,
+    state: Json<PersistedGameState>,
+}
+
+#[derive(FromRow, Debug)]
+struct AlbumFlagsRow {
+    uuid: String,
+    songlist_id: i32,
+    selected_songs: Json<HashMap<String, Vec<bool>>>,
+}
+
+#[derive(FromRow, Debug)]
+struct UuidRow {
+    uuid: String,
+}
+
+#[derive(FromRow, Debug)]
+struct AccountSchema {
+    id: i32,
+    name: String,
+    password_hash: String,
+}
+
+impl From<AccountSchema> for Account {
+    fn from(account: AccountSchema) -> Self {
+        Account {
+            id: account.id,
+            name: account.name,
+            password_hash: account.password_hash,
+        }
+    }
+}
+
+#[derive(FromRow, Debug)]
+struct Count {
+    total: Option<i32>,
+}
+
+impl MySqlStorage {
+    /// Runs a `SELECT ... AS total` query, optionally restricted to the last `period_in_days` days.
+    /// `query` must end in a `WHERE` clause, and `time_column` is the column compared against the period.
+    async fn count(
+        &self,
+        query: &str,
+        time_column: &str,
+        period_in_days: Option<i32>,
+    ) -> StorageResult<i32> {
+        let count: Count = match period_in_days {
+            Some(n) => {
+                sqlx::query_as(&format!(
+                    "{} AND {} > NOW() - INTERVAL ? DAY",
+                    query, time_column
+                ))
+                .bind(n)
+                .fetch_one(&self.pool)
+                .await?
+            }
+            None => sqlx::query_as(query).fetch_one(&self.pool).await?,
+        };
+        Ok(count.total.unwrap_or_default())
+    }
+}
+
+/// Whether `error` is a violation of a unique index.
+fn is_duplicate_key(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Database(e) => e
+            .try_downcast_ref::<MySqlDatabaseError>()
+            .is_some_and(|e| e.number() == ER_DUP_ENTRY),
+        _ => false,
+    }
+}
+
+/// The condition on `games` which selects the games of `player`. Its parameter is bound by [`bind_player`].
+fn player_filter(player: &PlayerKey) -> &'static str {
+    match player {
+        PlayerKey::Account(_) => "games.account_id = ?",
+        PlayerKey::Name(_) => "games.player_name = ?",
+    }
+}
+
+/// Bind the parameter of [`player_filter`].
+fn bind_player<'q, O>(
+    query: QueryAs<'q, MySql, O, MySqlArguments>,
+    player: &'q PlayerKey,
+) -> QueryAs<'q, MySql, O, MySqlArguments> {
+    match player {
+        PlayerKey::Account(account_id) => query.bind(*account_id),
+        PlayerKey::Name(name) => query.bind(name.as_str()),
+    }
+}
+
+#[async_trait]
+impl Storage for MySqlStorage {
+    async fn migrate(&self) -> StorageResult<()> {
+        MIGRATOR.run(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn get_songlists(&self) -> StorageResult<Vec<Songlist>> {
+        let songlists: Vec<SonglistSchema> = sqlx::query_as("SELECT * from songlists")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(songlists.into_iter().map(Songlist::from).collect())
+    }
+
+    async fn get_songlist(&self, id: i32) -> StorageResult<Option<Songlist>> {
+        let songlist: Option<SonglistSchema> =
+            sqlx::query_as("SELECT * FROM songlists WHERE id LIKE ?")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(songlist.map(Songlist::from))
+    }
+
+    async fn get_or_create_songlist(
+        &self,
+        sha1sum: &str,
+        content: &[(String, String)],
+    ) -> StorageResult<i32> {
+        // check if the current songlist SHA already exists
+        let result: Vec<SonglistSchema> =
+            sqlx::query_as("SELECT * FROM songlists WHERE sha1sum LIKE ? AND name IS NULL")
+                .bind(sha1sum)
+                .fetch_all(&self.pool)
+                .await?;
+
+        if let Some(songlist) = result.first() {
+            return Ok(songlist.id);
+        }
+
+        // insert a new record if the current songlist sha is not found
+        let result = sqlx::query("INSERT INTO songlists (sha1sum, content) VALUES (?, ?)")
+            .bind(sha1sum)
+            .bind(Json(content))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.last_insert_id() as i32)
+    }
+
+    async fn create_songlist(&self, songlist: &NewSonglist) -> StorageResult<i32> {
+        let result = sqlx::query(
+            "INSERT INTO songlists (sha1sum, content, name, description) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&songlist.sha1sum)
+        .bind(Json(&songlist.content))
+        .bind(&songlist.name)
+        .bind(&songlist.description)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.last_insert_id() as i32)
+    }
+
+    async fn update_songlist(&self, id: i32, songlist: &NewSonglist) -> StorageResult<()> {
+        sqlx::query(
+            "UPDATE songlists SET sha1sum = ?, content = ?, name = ?, description = ? WHERE id = ?",
+        )
+        .bind(&songlist.sha1sum)
+        .bind(Json(&songlist.content))
+        .bind(&songlist.name)
+        .bind(&songlist.description)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_game(&self, game: &NewGame) -> StorageResult<bool> {
+        let result = sqlx::query(
+            "INSERT INTO games (
+                uuid, start_time, songlist_id, selection, seed, rules, daily_date, has_terminated, player_name,
+                secret_hash, account_id
+            )
+            VALUES (?, NOW(), ?, ?, ?, ?, ?, 0, ?, ?, ?)",
+        )
+        .bind(&game.uuid)
+        .bind(game.songlist_id)
+        .bind(&game.selection)
+        .bind(game.seed)
+        .bind(Json(&game.rules))
+        .bind(game.daily_date)
+        .bind(&game.player_name)
+        .bind(&game.secret_hash)
+        .bind(game.account_id)
+        .execute(&self.pool)
+        .await;
+        // the only unique index besides the uuid is the one on the daily date and the player name
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if is_duplicate_key(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn insert_preset(&self, preset: &Preset) -> StorageResult<()> {
+        sqlx::query(
+            "INSERT INTO presets (code, songlist_id, selection, rules, created_time) VALUES (?, ?, ?, ?, NOW())",
+        )
+        .bind(&preset.code)
+        .bind(preset.songlist_id)
+        .bind(&preset.selection)
+        .bind(&preset.rules)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_preset(&self, code: &str) -> StorageResult<Option<Preset>> {
+        let preset: Option<PresetSchema> = sqlx::query_as(
+            "SELECT code, songlist_id, selection, rules FROM presets WHERE code = ?",
+        )
+        .bind(code)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(preset.map(|preset| Preset {
+            code: preset.code,
+            songlist_id: preset.songlist_id,
+            selection: preset.selection,
+            rules: preset.rules,
+        }))
+    }
+
+    async fn backfill_selections(&self) -> StorageResult<usize> {
+        let games: Vec<AlbumFlagsRow> = sqlx::query_as(
+            "SELECT uuid, songlist_id, selected_songs FROM games
+            WHERE selection IS NULL AND selected_songs IS NOT NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        if games.is_empty() {
+            return Ok(0);
+        }
+
+        let songlists = self.get_songlists().await?;
+        let mut num_converted = 0;
+        for game in games {
+            let songlist = songlists.iter().find(|s| s.id == game.songlist_id);
+            let selection = match songlist {
+                Some(songlist) => {
+                    Selection::from_album_flags(&songlist.content, &game.selected_songs.0)
+                        .map_err(|e| e.to_string())
+                }
+                None => Err(format!("unknown songlist {}", game.songlist_id)),
+            };
+            match selection {
+                Ok(selection) => {
+                    sqlx::query("UPDATE games SET selection = ? WHERE uuid = ?")
+                        .bind(selection.encode())
+                        .bind(&game.uuid)
+                        .execute(&self.pool)
+                        .await?;
+                    num_converted += 1;
+                }
+                Err(e) => println!(
+                    "Warning, the selected songs of game `{}` can't be converted: {}",
+                    game.uuid, e
+                ),
+            }
+        }
+        Ok(num_converted)
+    }
+
+    async fn get_game(&self, uuid: &str) -> StorageResult<Option<GameSchema>> {
+        sqlx::query_as(
+            "SELECT *, (select count(*) from guesses where game_uuid like uuid) as num_guesses from games
+            WHERE uuid LIKE ?",
+        )
+        .bind(uuid)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn get_games(&self, query: &GamesQuery) -> StorageResult<Vec<GameSchema>> {
+        let sub_query = "select count(*) from guesses where game_uuid like uuid";
+        let name_filter = if query.include_nameless {
+            "(player_name LIKE ? OR player_name IS NULL)"
+        } else {
+            "(player_name LIKE ?)"
+        };
+        let daily_filter = if query.daily_date.is_some() {
+            "daily_date = ?"
+        } else {
+            "TRUE"
+        };
+        let account_filter = match (query.account_id, query.verified_only) {
+            (Some(_), _) => "account_id = ?",
+            (None, true) => "account_id IS NOT NULL",
+            (None, false) => "TRUE",
+        };
+        let order = if query.sort_by_score {
+            "terminal_score"
+        } else {
+            "start_time"
+        };
+
+        let sql = format!(
+            "SELECT *, ({}) as num_guesses from games
+            WHERE {} AND {} AND {} AND has_terminated LIKE TRUE
+            ORDER BY {} DESC
+            LIMIT ? OFFSET ?",
+            sub_query, name_filter, daily_filter, account_filter, order
+        );
+
+        let mut sql_query = sqlx::query_as(&sql).bind(format!("%{}%", query.search));
+        if let Some(daily_date) = query.daily_date {
+            sql_query = sql_query.bind(daily_date);
+        }
+        if let Some(account_id) = query.account_id {
+            sql_query = sql_query.bind(account_id);
+        }
+        sql_query
+            .bind(query.limit as u64)
+            .bind(query.offset as u64)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn count_better_daily_games(
+        &self,
+        date: Date,
+        score: i32,
+        verified_only: bool,
+    ) -> StorageResult<usize> {
+        let account_filter = if verified_only {
+            "account_id IS NOT NULL"
+        } else {
+            "TRUE"
+        };
+        let sql = format!(
+            "SELECT count(1) as total FROM games
+            WHERE daily_date = ? AND player_name IS NOT NULL AND has_terminated LIKE TRUE
+            AND terminal_score > ? AND {}",
+            account_filter
+        );
+        let count: Count = sqlx::query_as(&sql)
+            .bind(date)
+            .bind(score)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count.total.unwrap_or_default() as usize)
+    }
+
+    async fn end_game(
+        &self,
+        uuid: &str,
+        terminal_score: i32,
+        end_reason: &str,
+    ) -> StorageResult<()> {
+        sqlx::query(
+            "UPDATE games
+            SET
+                has_terminated = true,
+                terminal_score = ?,
+                end_reason = ?
+            WHERE
+                UUID = ?
+            ",
+        )
+        .bind(terminal_score)
+        .bind(end_reason)
+        .bind(uuid)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn claim_game(&self, uuid: &str, player_name: &str) -> StorageResult<bool> {
+        let result = sqlx::query(
+            "UPDATE games
+            SET
+                player_name = ?
+            WHERE
+                UUID = ? AND player_name IS NULL
+            ",
+        )
+        .bind(player_name)
+        .bind(uuid)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn link_game(&self, uuid: &str, account: &Account) -> StorageResult<bool> {
+        let result = sqlx::query("UPDATE games SET account_id = ?, player_name = ? WHERE uuid = ?")
+            .bind(account.id)
+            .bind(&account.name)
+            .bind(uuid)
+            .execute(&self.pool)
+            .await;
+        // renaming a daily challenge game can only collide on the index on the daily date and the player name
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if is_duplicate_key(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn insert_account(&self, name: &str, password_hash: &str) -> StorageResult<Option<i32>> {
+        let result = sqlx::query(
+            "INSERT INTO accounts (name, password_hash, created_time) VALUES (?, ?, NOW())",
+        )
+        .bind(name)
+        .bind(password_hash)
+        .execute(&self.pool)
+        .await;
+        match result {
+            Ok(result) => Ok(Some(result.last_insert_id() as i32)),
+            Err(e) if is_duplicate_key(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_account(&self, name: &str) -> StorageResult<Option<Account>> {
+        let account: Option<AccountSchema> =
+            sqlx::query_as("SELECT id, name, password_hash FROM accounts WHERE name = ?")
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(account.map(Account::from))
+    }
+
+    async fn insert_session(
+        &self,
+        token_hash: &str,
+        account_id: i32,
+        expires_time: PrimitiveDateTime,
+    ) -> StorageResult<()> {
+        sqlx::query(
+            "INSERT INTO sessions (token_hash, account_id, created_time, expires_time)
+            VALUES (?, ?, NOW(), ?)",
+        )
+        .bind(token_hash)
+        .bind(account_id)
+        .bind(expires_time)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_session_account(&self, token_hash: &str) -> StorageResult<Option<Account>> {
+        let account: Option<AccountSchema> = sqlx::query_as(
+            "SELECT accounts.id, accounts.name, accounts.password_hash FROM sessions
+            INNER JOIN accounts ON accounts.id = sessions.account_id
+            WHERE sessions.token_hash = ? AND sessions.expires_time > NOW()",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(account.map(Account::from))
+    }
+
+    async fn delete_session(&self, token_hash: &str) -> StorageResult<()> {
+        sqlx::query("DELETE FROM sessions WHERE token_hash = ?")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_stale_games(&self, idle_since: PrimitiveDateTime) -> StorageResult<Vec<String>> {
+        let stale_games: Vec<UuidRow> = sqlx::query_as(
+            "SELECT games.uuid FROM games
+            LEFT JOIN active_games ON active_games.uuid = games.uuid
+            WHERE games.has_terminated = FALSE
+            AND COALESCE(
+                active_games.last_updated,
+                (SELECT MAX(submit_time) FROM guesses WHERE guesses.game_uuid = games.uuid),
+                games.start_time
+            ) < ?",
+        )
+        .bind(idle_since)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(stale_games.into_iter().map(|game| game.uuid).collect())
+    }
+
+    async fn end_game_with_recorded_score(
+        &self,
+        uuid: &str,
+        end_reason: &str,
+    ) -> StorageResult<()> {
+        sqlx::query(
+            "UPDATE games
+            SET
+                has_terminated = TRUE,
+                end_reason = ?,
+                terminal_score = (
+                    SELECT COALESCE(SUM(points_earned), 0) FROM guesses WHERE game_uuid = ?
+                )
+            WHERE
+                uuid = ?
+            ",
+        )
+        .bind(end_reason)
+        .bind(uuid)
+        .bind(uuid)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn save_game_state(&self, uuid: &str, state: &PersistedGameState) -> StorageResult<()> {
+        sqlx::query("REPLACE INTO active_games (uuid, state, last_updated) VALUES (?, ?, NOW())")
+            .bind(uuid)
+            .bind(Json(state))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_game_state(&self, uuid: &str) -> StorageResult<()> {
+        sqlx::query("DELETE FROM active_games WHERE uuid = ?")
+            .bind(uuid)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_game_states(&self) -> StorageResult<Vec<(String, PersistedGameState)>> {
+        let rows: Vec<ActiveGameSchema> = sqlx::query_as("SELECT uuid, state FROM active_games")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.uuid, row.state.0))
+            .collect())
+    }
+
+    async fn insert_guess(&self, guess: &NewGuess) -> StorageResult<()> {
+        sqlx::query(
+            "INSERT INTO guesses (
+                game_uuid, order_num, album, song_name, line_id, prompt, correct_answer, result, user_guess,
+                points_earned, lifeline_earned, lifelines_used, options, selected_choice,
+                submit_time
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, NOW())",
+        )
+        .bind(&guess.game_uuid)
+        .bind(guess.order_num)
+        .bind(&guess.album)
+        .bind(&guess.song_name)
+        .bind(&guess.line_id)
+        .bind(&guess.prompt)
+        .bind(&guess.correct_answer)
+        .bind(&guess.result)
+        .bind(&guess.user_guess)
+        .bind(guess.points_earned)
+        .bind(&guess.lifeline_earned)
+        .bind(Json(&guess.lifelines_used))
+        .bind(Json(&guess.options))
+        .bind(guess.selected_choice)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_guesses_for_game(&self, game_uuid: &str) -> StorageResult<Vec<GuessRecord>> {
+        let guesses: Vec<GuessSchema> = sqlx::query_as(
+            "SELECT guesses.*, games.player_name from guesses
+            INNER JOIN games ON guesses.game_uuid=games.uuid
+            WHERE game_uuid LIKE ?",
+        )
+        .bind(game_uuid)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(guesses.into_iter().map(GuessRecord::from).collect())
+    }
+
+    async fn get_player_games(&self, player: &PlayerKey) -> StorageResult<Vec<GameSchema>> {
+        let sql = format!(
+            "SELECT *, (select count(*) from guesses where game_uuid like uuid) as num_guesses from games
+            WHERE {} AND has_terminated LIKE TRUE
+            ORDER BY start_time",
+            player_filter(player)
+        );
+        bind_player(sqlx::query_as(&sql), player)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_player_guesses(&self, player: &PlayerKey) -> StorageResult<Vec<GuessRecord>> {
+        let sql = format!(
+            "SELECT guesses.*, games.player_name from guesses
+            INNER JOIN games ON guesses.game_uuid=games.uuid
+            WHERE {} AND games.has_terminated LIKE TRUE
+            ORDER BY submit_time",
+            player_filter(player)
+        );
+        let guesses: Vec<GuessSchema> = bind_player(sqlx::query_as(&sql), player)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(guesses.into_iter().map(GuessRecord::from).collect())
+    }
+
+    async fn get_guesses_for_line(&self, line_id: &str) -> StorageResult<Vec<GuessRecord>> {
+        let guesses: Vec<GuessSchema> = sqlx::query_as(
+            "SELECT guesses.*, games.player_name from guesses
+            INNER JOIN games ON guesses.game_uuid=games.uuid
+            WHERE line_id = ?
+            ORDER BY submit_time DESC
+            ",
+        )
+        .bind(line_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(guesses.into_iter().map(GuessRecord::from).collect())
+    }
+
+    async fn count_guesses_for_line(&self, line_id: &str) -> StorageResult<i32> {
+        let count: Count =
+            sqlx::query_as("SELECT count(1) as total from guesses WHERE line_id = ?")
+                .bind(line_id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(count.total.unwrap_or_default())
+    }
+
+    async fn add_vote(&self, line: &LineKey<'_>, is_upvote: bool) -> StorageResult<()> {
+        let query = if is_upvote {
+            "INSERT INTO votes (album, song_name, lyric, line_id, num_upvotes, num_downvotes) VALUES (?, ?, ?, ?, 1, 0)
+            ON DUPLICATE KEY UPDATE num_upvotes = num_upvotes + 1, line_id = VALUES(line_id);"
+        } else {
+            "INSERT INTO votes (album, song_name, lyric, line_id, num_upvotes, num_downvotes) VALUES (?, ?, ?, ?, 0, 1)
+            ON DUPLICATE KEY UPDATE num_downvotes = num_downvotes + 1, line_id = VALUES(line_id);"
+        };
+        sqlx::query(query)
+            .bind(line.album)
+            .bind(line.song_name)
+            .bind(line.text)
+            .bind(line.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_votes(&self, line_id: &str) -> StorageResult<(i32, i32)> {
+        // lyrics whose punctuation was corrected have several rows with the same line id
+        let votes: VotesSchema = sqlx::query_as(
+            "SELECT
+                CAST(COALESCE(SUM(num_upvotes), 0) AS SIGNED) AS num_upvotes,
+                CAST(COALESCE(SUM(num_downvotes), 0) AS SIGNED) AS num_downvotes
+            FROM votes WHERE line_id = ?;",
+        )
+        .bind(line_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((votes.num_upvotes as i32, votes.num_downvotes as i32))
+    }
+
+    async fn insert_feedback(
+        &self,
+        line: &LineKey<'_>,
+        message: &str,
+        contact: &str,
+    ) -> StorageResult<()> {
+        sqlx::query("INSERT INTO feedback (time, album, song_name, lyric, line_id, message, contact) VALUES (NOW(), ?, ?, ?, ?, ?, ?)")
+            .bind(line.album)
+            .bind(line.song_name)
+            .bind(line.text)
+            .bind(line.id)
+            .bind(message)
+            .bind(contact)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn backfill_line_ids(&self, line_id: &LineIdResolver<'_>) -> StorageResult<usize> {
+        let mut num_updated = 0;
+        for (table, text_column) in [
+            ("guesses", "prompt"),
+            ("votes", "lyric"),
+            ("feedback", "lyric"),
+        ] {
+            let lines: Vec<LineRow> = sqlx::query_as(&format!(
+                "SELECT DISTINCT album, song_name, {} AS text FROM {} WHERE line_id IS NULL",
+                text_column, table
+            ))
+            .fetch_all(&self.pool)
+            .await?;
+            for line in lines {
+                let result = sqlx::query(&format!(
+                    "UPDATE {} SET line_id = ? WHERE line_id IS NULL AND album = ? AND song_name = ? AND {} = ?",
+                    table, text_column
+                ))
+                .bind(line_id(&line.album, &line.song_name, &line.text))
+                .bind(&line.album)
+                .bind(&line.song_name)
+                .bind(&line.text)
+                .execute(&self.pool)
+                .await?;
+                num_updated += result.rows_affected() as usize;
+            }
+        }
+        Ok(num_updated)
+    }
+
+    async fn get_song_revisions(&self) -> StorageResult<HashMap<String, Vec<String>>> {
+        let rows: Vec<SongRevisionSchema> =
+            sqlx::query_as("SELECT song_id, line_texts FROM song_revisions")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.song_id, row.line_texts.0))
+            .collect())
+    }
+
+    async fn save_song_revision(&self, song_id: &str, lines: &[&str]) -> StorageResult<()> {
+        sqlx::query(
+            "REPLACE INTO song_revisions (song_id, line_texts, last_updated) VALUES (?, ?, NOW())",
+        )
+        .bind(song_id)
+        .bind(Json(lines))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_line_revisions(&self, revisions: &[LineRevision]) -> StorageResult<()> {
+        for revision in revisions {
+            sqlx::query(
+                "REPLACE INTO line_revisions (old_line_id, new_line_id, old_text, new_text, time)
+                VALUES (?, ?, ?, ?, NOW())",
+            )
+            .bind(&revision.old_line_id)
+            .bind(&revision.new_line_id)
+            .bind(&revision.old_text)
+            .bind(&revision.new_text)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_line_revisions(&self) -> StorageResult<Vec<LineRevision>> {
+        let rows: Vec<LineRevisionSchema> = sqlx::query_as(
+            "SELECT old_line_id, new_line_id, old_text, new_text FROM line_revisions",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| LineRevision {
+                old_line_id: row.old_line_id,
+                new_line_id: row.new_line_id,
+                old_text: row.old_text,
+                new_text: row.new_text,
+            })
+            .collect())
+    }
+
+    async fn get_recent_feedback(&self, limit: usize) -> StorageResult<Vec<FeedbackRecord>> {
+        let feedback: Vec<FeedbackSchema> =
+            sqlx::query_as("SELECT * FROM feedback ORDER BY time DESC LIMIT ?;")
+                .bind(limit as i32)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(feedback
+            .into_iter()
+            .map(|f| FeedbackRecord {
+                time: f.time,
+                album: f.album,
+                song_name: f.song_name,
+                lyric: f.lyric,
+                line_id: f.line_id,
+                message: f.message,
+                contact: f.contact,
+            })
+            .collect())
+    }
+
+    async fn get_stats(&self, period_in_days: Option<i32>) -> StorageResult<StatsData> {
+        let games =
+            |condition: &str| format!("SELECT COUNT(*) AS total FROM games WHERE {}", condition);
+        let guesses =
+            |condition: &str| format!("SELECT COUNT(*) AS total FROM guesses WHERE {}", condition);
+
+        Ok(StatsData {
+            num_games: self.count(&games("TRUE"), "start_time", period_in_days).await?,
+            num_abandoned_games: self
+                .count(
+                    &games(&format!(
+                        "end_reason = \"{}\"",
+                        crate::reaper::END_REASON_ABANDONED
+                    )),
+                    "start_time",
+                    period_in_days,
+                )
+                .await?,
+            num_guesses: self.count(&guesses("TRUE"), "submit_time", period_in_days).await?,
+            multiple_choice_guesses: self
+                .count(
+                    &guesses("JSON_UNQUOTE(options) != \"[]\""),
+                    "submit_time",
+                    period_in_days,
+                )
+                .await?,
+            free_response_guesses: self
+                .count(
+                    &guesses("JSON_UNQUOTE(options) = \"[]\""),
+                    "submit_time",
+                    period_in_days,
+                )
+                .await?,
+            skipped: self
+                .count(&guesses("result = \"skipped\""), "submit_time", period_in_days)
+                .await?,
+            num_lifelines_earned: self
+                .count(
+                    &guesses("lifeline_earned IS NOT NULL"),
+                    "submit_time",
+                    period_in_days,
+                )
+                .await?,
+            num_lifelines_used: self
+                .count(
+                    "SELECT CAST(SUM(JSON_LENGTH(lifelines_used)) AS SIGNED) as total FROM guesses WHERE TRUE",
+                    "submit_time",
+                    period_in_days,
+                )
+                .await?,
+        })
+    }
+}